@@ -89,6 +89,23 @@ pub struct ConnectArgs {
 
     #[clap(short, long)]
     pub from_config: bool,
+
+    /// Verify every transferred file against a checksum of the original.
+    #[clap(long)]
+    pub verify: bool,
+
+    /// Cap the bandwidth used by all transfers, e.g. '500K' or '5M' (bytes per second).
+    /// '0' removes a cap set in the config file.
+    #[clap(long, value_name = "RATE")]
+    pub limit_rate: Option<String>,
+
+    /// Transfer directories as a compressed tar stream when the remote has `tar`.
+    #[clap(long)]
+    pub tar: bool,
+
+    /// Resume interrupted copies between hosts instead of starting over.
+    #[clap(long)]
+    pub resume: bool,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -99,4 +116,9 @@ pub struct ResolvedConnectArgs {
     pub private_key: PathBuf,
     pub openssh_certificate: Option<PathBuf>,
     pub path: PathBuf,
+    pub verify: bool,
+    /// Bytes per second from `--limit-rate`, `Some(0)` meaning unlimited.
+    pub limit_rate: Option<u64>,
+    pub tar: bool,
+    pub resume: bool,
 }
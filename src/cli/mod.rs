@@ -4,9 +4,32 @@ pub use definition::*;
 use std::path::{Path, PathBuf};
 use tracing::debug;
 
-use crate::ssh_config::{self, Host, Hosts, reader::SSHConfigReader};
+use crate::ssh_config::{self, Host};
+use crate::transfer::rate_limit::parse_rate;
 
 impl ResolvedConnectArgs {
+    /// Connection details for a host from the ssh config, opening at `path`.
+    pub fn from_host(host: &Host, path: PathBuf) -> Result<Self> {
+        let Host {
+            host_name,
+            user,
+            port,
+            identity_file,
+            name: _,
+        } = host;
+        debug!("pvt_key_path: {:?}", identity_file);
+        let pvt_key_path = shellexpand::full(&identity_file)?;
+        let pvt_key_path = PathBuf::from(pvt_key_path.as_ref()).canonicalize()?;
+        Ok(ResolvedConnectArgs {
+            host: host_name.to_string(),
+            port: *port,
+            username: Some(user.to_owned()),
+            private_key: pvt_key_path,
+            path,
+            ..Default::default()
+        })
+    }
+
     /// Build a base SSH command (no remote path yet)
     pub fn build_ssh_command(&self) -> std::process::Command {
         type Command = std::process::Command;
@@ -43,16 +66,20 @@ impl ResolvedConnectArgs {
 
 impl ConnectArgs {
     pub fn resolve(&self) -> Result<ResolvedConnectArgs> {
+        let limit_rate = self
+            .limit_rate
+            .as_deref()
+            .map(parse_rate)
+            .transpose()
+            .wrap_err("Invalid value for --limit-rate. Example: --limit-rate 5M")?
+            // Keep an explicit 0 so that it can lift a configured cap
+            .map(|rate| rate.unwrap_or(0));
         if self.from_config {
             let host = self
                 .host
                 .as_ref()
                 .ok_or_else(|| eyre!("missing required argument: <host>"))?;
-            let mut config_reader = SSHConfigReader::new();
-
-            config_reader.read()?;
-            let config = config_reader.finalize();
-            let config: Hosts = ssh_config::from_str(&config)?;
+            let config = ssh_config::read_hosts()?;
             let Some(host_config) = config.0.iter().find(|h| &h.name == host) else {
                 return Err(eyre!("Host not found in config file"));
             };
@@ -62,23 +89,13 @@ impl ConnectArgs {
                 .ok_or_else(|| eyre!("missing required argument: <path>"))
                 .wrap_err("You must provide a path. Example: filessh example.com /var/www")?
                 .clone();
-            let Host {
-                host_name,
-                user,
-                port,
-                identity_file,
-                name: _,
-            } = host_config;
-            debug!("pvt_key_path: {:?}", identity_file);
-            let pvt_key_path = shellexpand::full(&identity_file)?;
-            let pvt_key_path = PathBuf::from(pvt_key_path.as_ref()).canonicalize()?;
             return Ok(ResolvedConnectArgs {
-                host: host_name.to_string(),
-                port: *port,
-                username: Some(user.to_owned()),
-                private_key: pvt_key_path,
                 openssh_certificate: self.openssh_certificate.clone(),
-                path,
+                verify: self.verify,
+                limit_rate,
+                tar: self.tar,
+                resume: self.resume,
+                ..ResolvedConnectArgs::from_host(host_config, path)?
             });
         }
         let host = self
@@ -109,6 +126,10 @@ impl ConnectArgs {
             private_key,
             openssh_certificate: self.openssh_certificate.clone(),
             path,
+            verify: self.verify,
+            limit_rate,
+            tar: self.tar,
+            resume: self.resume,
         })
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     env,
     fmt::{self, Display, Formatter},
     path::PathBuf,
@@ -12,6 +13,8 @@ use rat_theme3::{DarkTheme, Palette, SalsaTheme, ShellTheme};
 use ratatui::style::Color;
 use serde::{Deserialize, Serialize};
 
+use crate::files::Column;
+use crate::files::SortKey;
 use crate::logging::{PROJECT_NAME, project_directory};
 
 pub static THEME: OnceLock<&'static str> = OnceLock::new();
@@ -35,6 +38,122 @@ pub(crate) struct Settings {
     pub(crate) silent: bool,
     #[serde(default)]
     pub(crate) theme: Theme,
+    #[serde(default)]
+    pub(crate) transfers: TransferSettings,
+    #[serde(default)]
+    pub(crate) sort: SortSettings,
+    #[serde(default)]
+    pub(crate) table: TableSettings,
+    #[serde(default)]
+    pub(crate) editor: EditorSettings,
+}
+
+/// Settings for downloads and uploads, under the `[transfers]` table.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct TransferSettings {
+    /// Compare checksums of both ends after every transfer.
+    #[serde(default)]
+    pub(crate) verify: bool,
+    /// How often a transfer is retried after a checksum mismatch.
+    #[serde(default = "default_retries")]
+    pub(crate) retries: u32,
+    /// Bandwidth cap shared by all transfers, e.g. `"5M"`.
+    #[serde(default)]
+    pub(crate) limit_rate: Option<String>,
+    /// Bandwidth cap for each individual transfer job.
+    #[serde(default)]
+    pub(crate) job_limit_rate: Option<String>,
+    /// Transfer directories as one compressed tar stream over an exec channel.
+    #[serde(default)]
+    pub(crate) tar_mode: bool,
+    /// Continue copies between hosts where a partial destination file left off.
+    #[serde(default)]
+    pub(crate) resume: bool,
+}
+
+const fn default_retries() -> u32 {
+    2
+}
+
+impl Default for TransferSettings {
+    fn default() -> Self {
+        Self {
+            verify: false,
+            retries: default_retries(),
+            limit_rate: None,
+            job_limit_rate: None,
+            tar_mode: false,
+            resume: false,
+        }
+    }
+}
+
+/// Initial sort order of the file table, under the `[sort]` table.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub(crate) struct SortSettings {
+    #[serde(default)]
+    pub(crate) key: SortKey,
+    #[serde(default)]
+    pub(crate) reverse: bool,
+    #[serde(default = "default_dirs_first")]
+    pub(crate) dirs_first: bool,
+}
+
+const fn default_dirs_first() -> bool {
+    true
+}
+
+impl Default for SortSettings {
+    fn default() -> Self {
+        Self {
+            key: SortKey::default(),
+            reverse: false,
+            dirs_first: default_dirs_first(),
+        }
+    }
+}
+
+/// Layout of the file table, under the `[table]` table.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub(crate) struct TableSettings {
+    /// Columns in display order. Columns that don't fit the pane are dropped
+    /// from the right, the name is always shown.
+    #[serde(default = "default_columns")]
+    pub(crate) columns: Vec<Column>,
+}
+
+fn default_columns() -> Vec<Column> {
+    Column::DEFAULT.to_vec()
+}
+
+impl Default for TableSettings {
+    fn default() -> Self {
+        Self {
+            columns: default_columns(),
+        }
+    }
+}
+
+/// Editors for remote files, under the `[editor]` table. Files without an
+/// entry open in `$VISUAL` or `$EDITOR`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub(crate) struct EditorSettings {
+    /// Editor commands by file extension, e.g. `md = "typora"`. Commands
+    /// are split like a shell does and get the file as last argument.
+    #[serde(default)]
+    pub(crate) extensions: HashMap<String, String>,
+}
+
+impl EditorSettings {
+    /// The configured command for a file name, matching its extension
+    /// case-insensitively.
+    pub(crate) fn command_for(&self, name: &str) -> Option<&str> {
+        let (_, extension) = name.rsplit_once('.')?;
+        self.extensions
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('.').eq_ignore_ascii_case(extension))
+            .map(|(_, command)| command.as_str())
+    }
 }
 
 pub static CONFIG_FOLDER: LazyLock<Option<PathBuf>> = LazyLock::new(|| {
@@ -159,6 +278,18 @@ impl Settings {
     pub(crate) fn get_theme(&self) -> &Theme {
         &self.theme
     }
+    pub(crate) fn get_transfers(&self) -> &TransferSettings {
+        &self.transfers
+    }
+    pub(crate) fn get_sort(&self) -> SortSettings {
+        self.sort
+    }
+    pub(crate) fn get_table(&self) -> &TableSettings {
+        &self.table
+    }
+    pub(crate) fn get_editor(&self) -> &EditorSettings {
+        &self.editor
+    }
 }
 
 pub(crate) struct LoggingConfig {
@@ -408,3 +539,18 @@ pub fn init_config() -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_command_for() {
+        let settings = EditorSettings {
+            extensions: [("MD".to_string(), "typora".to_string())].into(),
+        };
+        assert_eq!(settings.command_for("notes.md"), Some("typora"));
+        assert_eq!(settings.command_for("notes.txt"), None);
+        assert_eq!(settings.command_for("md"), None);
+    }
+}
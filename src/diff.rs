@@ -0,0 +1,214 @@
+//! Line diffs and three-way merges of text files.
+use std::ops::Range;
+
+use imara_diff::intern::InternedInput;
+use imara_diff::sources::lines_with_terminator;
+use imara_diff::{Algorithm, diff};
+
+/// Lines of context around the changes of a unified diff.
+const CONTEXT: usize = 3;
+
+/// Lines of `before` replaced by lines of `after`, by index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    pub before: Range<usize>,
+    pub after: Range<usize>,
+}
+
+/// The lines of `text`, each with its line end.
+pub fn lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+/// The changes turning `before` into `after`, in order.
+pub fn hunks(before: &str, after: &str) -> Vec<Hunk> {
+    let input = InternedInput::new(lines_with_terminator(before), lines_with_terminator(after));
+    let mut hunks = Vec::new();
+    diff(
+        Algorithm::Histogram,
+        &input,
+        |before: Range<u32>, after: Range<u32>| {
+            hunks.push(Hunk {
+                before: before.start as usize..before.end as usize,
+                after: after.start as usize..after.end as usize,
+            })
+        },
+    );
+    hunks
+}
+
+/// The lines of a unified diff from `before` to `after`, without the file
+/// headers. Empty if the texts have the same lines.
+pub fn unified(before: &str, after: &str) -> Vec<String> {
+    let (old, new) = (lines(before), lines(after));
+    let hunks = hunks(before, after);
+    let strip = |line: &str| line.trim_end_matches(['\n', '\r']).to_string();
+    let mut out = Vec::new();
+    let mut idx = 0;
+    while idx < hunks.len() {
+        // Hunks whose context would touch are shown as one
+        let mut last = idx;
+        while last + 1 < hunks.len()
+            && hunks[last + 1].before.start - hunks[last].before.end <= 2 * CONTEXT
+        {
+            last += 1;
+        }
+        let (first, end) = (&hunks[idx], &hunks[last]);
+        let lead = first.before.start.min(CONTEXT);
+        let trail = (old.len() - end.before.end).min(CONTEXT);
+        let old_range = first.before.start - lead..end.before.end + trail;
+        let new_range = first.after.start - lead..end.after.end + trail;
+        out.push(format!(
+            "@@ -{},{} +{},{} @@",
+            old_range.start + 1,
+            old_range.len(),
+            new_range.start + 1,
+            new_range.len()
+        ));
+        let mut pos = old_range.start;
+        for hunk in &hunks[idx..=last] {
+            out.extend(
+                old[pos..hunk.before.start]
+                    .iter()
+                    .map(|line| format!(" {}", strip(line))),
+            );
+            out.extend(
+                old[hunk.before.clone()]
+                    .iter()
+                    .map(|line| format!("-{}", strip(line))),
+            );
+            out.extend(
+                new[hunk.after.clone()]
+                    .iter()
+                    .map(|line| format!("+{}", strip(line))),
+            );
+            pos = hunk.before.end;
+        }
+        out.extend(
+            old[pos..old_range.end]
+                .iter()
+                .map(|line| format!(" {}", strip(line))),
+        );
+        idx = last + 1;
+    }
+    out
+}
+
+/// The result of [`merge`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merge {
+    pub text: String,
+    /// Places where both sides changed the same lines differently, marked
+    /// like git does.
+    pub conflicts: usize,
+}
+
+/// Merges the changes `ours` and `theirs` made to `base`. Changes to the
+/// same or adjacent lines conflict unless they are the same, both versions
+/// are then kept between markers naming the sides by `labels`.
+pub fn merge(base: &str, ours: &str, theirs: &str, labels: (&str, &str)) -> Merge {
+    let sides = [lines(ours), lines(theirs)];
+    let base_lines = lines(base);
+    let mut changes: Vec<(usize, Hunk)> = hunks(base, ours)
+        .into_iter()
+        .map(|hunk| (0, hunk))
+        .chain(hunks(base, theirs).into_iter().map(|hunk| (1, hunk)))
+        .collect();
+    changes.sort_by_key(|(side, hunk)| (hunk.before.start, *side));
+
+    let mut merge = Merge {
+        text: String::new(),
+        conflicts: 0,
+    };
+    // How far each side has moved its lines from those of the base
+    let mut shift = [0isize; 2];
+    let mut pos = 0;
+    let mut idx = 0;
+    while idx < changes.len() {
+        let start = changes[idx].1.before.start;
+        let mut end = changes[idx].1.before.end;
+        let mut touched = [false; 2];
+        let mut grown = [0isize; 2];
+        while idx < changes.len() && changes[idx].1.before.start <= end {
+            let (side, hunk) = &changes[idx];
+            end = end.max(hunk.before.end);
+            touched[*side] = true;
+            grown[*side] += hunk.after.len() as isize - hunk.before.len() as isize;
+            idx += 1;
+        }
+        merge.text.extend(base_lines[pos..start].iter().copied());
+        let text = |side: usize| -> String {
+            let from = start.saturating_add_signed(shift[side]);
+            let to = end.saturating_add_signed(shift[side] + grown[side]);
+            sides[side][from..to].concat()
+        };
+        match touched {
+            [true, false] => merge.text += &text(0),
+            [false, true] => merge.text += &text(1),
+            _ => {
+                let (ours, theirs) = (text(0), text(1));
+                if ours == theirs {
+                    merge.text += &ours;
+                } else {
+                    merge.conflicts += 1;
+                    let close = |text: &str| {
+                        if text.is_empty() || text.ends_with('\n') {
+                            ""
+                        } else {
+                            "\n"
+                        }
+                    };
+                    merge.text += &format!(
+                        "<<<<<<< {}\n{}{}=======\n{}{}>>>>>>> {}\n",
+                        labels.0,
+                        ours,
+                        close(&ours),
+                        theirs,
+                        close(&theirs),
+                        labels.1
+                    );
+                }
+            }
+        }
+        shift[0] += grown[0];
+        shift[1] += grown[1];
+        pos = end;
+    }
+    merge.text.extend(base_lines[pos..].iter().copied());
+    merge
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_and_merge() {
+        let base = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let ours = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let theirs = "a\nb\nc\nd\ne\nf\ng\nh\nI\nj\nk\n";
+
+        assert_eq!(
+            unified(base, ours),
+            ["@@ -1,5 +1,5 @@", " a", "-b", "+B", " c", " d", " e"]
+        );
+        assert!(unified(base, base).is_empty());
+
+        // Changes to different lines go together
+        let clean = merge(base, ours, theirs, ("edited", "remote"));
+        assert_eq!(clean.conflicts, 0);
+        assert_eq!(clean.text, "a\nB\nc\nd\ne\nf\ng\nh\nI\nj\nk\n");
+
+        // Changes to the same line are marked
+        let theirs = "a\nX\nc\nd\ne\nf\ng\nh\ni\nj\n";
+        let conflict = merge(base, ours, theirs, ("edited", "remote"));
+        assert_eq!(conflict.conflicts, 1);
+        assert!(
+            conflict
+                .text
+                .starts_with("a\n<<<<<<< edited\nB\n=======\nX\n>>>>>>> remote\nc\n")
+        );
+        // Unless they are the same
+        assert_eq!(merge(base, ours, ours, ("edited", "remote")).text, ours);
+    }
+}
@@ -1,21 +1,231 @@
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::path::MAIN_SEPARATOR;
+use std::path::Path;
 
 use derive_getters::Getters;
 use rat_ftable::TableData;
 use rat_widget::paragraph::Paragraph;
 use ratatui::prelude::Line;
-use ratatui::style::{Modifier, Style, Stylize};
+use ratatui::style::{Color, Modifier, Style, Stylize};
 use ratatui::symbols::line::{ROUNDED_BOTTOM_LEFT, VERTICAL_RIGHT};
 use ratatui::{text::Span, widgets::Widget};
 use russh_sftp::client::fs::Metadata;
 use russh_sftp::protocol::{FileAttributes, FileType};
+use serde::{Deserialize, Serialize};
 
 #[derive(Getters, Debug, Clone)]
 pub struct FileEntry {
     pub name: String,
     pub type_: FileType,
     pub attributes: FileAttributes,
+    /// Where a symlink points.
+    pub link_target: Option<String>,
+    /// Whether a symlink points to something that doesn't exist.
+    pub link_broken: bool,
+    /// Number of entries in a directory, only counted when a column shows it.
+    pub item_count: Option<usize>,
+}
+
+/// A column of the file table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Permissions,
+    Name,
+    Size,
+    Modified,
+    Accessed,
+    Owner,
+    Group,
+    Uid,
+    Gid,
+    Octal,
+    LinkTarget,
+    Items,
+}
+
+impl Column {
+    pub const ALL: [Column; 12] = [
+        Column::Permissions,
+        Column::Name,
+        Column::Size,
+        Column::Modified,
+        Column::Accessed,
+        Column::Owner,
+        Column::Group,
+        Column::Uid,
+        Column::Gid,
+        Column::Octal,
+        Column::LinkTarget,
+        Column::Items,
+    ];
+
+    pub const DEFAULT: [Column; 4] = [
+        Column::Permissions,
+        Column::Name,
+        Column::Size,
+        Column::Modified,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Permissions => "Permissions",
+            Column::Name => "Path",
+            Column::Size => "Size",
+            Column::Modified => "Modified At",
+            Column::Accessed => "Accessed At",
+            Column::Owner => "Owner",
+            Column::Group => "Group",
+            Column::Uid => "UID",
+            Column::Gid => "GID",
+            Column::Octal => "Mode",
+            Column::LinkTarget => "Link Target",
+            Column::Items => "Items",
+        }
+    }
+
+    /// Width of the column; the name column takes the remaining space but
+    /// never less than this.
+    pub fn width(self) -> u16 {
+        match self {
+            Column::Permissions => 11,
+            Column::Name => 16,
+            Column::Size => 10,
+            Column::Modified | Column::Accessed => 19,
+            Column::Owner | Column::Group => 10,
+            Column::Uid | Column::Gid => 6,
+            Column::Octal => 5,
+            Column::LinkTarget => 24,
+            Column::Items => 6,
+        }
+    }
+
+    /// The leading `columns` that fit into `width`, always keeping the name.
+    pub fn fit(columns: &[Column], width: u16) -> Vec<Column> {
+        let mut used = Column::Name.width();
+        columns
+            .iter()
+            .copied()
+            .filter(|column| {
+                if *column == Column::Name {
+                    return true;
+                }
+                // One cell of spacing per column
+                let needed = column.width() + 1;
+                if used + needed > width {
+                    return false;
+                }
+                used += needed;
+                true
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Permissions,
+    Owner,
+    Extension,
+}
+
+impl SortKey {
+    /// The key a click on the header of `column` sorts by.
+    pub fn for_column(column: Column) -> Option<Self> {
+        match column {
+            Column::Name => Some(SortKey::Name),
+            Column::Size => Some(SortKey::Size),
+            Column::Modified => Some(SortKey::Modified),
+            Column::Permissions | Column::Octal => Some(SortKey::Permissions),
+            Column::Owner => Some(SortKey::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Modified,
+            SortKey::Modified => SortKey::Permissions,
+            SortKey::Permissions => SortKey::Owner,
+            SortKey::Owner => SortKey::Extension,
+            SortKey::Extension => SortKey::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Size => "size",
+            SortKey::Modified => "modified",
+            SortKey::Permissions => "permissions",
+            SortKey::Owner => "owner",
+            SortKey::Extension => "extension",
+        }
+    }
+
+    pub fn compare(self, a: &FileEntry, b: &FileEntry) -> Ordering {
+        let (x, y) = (&a.attributes, &b.attributes);
+        let ord = match self {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => x.size.cmp(&y.size),
+            SortKey::Modified => x.mtime.cmp(&y.mtime),
+            SortKey::Permissions => x
+                .permissions
+                .map(|mode| mode & 0o7777)
+                .cmp(&y.permissions.map(|mode| mode & 0o7777)),
+            // Numeric ids when the server doesn't send names
+            SortKey::Owner => x.user.cmp(&y.user).then(x.uid.cmp(&y.uid)),
+            SortKey::Extension => extension(&a.name).cmp(&extension(&b.name)),
+        };
+        // Ties are broken by name
+        ord.then_with(|| natural_cmp(&a.name, &b.name))
+    }
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+/// Compares names the way people read them: case-insensitively, with runs of
+/// digits compared by value, so `file2` comes before `file10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ord = match (x.peek().copied(), y.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d)) if c.is_ascii_digit() && d.is_ascii_digit() => {
+                let digits = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut run = String::new();
+                    while let Some(c) = it.next_if(char::is_ascii_digit) {
+                        run.push(c);
+                    }
+                    run.trim_start_matches('0').to_string()
+                };
+                let (m, n) = (digits(&mut x), digits(&mut y));
+                m.len().cmp(&n.len()).then_with(|| m.cmp(&n))
+            }
+            (Some(c), Some(d)) => {
+                x.next();
+                y.next();
+                c.to_lowercase().cmp(d.to_lowercase())
+            }
+        };
+        if ord != Ordering::Equal {
+            return ord;
+        }
+    }
 }
 
 impl From<russh_sftp::client::fs::DirEntry> for FileEntry {
@@ -27,6 +237,9 @@ impl From<russh_sftp::client::fs::DirEntry> for FileEntry {
             name: name.to_string(),
             type_,
             attributes,
+            link_target: None,
+            link_broken: false,
+            item_count: None,
         }
     }
 }
@@ -52,6 +265,25 @@ pub struct MetadataTable {
 pub struct MetadataSlice<'a>(pub &'a [MetadataTable]);
 
 impl<'a> MetadataSlice<'a> {
+    /// The attributes of `entry`, where it points for a symlink and the
+    /// type guessed from its contents, if known.
+    pub fn from_entry(
+        entry: &FileEntry,
+        file_type: Option<&str>,
+        rows: &'a mut Vec<MetadataTable>,
+    ) -> Self {
+        if let Some(target) = &entry.link_target {
+            rows.push(MetadataTable::new("link target", target));
+            if entry.link_broken {
+                rows.push(MetadataTable::new("link status", "broken"));
+            }
+        }
+        if let Some(file_type) = file_type {
+            rows.push(MetadataTable::new("file type", file_type));
+        }
+        Self::from_attributes(entry.attributes.clone(), rows)
+    }
+
     pub fn from_attributes(value: Metadata, rows: &'a mut Vec<MetadataTable>) -> Self {
         rows.push(MetadataTable::new(
             "size",
@@ -202,7 +434,12 @@ impl From<FileEntry> for Paragraph<'_> {
     }
 }
 
-pub struct FileDataSlice<'a>(pub &'a [FileEntry]);
+/// The entries of a pane with its columns and the names of marked entries.
+pub struct FileDataSlice<'a>(
+    pub &'a [FileEntry],
+    pub &'a [Column],
+    pub &'a HashSet<String>,
+);
 
 impl FileEntry {
     pub fn from_file(name: String, type_: FileType, attributes: FileAttributes) -> Self {
@@ -210,6 +447,9 @@ impl FileEntry {
             name,
             type_,
             attributes,
+            link_target: None,
+            link_broken: false,
+            item_count: None,
         }
     }
 }
@@ -250,6 +490,11 @@ impl<'a> TableData<'a> for FileDataSlice<'a> {
     fn rows(&self) -> usize {
         self.0.len()
     }
+    fn row_style(&self, row: usize) -> Option<Style> {
+        self.2
+            .contains(self.0[row].name())
+            .then(|| Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+    }
     fn render_cell(
         &self,
         _ctx: &rat_ftable::TableContext,
@@ -259,8 +504,43 @@ impl<'a> TableData<'a> for FileDataSlice<'a> {
         buf: &mut ratatui::prelude::Buffer,
     ) {
         let entry = &self.0[row];
+        let Some(column) = self.1.get(column) else {
+            return;
+        };
+        let attributes = &entry.attributes;
+        let text = |value: Option<String>, buf: &mut ratatui::prelude::Buffer| {
+            Span::from(value.unwrap_or_default()).render(area, buf)
+        };
         match column {
-            0 => {
+            Column::Accessed => match format_timestamp(attributes.atime) {
+                Some(timestamp_string) => Span::from(timestamp_string).render(area, buf),
+                None => Span::from("N/A").render(area, buf),
+            },
+            Column::Owner => text(
+                attributes
+                    .user
+                    .clone()
+                    .or(attributes.uid.map(|uid| uid.to_string())),
+                buf,
+            ),
+            Column::Group => text(
+                attributes
+                    .group
+                    .clone()
+                    .or(attributes.gid.map(|gid| gid.to_string())),
+                buf,
+            ),
+            Column::Uid => text(attributes.uid.map(|uid| uid.to_string()), buf),
+            Column::Gid => text(attributes.gid.map(|gid| gid.to_string()), buf),
+            Column::Octal => text(
+                attributes
+                    .permissions
+                    .map(|mode| format!("{:04o}", mode & 0o7777)),
+                buf,
+            ),
+            Column::LinkTarget => text(entry.link_target.clone(), buf),
+            Column::Items => text(entry.item_count.map(|count| count.to_string()), buf),
+            Column::Permissions => {
                 let perms = entry.attributes.permissions();
                 let perms_string = perms
                     .to_string()
@@ -277,7 +557,7 @@ impl<'a> TableData<'a> for FileDataSlice<'a> {
                 let line = Line::from(perms_string);
                 line.render(area, buf);
             }
-            1 => {
+            Column::Name => {
                 let vertical_right = if self.rows() - 1 != row {
                     VERTICAL_RIGHT.to_string()
                 } else {
@@ -296,6 +576,10 @@ impl<'a> TableData<'a> for FileDataSlice<'a> {
                 };
                 let entry_span = if entry.is_dir() {
                     ratatui_macros::span![entry.name.clone() + "/"].blue()
+                } else if entry.link_broken {
+                    ratatui_macros::span![entry.name.as_str()]
+                        .red()
+                        .crossed_out()
                 } else {
                     ratatui_macros::span![entry.name.as_str()]
                 };
@@ -315,15 +599,13 @@ impl<'a> TableData<'a> for FileDataSlice<'a> {
                 }
                 line.render(area, buf);
             }
-            2 => {
-                if !entry.is_dir() {
-                    let size = entry.attributes.size.unwrap_or_default();
-                    let size_string = human_readable_size(size);
-                    let span = Span::from(size_string);
-                    span.render(area, buf);
-                }
+            Column::Size if !entry.is_dir() => {
+                let size = entry.attributes.size.unwrap_or_default();
+                let size_string = human_readable_size(size);
+                let span = Span::from(size_string);
+                span.render(area, buf);
             }
-            3 => match format_timestamp(entry.attributes().mtime) {
+            Column::Modified => match format_timestamp(entry.attributes().mtime) {
                 Some(timestamp_string) => {
                     Span::from(timestamp_string).render(area, buf);
                 }
@@ -336,7 +618,7 @@ impl<'a> TableData<'a> for FileDataSlice<'a> {
     }
 }
 
-fn human_readable_size<'a>(bytes: u64) -> Cow<'a, str> {
+pub fn human_readable_size<'a>(bytes: u64) -> Cow<'a, str> {
     const UNITS: [&str; 6] = ["B", "KB", "MB", "GB", "TB", "PB"];
 
     if bytes == 0 {
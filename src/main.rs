@@ -1,9 +1,5 @@
-use std::sync::Arc;
-
 use crate::cli::{Cli, Commands};
 use crate::completions::detect_shell;
-use crate::ssh::Session;
-use async_lock::Mutex as AsyncMutex;
 use clap::Parser;
 use color_eyre::eyre::{self, Result, eyre};
 use tracing::{debug, info};
@@ -11,13 +7,17 @@ use tracing::{debug, info};
 mod cli;
 mod completions;
 mod config;
+mod diff;
 mod errors;
 mod files;
 mod logging;
 mod par_dir_traversal;
 mod patched_line_gauge;
+mod remote_fs;
+mod scp;
 mod ssh;
 mod ssh_config;
+mod transfer;
 mod tui;
 
 fn main() -> Result<()> {
@@ -63,30 +63,8 @@ fn main() -> Result<()> {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
-    let (session, sftp) = rt.block_on(async {
-        let cli = cli.clone();
-        let mut ssh = Session::connect(
-            cli.private_key,
-            cli.username.unwrap_or("root".to_string()),
-            cli.openssh_certificate,
-            (cli.host, cli.port),
-        )
-        .await?;
-        info!("Connected");
-        let sftp = ssh.sftp().await?;
-        sftp.set_timeout(60000).await;
-        eyre::Ok((ssh, sftp))
-    })?;
-    let sftp = Arc::new(sftp);
-    let session = Arc::new(AsyncMutex::new(session));
+    let connection = rt.block_on(remote_fs::connect(&cli))?;
 
-    crate::tui::tui(
-        cli.path.display().to_string(),
-        cli,
-        rt,
-        sftp,
-        session,
-        config.get_theme(),
-    )?;
+    crate::tui::tui(cli.path.display().to_string(), cli, rt, connection, &config)?;
     eyre::Ok(())
 }
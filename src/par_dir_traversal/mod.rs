@@ -4,10 +4,7 @@
 use color_eyre::eyre::Result;
 use crossbeam::deque::{Stealer, Worker as Deque};
 use futures::future::join_all; // add at top of file if not present
-use russh_sftp::{
-    client::{SftpSession, fs::Metadata},
-    protocol::FileType,
-};
+use russh_sftp::{client::fs::Metadata, protocol::FileType};
 use std::{
     path::PathBuf,
     sync::{
@@ -18,6 +15,7 @@ use std::{
 use tokio::task::JoinHandle;
 
 use crate::files::FileEntry;
+use crate::remote_fs::RemoteFs;
 
 pub type Filter = Arc<dyn Fn(&FileEntry) -> bool + Send + Sync + 'static>;
 
@@ -28,7 +26,7 @@ pub struct WalkParallel {
     pub max_depth: Option<usize>,
     pub min_depth: Option<usize>,
     pub threads: usize,
-    pub sftp: Arc<SftpSession>,
+    pub fs: Arc<dyn RemoteFs>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -111,7 +109,7 @@ impl WalkParallel {
         // --- Spawn workers ------------------------------------------------
         let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(threads);
 
-        for (stack, visitor) in stacks.into_iter().zip(visitors.into_iter()) {
+        for (stack, visitor) in stacks.into_iter().zip(visitors) {
             let worker = Woker {
                 visitor,
                 stack,
@@ -119,7 +117,7 @@ impl WalkParallel {
                 active_workers: Arc::clone(&active_workers),
                 max_depth: self.max_depth,
                 filter: Some(self.filter.clone()),
-                sftp: Arc::clone(&self.sftp),
+                fs: Arc::clone(&self.fs),
             };
 
             // Each worker runs concurrently; owns its visitor and stack
@@ -158,12 +156,12 @@ pub struct Work {
 }
 
 impl Work {
-    pub async fn read_dir(&self, sftp: Arc<SftpSession>) -> Result<Vec<Work>> {
+    pub async fn read_dir(&self, fs: &dyn RemoteFs) -> Result<Vec<Work>> {
         // Determine the directory path we should read.
         // For the root, `cwd` is already the directory path.
         let dir_path = if self.entry.is_dir() {
-            if self.cwd.ends_with(self.entry.name()) {
-                // If cwd already ends with the entry name, use it as-is
+            if self.cwd == *self.entry.name() {
+                // The root entry is named after the full path
                 self.cwd.clone()
             } else {
                 // Otherwise, join cwd + entry name
@@ -174,12 +172,12 @@ impl Work {
             return Ok(vec![]);
         };
 
-        // Read entries via SFTP
-        let entries = sftp.read_dir(dir_path.clone()).await?;
+        let entries = fs.list(&dir_path).await?;
 
         let works = entries
+            .into_iter()
             .map(|child| Work {
-                entry: FileEntry::from(child),
+                entry: child,
                 cwd: dir_path.clone(), // new base for children
             })
             .collect::<Vec<_>>();
@@ -265,7 +263,7 @@ pub struct Woker<'a> {
     active_workers: Arc<AtomicUsize>,
     max_depth: Option<usize>,
     filter: Option<Filter>,
-    sftp: Arc<SftpSession>,
+    fs: Arc<dyn RemoteFs>,
 }
 
 impl WalkState {
@@ -288,12 +286,11 @@ impl<'a> Woker<'a> {
     }
 
     pub async fn run_one(&mut self, work: Work) -> WalkState {
-        let sftp = Arc::clone(&self.sftp);
-        let readdir = work.read_dir(sftp).await;
+        let readdir = work.read_dir(&*self.fs).await;
 
         // --- Create a new FileEntry with an absolute name -----------------
         let abs_entry = {
-            let path = if work.cwd.ends_with(work.entry.name()) {
+            let path = if work.cwd == *work.entry.name() {
                 work.cwd.clone()
             } else {
                 PathBuf::from(&work.cwd)
@@ -302,8 +299,8 @@ impl<'a> Woker<'a> {
                     .to_string()
             };
 
-            // Rebuild FileEntry, preserving its metadata but using abs path as the name
-            FileEntry::from_file(path, FileType::Dir, work.entry.attributes.clone())
+            // Rebuild FileEntry, preserving its type and metadata but using abs path as the name
+            FileEntry::from_file(path, work.entry.type_, work.entry.attributes.clone())
         };
 
         // Visit the current file/directory with absolute name
@@ -418,3 +415,61 @@ impl<'a> Woker<'a> {
         self.active_workers.fetch_add(1, Ordering::Release);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::remote_fs::local::LocalFs;
+
+    // Workers block their thread while idle, so each needs one of its own
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_walk_local_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        std::fs::write(dir.path().join("a/b/deep.txt"), b"deep").unwrap();
+        std::fs::write(dir.path().join("top.txt"), b"top").unwrap();
+
+        let walker = WalkParallel {
+            filter: Arc::new(|_| true),
+            path: dir.path().to_path_buf(),
+            max_depth: None,
+            min_depth: None,
+            threads: 2,
+            fs: Arc::new(LocalFs),
+        };
+        let visited = Arc::new(Mutex::new(Vec::new()));
+        let visited_ref = Arc::clone(&visited);
+        walker
+            .run(|| {
+                let visited = Arc::clone(&visited_ref);
+                Box::new(move |entry: Result<FileEntry>| {
+                    let entry = entry.unwrap();
+                    let is_dir = entry.is_dir();
+                    visited.lock().unwrap().push((entry.name, is_dir));
+                    WalkState::Continue
+                })
+            })
+            .await;
+
+        let root = dir.path().display().to_string();
+        let mut visited: Vec<_> = visited
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, is_dir)| (name.strip_prefix(&root).unwrap().to_string(), *is_dir))
+            .collect();
+        visited.sort();
+        assert_eq!(
+            visited,
+            [
+                ("".to_string(), true),
+                ("/a".to_string(), true),
+                ("/a/b".to_string(), true),
+                ("/a/b/deep.txt".to_string(), false),
+                ("/top.txt".to_string(), false),
+            ]
+        );
+    }
+}
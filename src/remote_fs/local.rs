@@ -0,0 +1,158 @@
+use std::fs::Metadata;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use russh_sftp::protocol::{FileAttributes, FileType};
+use tokio::io::{AsyncSeekExt, SeekFrom};
+
+use super::{FsReader, FsWriter, RemoteFs};
+use crate::files::FileEntry;
+use crate::transfer::checksum::{Checksum, HashAlgorithm, local_checksum};
+
+/// The local disk.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalFs;
+
+pub(crate) fn attributes(metadata: &Metadata) -> FileAttributes {
+    let attributes = FileAttributes::from(metadata);
+    // `From<&Metadata>` only knows files and directories, keep the real type bits
+    #[cfg(unix)]
+    let attributes = FileAttributes {
+        permissions: Some(metadata.mode()),
+        ..attributes
+    };
+    attributes
+}
+
+#[async_trait]
+impl RemoteFs for LocalFs {
+    fn kind(&self) -> &'static str {
+        "local"
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        let path = shellexpand::tilde(path);
+        Ok(tokio::fs::canonicalize(path.as_ref())
+            .await?
+            .display()
+            .to_string())
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<FileEntry>> {
+        let mut entries = Vec::new();
+        let mut dir = tokio::fs::read_dir(path).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let metadata = tokio::fs::symlink_metadata(entry.path()).await?;
+            let attributes = attributes(&metadata);
+            let type_ = FileType::from(attributes.permissions.unwrap_or_default());
+            entries.push(FileEntry::from_file(
+                entry.file_name().to_string_lossy().into_owned(),
+                type_,
+                attributes,
+            ));
+        }
+        Ok(entries)
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileAttributes> {
+        Ok(attributes(&tokio::fs::metadata(path).await?))
+    }
+
+    async fn open(&self, path: &str) -> Result<FsReader> {
+        Ok(Box::new(tokio::fs::File::open(path).await?))
+    }
+
+    async fn open_at(&self, path: &str, offset: u64) -> Result<FsReader> {
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn create(&self, path: &str) -> Result<FsWriter> {
+        if let Some(parent) = Path::new(path).parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(Box::new(tokio::fs::File::create(path).await?))
+    }
+
+    async fn append(&self, path: &str) -> Result<FsWriter> {
+        let file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .await?;
+        Ok(Box::new(file))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        Ok(tokio::fs::rename(from, to).await?)
+    }
+
+    async fn replace(&self, from: &str, to: &str) -> Result<()> {
+        self.rename(from, to).await
+    }
+
+    async fn hard_link(&self, target: &str, link: &str) -> Result<()> {
+        Ok(tokio::fs::hard_link(target, link).await?)
+    }
+
+    async fn remove_file(&self, path: &str) -> Result<()> {
+        Ok(tokio::fs::remove_file(path).await?)
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<()> {
+        Ok(tokio::fs::remove_dir(path).await?)
+    }
+
+    async fn mkdir(&self, path: &str) -> Result<()> {
+        Ok(tokio::fs::create_dir(path).await?)
+    }
+
+    async fn symlink(&self, target: &str, link: &str) -> Result<()> {
+        #[cfg(unix)]
+        tokio::fs::symlink(target, link).await?;
+        #[cfg(windows)]
+        tokio::fs::symlink_file(target, link).await?;
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &str) -> Result<String> {
+        Ok(tokio::fs::read_link(path).await?.display().to_string())
+    }
+
+    async fn setstat(&self, path: &str, attributes: FileAttributes) -> Result<()> {
+        #[cfg(unix)]
+        if let Some(mode) = attributes.permissions {
+            use std::os::unix::fs::PermissionsExt;
+            let permissions = std::fs::Permissions::from_mode(mode & 0o7777);
+            tokio::fs::set_permissions(path, permissions).await?;
+        }
+        #[cfg(unix)]
+        if attributes.uid.is_some() || attributes.gid.is_some() {
+            let path = path.to_string();
+            tokio::task::spawn_blocking(move || {
+                std::os::unix::fs::chown(path, attributes.uid, attributes.gid)
+            })
+            .await??;
+        }
+        Ok(())
+    }
+
+    async fn checksum(&self, path: &str, algorithms: &[HashAlgorithm]) -> Result<Option<Checksum>> {
+        // Every algorithm is available here
+        match algorithms.first() {
+            Some(algorithm) => Ok(Some(local_checksum(Path::new(path), *algorithm).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        Ok(tokio::fs::try_exists(path).await?)
+    }
+}
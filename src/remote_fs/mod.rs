@@ -0,0 +1,318 @@
+//! The filesystem backends the browser and the transfer engine operate on.
+//!
+//! Everything that touches files goes through [`RemoteFs`], so the same code
+//! works over SFTP, over the SCP fallback and on the local disk.
+pub mod local;
+pub mod scp;
+pub mod sftp;
+pub mod sudo;
+
+use std::fmt;
+use std::sync::Arc;
+
+use async_lock::Mutex as AsyncMutex;
+use async_trait::async_trait;
+use color_eyre::eyre::{Result, bail};
+use futures::StreamExt;
+use russh_sftp::protocol::FileAttributes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::{info, warn};
+
+use crate::cli::ResolvedConnectArgs;
+use crate::files::{Column, FileEntry, JoinablePaths};
+use crate::ssh::Session;
+use crate::transfer::checksum::{Checksum, HashAlgorithm};
+use crate::transfer::copy_stream;
+use crate::transfer::rate_limit::Throttle;
+
+pub use self::local::LocalFs;
+pub use self::scp::ScpFs;
+pub use self::sftp::SftpFs;
+pub use self::sudo::SudoFs;
+
+/// An open connection to a host: the SSH session and the filesystem on it.
+#[derive(Clone)]
+pub struct Connection {
+    pub session: Arc<AsyncMutex<Session>>,
+    pub fs: Arc<dyn RemoteFs>,
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("kind", &self.fs.kind())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Connects to the host in `cli`, using SFTP when the host offers it and
+/// SCP otherwise.
+pub async fn connect(cli: &ResolvedConnectArgs) -> Result<Connection> {
+    let cli = cli.clone();
+    let ssh = Session::connect(
+        cli.private_key,
+        cli.username.unwrap_or("root".to_string()),
+        cli.openssh_certificate,
+        (cli.host, cli.port),
+    )
+    .await?;
+    info!("Connected");
+    let sftp = match ssh.sftp().await {
+        Ok(sftp) => {
+            sftp.set_timeout(60000).await;
+            Some(Arc::new(sftp))
+        }
+        Err(err) => {
+            warn!("SFTP unavailable ({err}), falling back to SCP");
+            None
+        }
+    };
+    let session = Arc::new(AsyncMutex::new(ssh));
+    let fs: Arc<dyn RemoteFs> = match sftp {
+        Some(sftp) => Arc::new(SftpFs::new(sftp, Arc::clone(&session))),
+        None => Arc::new(ScpFs::new(Arc::clone(&session))),
+    };
+    Ok(Connection { session, fs })
+}
+
+pub type FsReader = Box<dyn AsyncRead + Send + Unpin>;
+pub type FsWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+#[async_trait]
+pub trait RemoteFs: Send + Sync {
+    /// Short name of the backend, shown in the status line.
+    fn kind(&self) -> &'static str;
+
+    /// Whether files can only be written whole with [`RemoteFs::write`]:
+    /// streaming, appending, renaming and removing are not supported, so
+    /// writes replace a file in place and nothing else can be changed.
+    fn whole_file_writes(&self) -> bool {
+        false
+    }
+
+    /// Whether this is the local disk rather than a remote host.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// Resolves `path` to an absolute path.
+    async fn canonicalize(&self, path: &str) -> Result<String>;
+
+    /// Lists the entries of a directory, without `.` and `..`.
+    async fn list(&self, path: &str) -> Result<Vec<FileEntry>>;
+
+    /// Attributes of `path`, following symlinks.
+    async fn stat(&self, path: &str) -> Result<FileAttributes>;
+
+    /// Opens a file for reading.
+    async fn open(&self, path: &str) -> Result<FsReader>;
+
+    /// Opens a file for reading, starting `offset` bytes in.
+    async fn open_at(&self, path: &str, offset: u64) -> Result<FsReader> {
+        let mut reader = self.open(path).await?;
+        tokio::io::copy(&mut (&mut reader).take(offset), &mut tokio::io::sink()).await?;
+        Ok(reader)
+    }
+
+    /// Creates or truncates a file for writing.
+    async fn create(&self, path: &str) -> Result<FsWriter>;
+
+    /// Opens an existing file for writing at its end.
+    async fn append(&self, _path: &str) -> Result<FsWriter> {
+        bail!("Appending is not supported over {}", self.kind())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()>;
+
+    /// Renames `from` over `to` in one step, so that `to` never goes missing.
+    async fn replace(&self, _from: &str, _to: &str) -> Result<()> {
+        bail!("Replacing files is not supported over {}", self.kind())
+    }
+
+    /// Creates `link` as another name of the file `target`.
+    async fn hard_link(&self, _target: &str, _link: &str) -> Result<()> {
+        bail!("Hard links are not supported over {}", self.kind())
+    }
+
+    async fn remove_file(&self, path: &str) -> Result<()>;
+
+    /// Removes an empty directory.
+    async fn remove_dir(&self, path: &str) -> Result<()>;
+
+    async fn mkdir(&self, path: &str) -> Result<()>;
+
+    /// Creates a symlink at `link` pointing to `target`.
+    async fn symlink(&self, target: &str, link: &str) -> Result<()>;
+
+    /// Where the symlink at `path` points.
+    async fn read_link(&self, path: &str) -> Result<String>;
+
+    async fn setstat(&self, path: &str, attributes: FileAttributes) -> Result<()>;
+
+    /// Digest of the file at `path` in the first of `algorithms` the backend
+    /// can compute, `None` if it can't produce any of them.
+    async fn checksum(&self, path: &str, algorithms: &[HashAlgorithm]) -> Result<Option<Checksum>>;
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.stat(path).await.is_ok())
+    }
+
+    /// Reads a whole file into memory.
+    async fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.open(path).await?.read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Reads up to `len` bytes of a file, starting `offset` bytes in.
+    async fn read_at(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.open_at(path, offset)
+            .await?
+            .take(len)
+            .read_to_end(&mut buf)
+            .await?;
+        Ok(buf)
+    }
+
+    /// Replaces the contents of a file with `data`.
+    async fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut file = self.create(path).await?;
+        file.write_all(data).await?;
+        file.shutdown().await?;
+        Ok(())
+    }
+
+    /// Replaces the contents of a file with the `size` bytes `reader` yields,
+    /// without holding them in memory.
+    async fn write_from(
+        &self,
+        path: &str,
+        reader: &mut FsReader,
+        size: u64,
+        throttle: &Throttle,
+    ) -> Result<u64> {
+        let mut file = self.create(path).await?;
+        let len = copy_stream(&mut reader.take(size), &mut file, throttle).await?;
+        file.shutdown().await?;
+        Ok(len)
+    }
+}
+
+/// Lists `dir`, resolving symlinks and looking up the details that only
+/// some `columns` show.
+pub async fn list_for(
+    fs: &Arc<dyn RemoteFs>,
+    dir: &str,
+    columns: &[Column],
+) -> Result<Vec<FileEntry>> {
+    let mut entries = fs.list(dir).await?;
+    let items = columns.contains(&Column::Items);
+    if !items && !entries.iter().any(FileEntry::is_symlink) {
+        return Ok(entries);
+    }
+    let lookups: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let fs = Arc::clone(fs);
+            let path = dir.to_string().join(entry.name());
+            let (link, known) = (entry.is_symlink(), entry.link_target.is_some());
+            let count = items && entry.is_dir();
+            async move {
+                let (mut target, mut broken) = (None, false);
+                if link {
+                    if !known {
+                        target = fs.read_link(&path).await.ok();
+                    }
+                    // Stat follows the link
+                    broken = fs.stat(&path).await.is_err();
+                }
+                let count = match count {
+                    true => fs.list(&path).await.ok().map(|children| children.len()),
+                    false => None,
+                };
+                (target, broken, count)
+            }
+        })
+        .collect();
+    let details: Vec<_> = futures::stream::iter(lookups).buffered(8).collect().await;
+    for (entry, (target, broken, count)) in entries.iter_mut().zip(details) {
+        entry.link_target = entry.link_target.take().or(target);
+        entry.link_broken = broken;
+        entry.item_count = count;
+    }
+    Ok(entries)
+}
+
+/// Removes `root` and everything below it.
+pub async fn remove_all(fs: &dyn RemoteFs, root: &str) -> Result<()> {
+    // Directories are removed in reverse order of discovery, children first
+    let mut dirs = Vec::new();
+    let mut stack = vec![root.to_string()];
+    while let Some(path) = stack.pop() {
+        for entry in fs.list(&path).await? {
+            let child_path = format!("{}/{}", path.trim_end_matches('/'), entry.name());
+            if entry.is_dir() {
+                stack.push(child_path);
+            } else {
+                fs.remove_file(&child_path).await?;
+            }
+        }
+        dirs.push(path);
+    }
+    for dir in dirs.into_iter().rev() {
+        fs.remove_dir(&dir).await?;
+    }
+    Ok(())
+}
+
+pub(crate) fn unsupported<T>(fs: &dyn RemoteFs, action: &str) -> Result<T> {
+    bail!("{} is not supported over {}", action, fs.kind())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote_fs::local::LocalFs;
+
+    #[tokio::test]
+    async fn test_remove_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("tree");
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/file"), b"data").unwrap();
+        std::fs::write(root.join("top"), b"data").unwrap();
+
+        let root = root.display().to_string();
+        let fs = LocalFs;
+        assert!(fs.exists(&root).await.unwrap());
+        remove_all(&fs, &root).await.unwrap();
+        assert!(!fs.exists(&root).await.unwrap());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_list_resolves_links() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("file"), b"data").unwrap();
+        std::os::unix::fs::symlink("file", dir.path().join("good")).unwrap();
+        std::os::unix::fs::symlink("missing", dir.path().join("bad")).unwrap();
+
+        let fs: Arc<dyn RemoteFs> = Arc::new(LocalFs);
+        let root = dir.path().display().to_string();
+        let mut entries = list_for(&fs, &root, &Column::DEFAULT).await.unwrap();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        let links: Vec<_> = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.link_target.as_deref(), e.link_broken))
+            .collect();
+        assert_eq!(
+            links,
+            [
+                ("bad", Some("missing"), true),
+                ("file", None, false),
+                ("good", Some("file"), false)
+            ]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use async_lock::Mutex as AsyncMutex;
+use async_trait::async_trait;
+use color_eyre::eyre::Result;
+use russh_sftp::protocol::FileAttributes;
+
+use super::{FsReader, FsWriter, RemoteFs, unsupported};
+use crate::files::FileEntry;
+use crate::scp;
+use crate::ssh::Session;
+use crate::transfer::checksum::{Checksum, HashAlgorithm, remote_checksum};
+use crate::transfer::rate_limit::Throttle;
+
+/// A remote host without the `sftp` subsystem: listings come from `ls`,
+/// files are read with `scp -f` and written whole with `scp -t`. Everything
+/// else is refused.
+pub struct ScpFs {
+    session: Arc<AsyncMutex<Session>>,
+}
+
+impl ScpFs {
+    pub fn new(session: Arc<AsyncMutex<Session>>) -> Self {
+        Self { session }
+    }
+}
+
+#[async_trait]
+impl RemoteFs for ScpFs {
+    fn kind(&self) -> &'static str {
+        "scp"
+    }
+
+    fn whole_file_writes(&self) -> bool {
+        true
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        scp::canonicalize(&self.session.lock().await.clone(), path).await
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<FileEntry>> {
+        scp::list_dir(&self.session.lock().await.clone(), path).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileAttributes> {
+        scp::stat(&self.session.lock().await.clone(), path).await
+    }
+
+    async fn open(&self, path: &str) -> Result<FsReader> {
+        let (reader, _) = scp::open_file(&self.session.lock().await.clone(), path).await?;
+        Ok(Box::new(reader))
+    }
+
+    async fn create(&self, _path: &str) -> Result<FsWriter> {
+        unsupported(self, "Streaming writes")
+    }
+
+    async fn write(&self, path: &str, mut data: &[u8]) -> Result<()> {
+        let (session, size) = (self.session.lock().await.clone(), data.len() as u64);
+        scp::write_file(&session, path, &mut data, size, &Throttle::default()).await
+    }
+
+    async fn write_from(
+        &self,
+        path: &str,
+        reader: &mut FsReader,
+        size: u64,
+        throttle: &Throttle,
+    ) -> Result<u64> {
+        let session = self.session.lock().await.clone();
+        scp::write_file(&session, path, reader, size, throttle).await?;
+        Ok(size)
+    }
+
+    async fn rename(&self, _from: &str, _to: &str) -> Result<()> {
+        unsupported(self, "Moving")
+    }
+
+    async fn remove_file(&self, _path: &str) -> Result<()> {
+        unsupported(self, "Deleting")
+    }
+
+    async fn remove_dir(&self, _path: &str) -> Result<()> {
+        unsupported(self, "Deleting")
+    }
+
+    async fn mkdir(&self, _path: &str) -> Result<()> {
+        unsupported(self, "Creating directories")
+    }
+
+    async fn symlink(&self, _target: &str, _link: &str) -> Result<()> {
+        unsupported(self, "Creating links")
+    }
+
+    async fn read_link(&self, path: &str) -> Result<String> {
+        scp::read_link(&self.session.lock().await.clone(), path).await
+    }
+
+    async fn setstat(&self, _path: &str, _attributes: FileAttributes) -> Result<()> {
+        unsupported(self, "Changing attributes")
+    }
+
+    async fn checksum(&self, path: &str, algorithms: &[HashAlgorithm]) -> Result<Option<Checksum>> {
+        remote_checksum(&self.session.lock().await.clone(), path, algorithms).await
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+
+use async_lock::Mutex as AsyncMutex;
+use async_trait::async_trait;
+use color_eyre::eyre::{Result, bail};
+use russh_sftp::client::SftpSession;
+use russh_sftp::protocol::{FileAttributes, OpenFlags, Packet, StatusCode};
+use tokio::io::{AsyncSeekExt, SeekFrom};
+
+use super::{FsReader, FsWriter, RemoteFs};
+use crate::files::FileEntry;
+use crate::ssh::Session;
+use crate::transfer::checksum::{Checksum, HashAlgorithm, put_string, remote_checksum};
+
+/// OpenSSH's rename that replaces an existing target, as rename(2) does.
+const POSIX_RENAME_EXT: &str = "posix-rename@openssh.com";
+
+/// A remote host reached over the `sftp` subsystem.
+pub struct SftpFs {
+    sftp: Arc<SftpSession>,
+    /// Used for the checksum commands, which run over exec channels.
+    session: Arc<AsyncMutex<Session>>,
+}
+
+impl SftpFs {
+    pub fn new(sftp: Arc<SftpSession>, session: Arc<AsyncMutex<Session>>) -> Self {
+        Self { sftp, session }
+    }
+}
+
+#[async_trait]
+impl RemoteFs for SftpFs {
+    fn kind(&self) -> &'static str {
+        "sftp"
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        Ok(self.sftp.canonicalize(path).await?)
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<FileEntry>> {
+        let entries = self.sftp.read_dir(path).await?;
+        Ok(entries.map(FileEntry::from).collect())
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileAttributes> {
+        Ok(self.sftp.metadata(path).await?)
+    }
+
+    async fn open(&self, path: &str) -> Result<FsReader> {
+        Ok(Box::new(self.sftp.open(path).await?))
+    }
+
+    async fn open_at(&self, path: &str, offset: u64) -> Result<FsReader> {
+        let mut file = self.sftp.open(path).await?;
+        file.seek(SeekFrom::Start(offset)).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn create(&self, path: &str) -> Result<FsWriter> {
+        Ok(Box::new(self.sftp.create(path).await?))
+    }
+
+    async fn append(&self, path: &str) -> Result<FsWriter> {
+        // Not every server honours SSH_FXF_APPEND, so seek to the end instead
+        let mut file = self.sftp.open_with_flags(path, OpenFlags::WRITE).await?;
+        file.seek(SeekFrom::End(0)).await?;
+        Ok(Box::new(file))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        Ok(self.sftp.rename(from, to).await?)
+    }
+
+    async fn replace(&self, from: &str, to: &str) -> Result<()> {
+        // The plain SFTP rename fails if the target exists
+        let (raw, version) = self.session.lock().await.clone().raw_sftp().await?;
+        if !version.extensions.contains_key(POSIX_RENAME_EXT) {
+            let _ = raw.close_session();
+            bail!("The server does not support {}", POSIX_RENAME_EXT);
+        }
+        let mut data = Vec::new();
+        put_string(&mut data, from.as_bytes());
+        put_string(&mut data, to.as_bytes());
+        let reply = raw.extended(POSIX_RENAME_EXT, data).await;
+        let _ = raw.close_session();
+        match reply? {
+            Packet::Status(status) if status.status_code == StatusCode::Ok => Ok(()),
+            Packet::Status(status) => bail!("Renaming {} failed: {}", from, status.error_message),
+            _ => bail!("Unexpected reply to {}", POSIX_RENAME_EXT),
+        }
+    }
+
+    async fn hard_link(&self, target: &str, link: &str) -> Result<()> {
+        if !self.sftp.hardlink(target, link).await? {
+            bail!("The server does not support hard links");
+        }
+        Ok(())
+    }
+
+    async fn remove_file(&self, path: &str) -> Result<()> {
+        Ok(self.sftp.remove_file(path).await?)
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<()> {
+        Ok(self.sftp.remove_dir(path).await?)
+    }
+
+    async fn mkdir(&self, path: &str) -> Result<()> {
+        Ok(self.sftp.create_dir(path).await?)
+    }
+
+    async fn symlink(&self, target: &str, link: &str) -> Result<()> {
+        // OpenSSH's sftp-server swaps the arguments of SSH_FXP_SYMLINK
+        Ok(self.sftp.symlink(target, link).await?)
+    }
+
+    async fn read_link(&self, path: &str) -> Result<String> {
+        Ok(self.sftp.read_link(path).await?)
+    }
+
+    async fn setstat(&self, path: &str, attributes: FileAttributes) -> Result<()> {
+        Ok(self.sftp.set_metadata(path, attributes).await?)
+    }
+
+    async fn checksum(&self, path: &str, algorithms: &[HashAlgorithm]) -> Result<Option<Checksum>> {
+        remote_checksum(&self.session.lock().await.clone(), path, algorithms).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.sftp.try_exists(path).await?)
+    }
+}
@@ -0,0 +1,340 @@
+//! Files of a remote host read and written as root, through `sudo` over exec
+//! channels, for editing files the login user can't write.
+use std::fmt;
+use std::future::Future;
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use async_lock::Mutex as AsyncMutex;
+use async_trait::async_trait;
+use color_eyre::eyre::{Result, bail, eyre};
+use russh_sftp::protocol::FileAttributes;
+use shell_escape::unix::escape;
+use tokio::io::AsyncWrite;
+use zeroize::Zeroizing;
+
+use super::{FsReader, FsWriter, RemoteFs};
+use crate::files::FileEntry;
+use crate::ssh::{ExecOutput, Session};
+use crate::transfer::checksum::{Checksum, HashAlgorithm, parse_sum_output};
+
+/// Runs file operations as root with `sudo`, everything that lists
+/// directories goes to `inner`.
+#[derive(Clone)]
+pub struct SudoFs {
+    session: Arc<AsyncMutex<Session>>,
+    inner: Arc<dyn RemoteFs>,
+    /// Fed to `sudo -S` before the input of every command, `None` if sudo
+    /// doesn't ask for one. Cached credentials can't be relied on since every
+    /// command runs on a channel of its own, so it stays for the pane's sudo
+    /// mode and is wiped from memory when that ends.
+    password: Option<Arc<Zeroizing<String>>>,
+}
+
+impl fmt::Debug for SudoFs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SudoFs")
+            .field("inner", &self.inner.kind())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Whether sudo runs commands on the host without asking for a password.
+pub async fn needs_password(session: &Session) -> Result<bool> {
+    let output = session.exec("sudo -n true").await?;
+    if output.success() {
+        return Ok(false);
+    }
+    let stderr = output.stderr_lossy();
+    if stderr.contains("password") {
+        return Ok(true);
+    }
+    bail!("sudo is not available: {}", stderr.trim())
+}
+
+impl SudoFs {
+    /// Switches to root on the host of `inner`, checking `password` first.
+    pub async fn elevate(
+        session: Arc<AsyncMutex<Session>>,
+        inner: Arc<dyn RemoteFs>,
+        password: Option<Zeroizing<String>>,
+    ) -> Result<Self> {
+        let fs = Self {
+            session,
+            inner,
+            password: password.map(Arc::new),
+        };
+        let output = fs.exec("true", b"").await?;
+        if !output.success() {
+            match output.stderr_lossy().trim() {
+                stderr if stderr.contains("incorrect password") || stderr.contains("Sorry") => {
+                    bail!("Wrong sudo password")
+                }
+                stderr => bail!("sudo failed: {}", stderr),
+            }
+        }
+        Ok(fs)
+    }
+
+    /// Runs the shell `script` as root with `input` on its stdin.
+    async fn exec(&self, script: &str, input: &[u8]) -> Result<ExecOutput> {
+        let session = self.session.lock().await.clone();
+        let Some(password) = &self.password else {
+            let command = format!("sudo -n -- sh -c {}", escape(script.into()));
+            return session.exec_with_input(&command, input).await;
+        };
+        // sudo takes the line after a rejected password as the next attempt,
+        // so the password is checked on its own before any input follows it
+        if !input.is_empty() {
+            let check = with_password(&session, "true", password, b"").await?;
+            if !check.success() {
+                bail!(
+                    "sudo rejected the password: {}",
+                    check.stderr_lossy().trim()
+                );
+            }
+        }
+        with_password(&session, script, password, input).await
+    }
+
+    /// Runs `script` as root, failing with its error output if it fails.
+    async fn run(&self, script: &str, input: &[u8]) -> Result<Vec<u8>> {
+        let output = self.exec(script, input).await?;
+        if !output.success() {
+            bail!("sudo {} failed: {}", script, output.stderr_lossy().trim());
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// Runs `script` with `sudo -S`, feeding it `password` and then `input`.
+async fn with_password(
+    session: &Session,
+    script: &str,
+    password: &str,
+    input: &[u8],
+) -> Result<ExecOutput> {
+    // -k ignores cached credentials, so the password line is always read and
+    // never ends up in the input of the script
+    let command = format!("sudo -S -k -p '' -- sh -c {}", escape(script.into()));
+    let mut stdin = Zeroizing::new(Vec::with_capacity(password.len() + 1 + input.len()));
+    stdin.extend_from_slice(password.as_bytes());
+    stdin.push(b'\n');
+    stdin.extend_from_slice(input);
+    session.exec_with_input(&command, &stdin).await
+}
+
+/// Attributes from the output of `stat -c '%s %Y %f %u %g'`.
+fn parse_stat(output: &str) -> Option<FileAttributes> {
+    let fields: Vec<&str> = output.split_whitespace().collect();
+    let [size, mtime, mode, uid, gid] = fields[..] else {
+        return None;
+    };
+    Some(FileAttributes {
+        size: Some(size.parse().ok()?),
+        mtime: Some(mtime.parse().ok()?),
+        // The raw mode, in hex
+        permissions: Some(u32::from_str_radix(mode, 16).ok()?),
+        uid: Some(uid.parse().ok()?),
+        gid: Some(gid.parse().ok()?),
+        ..FileAttributes::empty()
+    })
+}
+
+fn quote(path: &str) -> String {
+    escape(path.into()).into_owned()
+}
+
+#[async_trait]
+impl RemoteFs for SudoFs {
+    fn kind(&self) -> &'static str {
+        "sudo"
+    }
+
+    async fn canonicalize(&self, path: &str) -> Result<String> {
+        self.inner.canonicalize(path).await
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<FileEntry>> {
+        self.inner.list(path).await
+    }
+
+    async fn stat(&self, path: &str) -> Result<FileAttributes> {
+        let stdout = self
+            .run(
+                &format!("stat -L -c '%s %Y %f %u %g' -- {}", quote(path)),
+                b"",
+            )
+            .await?;
+        let stdout = String::from_utf8_lossy(&stdout);
+        parse_stat(&stdout).ok_or_else(|| eyre!("Unexpected stat output {:?}", stdout))
+    }
+
+    async fn open(&self, path: &str) -> Result<FsReader> {
+        let data = self.run(&format!("cat -- {}", quote(path)), b"").await?;
+        Ok(Box::new(Cursor::new(data)))
+    }
+
+    async fn read_at(&self, path: &str, offset: u64, len: u64) -> Result<Vec<u8>> {
+        let script = format!(
+            "tail -c +{} -- {} | head -c {}",
+            offset + 1,
+            quote(path),
+            len
+        );
+        self.run(&script, b"").await
+    }
+
+    async fn create(&self, path: &str) -> Result<FsWriter> {
+        Ok(Box::new(SudoWriter {
+            fs: Arc::new(self.clone()),
+            path: path.to_string(),
+            data: Vec::new(),
+            save: None,
+        }))
+    }
+
+    async fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        self.run(&format!("cat > {}", quote(path)), data).await?;
+        Ok(())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<()> {
+        self.run(&format!("mv -f -- {} {}", quote(from), quote(to)), b"")
+            .await?;
+        Ok(())
+    }
+
+    async fn replace(&self, from: &str, to: &str) -> Result<()> {
+        // mv renames within a filesystem, replacing the target in one step
+        self.rename(from, to).await
+    }
+
+    async fn hard_link(&self, target: &str, link: &str) -> Result<()> {
+        self.run(&format!("ln -- {} {}", quote(target), quote(link)), b"")
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_file(&self, path: &str) -> Result<()> {
+        self.run(&format!("rm -- {}", quote(path)), b"").await?;
+        Ok(())
+    }
+
+    async fn remove_dir(&self, path: &str) -> Result<()> {
+        self.run(&format!("rmdir -- {}", quote(path)), b"").await?;
+        Ok(())
+    }
+
+    async fn mkdir(&self, path: &str) -> Result<()> {
+        self.run(&format!("mkdir -- {}", quote(path)), b"").await?;
+        Ok(())
+    }
+
+    async fn symlink(&self, target: &str, link: &str) -> Result<()> {
+        self.run(&format!("ln -s -- {} {}", quote(target), quote(link)), b"")
+            .await?;
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &str) -> Result<String> {
+        let stdout = self
+            .run(&format!("readlink -- {}", quote(path)), b"")
+            .await?;
+        Ok(String::from_utf8_lossy(&stdout).trim_end().to_string())
+    }
+
+    async fn setstat(&self, path: &str, attributes: FileAttributes) -> Result<()> {
+        if let Some(mode) = attributes.permissions {
+            self.run(
+                &format!("chmod {:o} -- {}", mode & 0o7777, quote(path)),
+                b"",
+            )
+            .await?;
+        }
+        let owner = match (attributes.uid, attributes.gid) {
+            (Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+            (Some(uid), None) => uid.to_string(),
+            (None, Some(gid)) => format!(":{}", gid),
+            (None, None) => return Ok(()),
+        };
+        self.run(&format!("chown {} -- {}", owner, quote(path)), b"")
+            .await?;
+        Ok(())
+    }
+
+    async fn checksum(&self, path: &str, algorithms: &[HashAlgorithm]) -> Result<Option<Checksum>> {
+        for &algorithm in algorithms {
+            let script = format!("{} -- {}", algorithm.command(), quote(path));
+            let output = self.exec(&script, b"").await?;
+            if !output.success() {
+                continue;
+            }
+            if let Some(hex) = parse_sum_output(&output.stdout_lossy()) {
+                return Ok(Some(Checksum { algorithm, hex }));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool> {
+        let output = self.exec(&format!("test -e {}", quote(path)), b"").await?;
+        Ok(output.success())
+    }
+}
+
+type Save = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// Collects what is written and hands it to `cat` in one go on shutdown,
+/// since every command runs on its own channel.
+struct SudoWriter {
+    fs: Arc<SudoFs>,
+    path: String,
+    data: Vec<u8>,
+    save: Option<Save>,
+}
+
+impl AsyncWrite for SudoWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if self.save.is_some() {
+            return Poll::Ready(Err(io::Error::other("write after shutdown")));
+        }
+        self.data.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let save = this.save.get_or_insert_with(|| {
+            let (fs, path) = (Arc::clone(&this.fs), this.path.clone());
+            let data = std::mem::take(&mut this.data);
+            Box::pin(async move { fs.write(&path, &data).await })
+        });
+        save.as_mut().poll(cx).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stat() {
+        let attributes = parse_stat("1024 1700000000 81a4 0 33\n").unwrap();
+        assert_eq!(attributes.size, Some(1024));
+        assert_eq!(attributes.mtime, Some(1700000000));
+        assert_eq!(attributes.permissions, Some(0o100644));
+        assert_eq!((attributes.uid, attributes.gid), (Some(0), Some(33)));
+        assert!(parse_stat("stat: cannot statx '/nope'").is_none());
+    }
+}
@@ -0,0 +1,536 @@
+//! The legacy SCP protocol, used as a fallback on hosts that don't offer the
+//! `sftp` subsystem.
+//!
+//! Transfers run `scp -f` (source) or `scp -t` (sink) on the remote over an
+//! exec channel. Listings are produced by parsing the output of `ls -la`.
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Result, bail, eyre};
+use russh::ChannelStream;
+use russh::client::Msg;
+use russh_sftp::protocol::{FileAttributes, FileType};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, Take};
+use tracing::{debug, info, warn};
+
+use crate::files::FileEntry;
+use crate::ssh::Session;
+use crate::transfer::copy_stream;
+use crate::transfer::rate_limit::Throttle;
+
+type Stream = ChannelStream<Msg>;
+
+fn escape(path: &str) -> String {
+    shell_escape::unix::escape(path.into()).into_owned()
+}
+
+/// A control record of the SCP protocol.
+#[derive(Debug, PartialEq, Eq)]
+enum Record {
+    File { mode: u32, size: u64, name: String },
+    Dir { mode: u32, name: String },
+    End,
+    Time,
+}
+
+fn parse_record(line: &str) -> Result<Record> {
+    let (kind, rest) = line.split_at(line.len().min(1));
+    match kind {
+        "C" | "D" => {
+            let mut parts = rest.splitn(3, ' ');
+            let mode = u32::from_str_radix(parts.next().unwrap_or_default(), 8)?;
+            let size = parts.next().unwrap_or_default().parse()?;
+            let name = parts
+                .next()
+                .filter(|n| !n.is_empty() && *n != ".." && !n.contains('/'))
+                .ok_or_else(|| eyre!("invalid scp record: {}", line))?
+                .to_string();
+            Ok(if kind == "C" {
+                Record::File { mode, size, name }
+            } else {
+                Record::Dir { mode, name }
+            })
+        }
+        "E" => Ok(Record::End),
+        "T" => Ok(Record::Time),
+        _ => bail!("unexpected scp record: {}", line),
+    }
+}
+
+async fn read_line(stream: &mut Stream) -> Result<Option<String>> {
+    let mut line = Vec::new();
+    loop {
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            return if line.is_empty() {
+                Ok(None)
+            } else {
+                bail!("scp stream ended unexpectedly")
+            };
+        }
+        match byte[0] {
+            b'\n' => return Ok(Some(String::from_utf8_lossy(&line).into_owned())),
+            // Warnings and errors are sent as a status byte followed by a message
+            1 | 2 if line.is_empty() => {
+                let mut message = Vec::new();
+                loop {
+                    if stream.read(&mut byte).await? == 0 || byte[0] == b'\n' {
+                        break;
+                    }
+                    message.push(byte[0]);
+                }
+                bail!("scp: {}", String::from_utf8_lossy(&message));
+            }
+            b => line.push(b),
+        }
+    }
+}
+
+async fn read_ack(stream: &mut Stream) -> Result<()> {
+    let mut byte = [0u8; 1];
+    stream.read_exact(&mut byte).await?;
+    match byte[0] {
+        0 => Ok(()),
+        _ => {
+            let mut message = Vec::new();
+            loop {
+                if stream.read(&mut byte).await? == 0 || byte[0] == b'\n' {
+                    break;
+                }
+                message.push(byte[0]);
+            }
+            bail!("scp: {}", String::from_utf8_lossy(&message))
+        }
+    }
+}
+
+async fn ack(stream: &mut Stream) -> Result<()> {
+    stream.write_all(&[0]).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+/// Downloads the remote file or directory tree `remote` to `local`.
+/// Returns the number of files received.
+pub async fn download(
+    session: &Session,
+    remote: &str,
+    local: &Path,
+    throttle: &Throttle,
+) -> Result<usize> {
+    let channel = session
+        .exec_channel(&format!("scp -r -f {}", escape(remote)))
+        .await?;
+    let mut stream = channel.into_stream();
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    let mut files = 0;
+    ack(&mut stream).await?;
+    while let Some(line) = read_line(&mut stream).await? {
+        let target = |name: &str| match dirs.last() {
+            Some(dir) => dir.join(name),
+            None => local.to_path_buf(),
+        };
+        match parse_record(&line)? {
+            Record::Dir { name, .. } => {
+                let dir = target(&name);
+                tokio::fs::create_dir_all(&dir).await?;
+                dirs.push(dir);
+            }
+            Record::End => {
+                dirs.pop();
+            }
+            Record::Time => {}
+            Record::File { size, name, .. } => {
+                let path = target(&name);
+                ack(&mut stream).await?;
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let mut file = tokio::fs::File::create(&path).await?;
+                let copied =
+                    copy_stream(&mut (&mut stream).take(size), &mut file, throttle).await?;
+                if copied != size {
+                    bail!(
+                        "scp: short read for {} ({} of {} bytes)",
+                        name,
+                        copied,
+                        size
+                    );
+                }
+                file.sync_all().await?;
+                read_ack(&mut stream).await?;
+                debug!(path = ?path.display(), size, "Received");
+                files += 1;
+            }
+        }
+        ack(&mut stream).await?;
+    }
+    info!(remote, files, "Downloaded over scp");
+    Ok(files)
+}
+
+/// Opens a remote file for reading, returning its contents as they arrive
+/// along with its size.
+pub async fn open_file(session: &Session, remote: &str) -> Result<(Take<Stream>, u64)> {
+    let channel = session
+        .exec_channel(&format!("scp -f {}", escape(remote)))
+        .await?;
+    let mut stream = channel.into_stream();
+    ack(&mut stream).await?;
+    loop {
+        let line = read_line(&mut stream)
+            .await?
+            .ok_or_else(|| eyre!("scp: no data received for {}", remote))?;
+        if let Record::File { size, .. } = parse_record(&line)? {
+            ack(&mut stream).await?;
+            // The closing acknowledgements are skipped, the channel goes
+            // away with the reader
+            return Ok((stream.take(size), size));
+        }
+        ack(&mut stream).await?;
+    }
+}
+
+async fn send_file<R: AsyncRead + Unpin>(
+    stream: &mut Stream,
+    reader: &mut R,
+    mode: u32,
+    size: u64,
+    name: &str,
+    throttle: &Throttle,
+) -> Result<()> {
+    stream
+        .write_all(format!("C{:04o} {} {}\n", mode & 0o7777, size, name).as_bytes())
+        .await?;
+    read_ack(stream).await?;
+    let copied = copy_stream(&mut reader.take(size), stream, throttle).await?;
+    if copied != size {
+        bail!("scp: {} ended after {} of {} bytes", name, copied, size);
+    }
+    ack(stream).await?;
+    read_ack(stream).await
+}
+
+/// Uploads the local file or directory tree `local` into the remote
+/// directory `remote_dir`, keeping its name. Returns the number of files sent.
+pub async fn upload(
+    session: &Session,
+    local: &Path,
+    remote_dir: &str,
+    throttle: &Throttle,
+) -> Result<usize> {
+    let channel = session
+        .exec_channel(&format!("scp -r -t {}", escape(remote_dir)))
+        .await?;
+    let mut stream = channel.into_stream();
+    read_ack(&mut stream).await?;
+
+    // (path, is the end marker of a directory)
+    let mut stack = vec![(local.to_path_buf(), false)];
+    let mut files = 0;
+    while let Some((path, end)) = stack.pop() {
+        if end {
+            stream.write_all(b"E\n").await?;
+            read_ack(&mut stream).await?;
+            continue;
+        }
+        let name = path
+            .file_name()
+            .ok_or_else(|| eyre!("invalid path: {}", path.display()))?
+            .to_string_lossy()
+            .into_owned();
+        let metadata = tokio::fs::metadata(&path).await?;
+        let mode = FileAttributes::from(&metadata).permissions.unwrap_or(0o644);
+        if metadata.is_dir() {
+            stream
+                .write_all(format!("D{:04o} 0 {}\n", mode & 0o7777, name).as_bytes())
+                .await?;
+            read_ack(&mut stream).await?;
+            stack.push((path.clone(), true));
+            let mut entries = tokio::fs::read_dir(&path).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                stack.push((entry.path(), false));
+            }
+        } else {
+            let mut file = tokio::fs::File::open(&path).await?;
+            send_file(
+                &mut stream,
+                &mut file,
+                mode,
+                metadata.len(),
+                &name,
+                throttle,
+            )
+            .await?;
+            files += 1;
+        }
+    }
+    stream.shutdown().await?;
+    info!(remote_dir, files, "Uploaded over scp");
+    Ok(files)
+}
+
+/// Writes the `size` bytes `reader` yields to the remote file `remote`.
+pub async fn write_file<R: AsyncRead + Unpin>(
+    session: &Session,
+    remote: &str,
+    reader: &mut R,
+    size: u64,
+    throttle: &Throttle,
+) -> Result<()> {
+    let path = Path::new(remote);
+    let dir = path
+        .parent()
+        .map_or(".".into(), |p| p.display().to_string());
+    let name = path
+        .file_name()
+        .ok_or_else(|| eyre!("invalid path: {}", remote))?
+        .to_string_lossy()
+        .into_owned();
+    let channel = session
+        .exec_channel(&format!("scp -t {}", escape(&dir)))
+        .await?;
+    let mut stream = channel.into_stream();
+    read_ack(&mut stream).await?;
+    send_file(&mut stream, reader, 0o644, size, &name, throttle).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Resolves `path` to an absolute path on the remote.
+pub async fn canonicalize(session: &Session, path: &str) -> Result<String> {
+    let output = session
+        .exec(&format!("cd {} && pwd -P", escape(path)))
+        .await?;
+    if !output.success() {
+        bail!("cannot resolve {}: {}", path, output.stderr_lossy().trim());
+    }
+    Ok(output.stdout_lossy().trim().to_string())
+}
+
+/// Reads the target of the symlink at `path`.
+pub async fn read_link(session: &Session, path: &str) -> Result<String> {
+    let output = session
+        .exec(&format!("readlink -- {}", escape(path)))
+        .await?;
+    if !output.success() {
+        bail!(
+            "cannot read link {}: {}",
+            path,
+            output.stderr_lossy().trim()
+        );
+    }
+    Ok(output.stdout_lossy().trim_end_matches('\n').to_string())
+}
+
+/// Runs `ls` with `flags` on `path` and parses its entries.
+async fn ls(session: &Session, flags: &str, path: &str) -> Result<Vec<FileEntry>> {
+    let escaped = escape(path);
+    // GNU ls can print raw timestamps, busybox and BSD ls fall back to the plain listing
+    let mut output = session
+        .exec(&format!(
+            "LC_ALL=C ls {} --time-style=+%s {}",
+            flags, escaped
+        ))
+        .await?;
+    if !output.success() {
+        output = session
+            .exec(&format!("LC_ALL=C ls {} {}", flags, escaped))
+            .await?;
+    }
+    if !output.success() {
+        bail!("ls {}: {}", path, output.stderr_lossy().trim());
+    }
+    Ok(output
+        .stdout_lossy()
+        .lines()
+        .filter_map(parse_ls_line)
+        .collect())
+}
+
+/// Lists a remote directory by parsing `ls -la`.
+pub async fn list_dir(session: &Session, path: &str) -> Result<Vec<FileEntry>> {
+    Ok(ls(session, "-la", path)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.name != "." && entry.name != "..")
+        .collect())
+}
+
+/// Attributes of `path`, following symlinks.
+pub async fn stat(session: &Session, path: &str) -> Result<FileAttributes> {
+    ls(session, "-ldL", path)
+        .await?
+        .pop()
+        .map(|entry| entry.attributes)
+        .ok_or_else(|| eyre!("ls {}: no output", path))
+}
+
+/// Splits the next whitespace separated field off `line`, returning it and
+/// everything after it.
+fn next_field(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    if line.is_empty() {
+        return None;
+    }
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    Some(line.split_at(end))
+}
+
+/// Parses one line of `ls -la` output. Returns `None` for lines that aren't entries.
+fn parse_ls_line(line: &str) -> Option<FileEntry> {
+    let (mode_str, rest) = next_field(line)?;
+    let mode = parse_mode(mode_str)?;
+    let (_links, rest) = next_field(rest)?;
+    let (user, rest) = next_field(rest)?;
+    let (group, rest) = next_field(rest)?;
+    let (mut size_field, mut rest) = next_field(rest)?;
+    // Device files show "major, minor" instead of a size
+    if size_field.ends_with(',') {
+        (size_field, rest) = next_field(rest)?;
+    }
+    let size: u64 = size_field.parse().unwrap_or(0);
+
+    let (first, rest) = next_field(rest)?;
+    let (mtime, rest) = match first.parse::<u32>() {
+        // `--time-style=+%s`
+        Ok(ts) => (Some(ts), rest),
+        // "Jan  1 12:00" or "Jan  1  2024"
+        Err(_) => {
+            let (_day, rest) = next_field(rest)?;
+            (None, next_field(rest)?.1)
+        }
+    };
+    // The name is everything after the single separating space, so names
+    // with inner or leading whitespace survive
+    let mut name = rest.strip_prefix([' ', '\t'])?;
+    if name.is_empty() {
+        return None;
+    }
+    let mut link_target = None;
+    let file_type: FileType = mode.into();
+    if file_type == FileType::Symlink
+        && let Some((link, target)) = name.split_once(" -> ")
+    {
+        name = link;
+        link_target = Some(target.to_string());
+    }
+
+    let attributes = FileAttributes {
+        size: Some(size),
+        user: Some(user.to_string()),
+        group: Some(group.to_string()),
+        permissions: Some(mode),
+        mtime,
+        ..FileAttributes::empty()
+    };
+    let mut entry = FileEntry::from_file(name.to_string(), file_type, attributes);
+    entry.link_target = link_target;
+    Some(entry)
+}
+
+/// Converts a mode string like `drwxr-xr-x` into mode bits including the file type.
+fn parse_mode(s: &str) -> Option<u32> {
+    let chars: Vec<char> = s.chars().take(10).collect();
+    if chars.len() != 10 {
+        return None;
+    }
+    let mut mode = match chars[0] {
+        '-' => 0o100000,
+        'd' => 0o040000,
+        'l' => 0o120000,
+        'c' => 0o020000,
+        'b' => 0o060000,
+        'p' => 0o010000,
+        's' => 0o140000,
+        _ => return None,
+    };
+    for (i, c) in chars[1..].iter().enumerate() {
+        let bit = 1 << (8 - i);
+        match (i % 3, c) {
+            (_, '-') => {}
+            (0, 'r') | (1, 'w') | (2, 'x') => mode |= bit,
+            // setuid/setgid/sticky, lowercase when the execute bit is set too
+            (2, 's' | 't') => mode |= bit | special_bit(i),
+            (2, 'S' | 'T') => mode |= special_bit(i),
+            _ => {
+                warn!(mode = s, "Unrecognised permission character");
+                return None;
+            }
+        }
+    }
+    Some(mode)
+}
+
+fn special_bit(i: usize) -> u32 {
+    match i / 3 {
+        0 => 0o4000,
+        1 => 0o2000,
+        _ => 0o1000,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(parse_mode("drwxr-xr-x"), Some(0o040755));
+        assert_eq!(parse_mode("-rw-r--r--"), Some(0o100644));
+        assert_eq!(parse_mode("-rwsr-xr-x"), Some(0o104755));
+        assert_eq!(parse_mode("drwxrwxrwt"), Some(0o041777));
+        assert_eq!(parse_mode("-rwSr--r--."), Some(0o104644));
+        assert_eq!(parse_mode("total"), None);
+    }
+
+    #[test]
+    fn test_parse_ls_line() {
+        let entry = parse_ls_line("-rw-r--r-- 1 root wheel 1234 1700000000 my file.txt").unwrap();
+        assert_eq!(entry.name, "my file.txt");
+        assert!(entry.is_file());
+        assert_eq!(entry.attributes.size, Some(1234));
+        assert_eq!(entry.attributes.mtime, Some(1700000000));
+        assert_eq!(entry.attributes.user.as_deref(), Some("root"));
+
+        let entry = parse_ls_line(
+            "lrwxrwxrwx    1 root     root            12 Jan  1 12:00 sh -> /bin/busybox",
+        )
+        .unwrap();
+        assert_eq!(entry.name, "sh");
+        assert!(entry.is_symlink());
+        assert_eq!(entry.attributes.mtime, None);
+
+        let entry = parse_ls_line("drwxr-xr-x 2 root root 4096 Mar 10  2024 etc").unwrap();
+        assert_eq!(entry.name, "etc");
+        assert!(entry.is_dir());
+
+        // Names that also appear earlier in the line
+        let entry = parse_ls_line("-rw-r--r-- 1 root root 4 1700000000 root").unwrap();
+        assert_eq!(entry.name, "root");
+        let entry = parse_ls_line("-rw-r--r-- 1 root root 4 Jan  1  2024  2024 ").unwrap();
+        assert_eq!(entry.name, " 2024 ");
+
+        assert!(parse_ls_line("total 12").is_none());
+    }
+
+    #[test]
+    fn test_parse_record() {
+        assert_eq!(
+            parse_record("C0644 12 hello.txt").unwrap(),
+            Record::File {
+                mode: 0o644,
+                size: 12,
+                name: "hello.txt".to_string()
+            }
+        );
+        assert_eq!(
+            parse_record("D0755 0 dir").unwrap(),
+            Record::Dir {
+                mode: 0o755,
+                name: "dir".to_string()
+            }
+        );
+        assert_eq!(parse_record("E").unwrap(), Record::End);
+        assert!(parse_record("C0644 1 ../escape").is_err());
+    }
+}
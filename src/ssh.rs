@@ -7,7 +7,8 @@ use color_eyre::Result;
 use color_eyre::eyre::bail;
 use russh::keys::*;
 use russh::*;
-use russh_sftp::client::SftpSession;
+use russh_sftp::client::{RawSftpSession, SftpSession};
+use russh_sftp::protocol::Version;
 use tokio::net::ToSocketAddrs;
 use tracing::debug;
 
@@ -40,8 +41,12 @@ impl client::Handler for Client {
 
 /// This struct is a convenience wrapper
 /// around a russh client
+///
+/// Clones share the connection. Every channel is independent, so callers
+/// only need to hold a lock on the session long enough to clone it.
+#[derive(Clone)]
 pub struct Session {
-    session: client::Handle<Client>,
+    session: Arc<client::Handle<Client>>,
 }
 
 impl Session {
@@ -55,8 +60,8 @@ impl Session {
 
         // load ssh certificate
         let mut openssh_cert = None;
-        if openssh_cert_path.is_some() {
-            openssh_cert = Some(load_openssh_certificate(openssh_cert_path.unwrap())?);
+        if let Some(openssh_cert_path) = openssh_cert_path {
+            openssh_cert = Some(load_openssh_certificate(openssh_cert_path)?);
         }
 
         let config = client::Config {
@@ -76,7 +81,15 @@ impl Session {
 
         let mut session = client::connect(config, addrs, sh).await?;
         // use publickey authentication, with or without certificate
-        if openssh_cert.is_none() {
+        if let Some(openssh_cert) = openssh_cert {
+            let auth_res = session
+                .authenticate_openssh_cert(user, Arc::new(key_pair), openssh_cert)
+                .await?;
+
+            if !auth_res.success() {
+                bail!("Authentication (with publickey+cert) failed");
+            }
+        } else {
             let auth_res = session
                 .authenticate_publickey(
                     user,
@@ -90,30 +103,105 @@ impl Session {
             if !auth_res.success() {
                 bail!("Authentication (with publickey) failed");
             }
-        } else {
-            let auth_res = session
-                .authenticate_openssh_cert(user, Arc::new(key_pair), openssh_cert.unwrap())
-                .await?;
-
-            if !auth_res.success() {
-                bail!("Authentication (with publickey+cert) failed");
-            }
         }
 
-        Ok(Self { session })
+        Ok(Self {
+            session: Arc::new(session),
+        })
     }
 
-    pub async fn sftp(&mut self) -> Result<SftpSession> {
+    pub async fn sftp(&self) -> Result<SftpSession> {
         let channel = self.session.channel_open_session().await?;
         channel.request_subsystem(true, "sftp").await?;
         let sftp = SftpSession::new(channel.into_stream()).await?;
         Ok(sftp)
     }
 
-    pub async fn close(&mut self) -> Result<()> {
+    /// Opens a second `sftp` subsystem channel without the high level
+    /// wrapper, so that protocol extensions such as `check-file` can be used.
+    pub async fn raw_sftp(&self) -> Result<(RawSftpSession, Version)> {
+        let channel = self.session.channel_open_session().await?;
+        channel.request_subsystem(true, "sftp").await?;
+        let raw = RawSftpSession::new(channel.into_stream());
+        let version = raw.init().await?;
+        Ok((raw, version))
+    }
+
+    /// Starts `command` on the remote host and hands out the channel, for
+    /// commands whose input or output is streamed.
+    pub async fn exec_channel(&self, command: &str) -> Result<Channel<client::Msg>> {
+        let channel = self.session.channel_open_session().await?;
+        channel.exec(true, command).await?;
+        debug!(command, "exec started");
+        Ok(channel)
+    }
+
+    /// Runs `command` on the remote host over an exec channel and collects
+    /// its output.
+    pub async fn exec(&self, command: &str) -> Result<ExecOutput> {
+        let channel = self.exec_channel(command).await?;
+        collect_output(channel, command).await
+    }
+
+    /// Like [`Session::exec`], with `input` fed to the command's stdin.
+    pub async fn exec_with_input(&self, command: &str, input: &[u8]) -> Result<ExecOutput> {
+        let channel = self.exec_channel(command).await?;
+        channel.data(input).await?;
+        channel.eof().await?;
+        collect_output(channel, command).await
+    }
+
+    pub async fn close(&self) -> Result<()> {
         self.session
             .disconnect(Disconnect::ByApplication, "", "English")
             .await?;
         Ok(())
     }
 }
+
+/// Collected output of a remote command, see [`Session::exec`].
+#[derive(Debug, Default)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    pub exit_status: Option<u32>,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.exit_status == Some(0)
+    }
+
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+}
+
+async fn collect_output(mut channel: Channel<client::Msg>, command: &str) -> Result<ExecOutput> {
+    let mut output = ExecOutput::default();
+    while let Some(msg) = channel.wait().await {
+        match msg {
+            ChannelMsg::Data { data } => output.stdout.extend_from_slice(&data),
+            ChannelMsg::ExtendedData { data, ext: 1 } => output.stderr.extend_from_slice(&data),
+            ChannelMsg::ExitStatus { exit_status } => output.exit_status = Some(exit_status),
+            _ => {}
+        }
+    }
+    debug!(command, status = ?output.exit_status, "exec finished");
+    Ok(output)
+}
+
+/// Waits for the remote command on `channel` to exit and returns its status.
+pub async fn wait_exit_status(channel: &mut Channel<client::Msg>) -> Option<u32> {
+    let mut status = None;
+    while let Some(msg) = channel.wait().await {
+        if let ChannelMsg::ExitStatus { exit_status } = msg {
+            status = Some(exit_status);
+        }
+    }
+    status
+}
@@ -1,3 +1,14 @@
 pub mod de;
 pub mod reader;
 pub use de::{Host, Hosts, from_str};
+
+/// Reads the hosts defined in the user's ssh config.
+pub fn read_hosts() -> color_eyre::Result<Hosts> {
+    let mut config_reader = reader::SSHConfigReader::new();
+    config_reader.read()?;
+    let config = config_reader.finalize();
+    if config.trim().is_empty() {
+        return Ok(Hosts(Vec::new()));
+    }
+    Ok(from_str(&config)?)
+}
@@ -0,0 +1,233 @@
+//! Checksums used to verify transfers.
+//!
+//! Remote digests are requested through the `check-file` SFTP extension
+//! first, and computed with `sha256sum`/`md5sum` over an exec channel when
+//! the server does not offer it.
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+
+use color_eyre::eyre::Result;
+use md5::Md5;
+use russh_sftp::protocol::Packet;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use tracing::debug;
+
+use crate::ssh::Session;
+
+const CHECK_FILE_EXT: &str = "check-file";
+const CHECK_FILE_NAME_REQ: &str = "check-file-name";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Md5,
+}
+
+impl HashAlgorithm {
+    /// Algorithms in order of preference.
+    pub const ALL: [HashAlgorithm; 2] = [HashAlgorithm::Sha256, HashAlgorithm::Md5];
+
+    /// Name as used by the `check-file` extension.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Md5 => "md5",
+        }
+    }
+
+    /// Coreutils command printing the digest of a file.
+    pub fn command(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256sum",
+            HashAlgorithm::Md5 => "md5sum",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.name() == name)
+    }
+}
+
+/// A hex encoded digest together with the algorithm that produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: HashAlgorithm,
+    pub hex: String,
+}
+
+impl Display for Checksum {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.algorithm.name(), self.hex)
+    }
+}
+
+/// Computes the digest of a local file.
+pub async fn local_checksum(path: &Path, algorithm: HashAlgorithm) -> Result<Checksum> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let digest = match algorithm {
+        HashAlgorithm::Sha256 => {
+            let mut hasher = Sha256::new();
+            loop {
+                let n = file.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            hasher.finalize().to_vec()
+        }
+        HashAlgorithm::Md5 => {
+            let mut hasher = Md5::new();
+            loop {
+                let n = file.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            hasher.finalize().to_vec()
+        }
+    };
+    Ok(Checksum {
+        algorithm,
+        hex: to_hex(&digest),
+    })
+}
+
+/// Asks the server for the digest of `path` in the first of `algorithms` it
+/// can compute.
+///
+/// Returns `Ok(None)` when neither the `check-file` extension nor one of the
+/// hashing commands is available on the remote host.
+pub async fn remote_checksum(
+    session: &Session,
+    path: &str,
+    algorithms: &[HashAlgorithm],
+) -> Result<Option<Checksum>> {
+    match check_file(session, path, algorithms).await {
+        Ok(Some(checksum)) => return Ok(Some(checksum)),
+        Ok(None) => {}
+        Err(err) => debug!(?err, "check-file extension failed"),
+    }
+
+    let escaped = shell_escape::unix::escape(path.into());
+    for &algorithm in algorithms {
+        let output = session
+            .exec(&format!("{} -- {}", algorithm.command(), escaped))
+            .await?;
+        if !output.success() {
+            debug!(
+                command = algorithm.command(),
+                stderr = output.stderr_lossy(),
+                "hash command failed"
+            );
+            continue;
+        }
+        if let Some(hex) = parse_sum_output(&output.stdout_lossy()) {
+            return Ok(Some(Checksum { algorithm, hex }));
+        }
+    }
+    Ok(None)
+}
+
+async fn check_file(
+    session: &Session,
+    path: &str,
+    algorithms: &[HashAlgorithm],
+) -> Result<Option<Checksum>> {
+    let (raw, version) = session.raw_sftp().await?;
+    if !version.extensions.contains_key(CHECK_FILE_EXT) {
+        let _ = raw.close_session();
+        return Ok(None);
+    }
+
+    let names = algorithms.iter().map(|a| a.name()).collect::<Vec<_>>();
+    let mut data = Vec::new();
+    put_string(&mut data, path.as_bytes());
+    put_string(&mut data, names.join(",").as_bytes());
+    data.extend_from_slice(&0u64.to_be_bytes()); // start offset
+    data.extend_from_slice(&0u64.to_be_bytes()); // length, 0 means whole file
+    data.extend_from_slice(&0u32.to_be_bytes()); // block size, 0 means one hash
+
+    let reply = raw.extended(CHECK_FILE_NAME_REQ, data).await;
+    let _ = raw.close_session();
+    match reply? {
+        Packet::ExtendedReply(reply) => Ok(parse_check_file_reply(&reply.data)
+            .filter(|checksum| algorithms.contains(&checksum.algorithm))),
+        _ => Ok(None),
+    }
+}
+
+/// Parses the body of a `check-file` reply: the extension name, the
+/// algorithm used and the raw hash bytes.
+fn parse_check_file_reply(data: &[u8]) -> Option<Checksum> {
+    let (_name, rest) = take_string(data)?;
+    let (algorithm, hash) = take_string(rest)?;
+    let algorithm = HashAlgorithm::from_name(std::str::from_utf8(algorithm).ok()?)?;
+    if hash.is_empty() {
+        return None;
+    }
+    Some(Checksum {
+        algorithm,
+        hex: to_hex(hash),
+    })
+}
+
+/// Extracts the digest from `sha256sum`/`md5sum` output.
+pub(crate) fn parse_sum_output(output: &str) -> Option<String> {
+    let hex = output.split_whitespace().next()?;
+    // A leading backslash marks escaped file names in coreutils output
+    let hex = hex.trim_start_matches('\\');
+    hex.chars()
+        .all(|c| c.is_ascii_hexdigit())
+        .then(|| hex.to_ascii_lowercase())
+}
+
+/// Appends `s` as an SFTP string, prefixed with its length.
+pub(crate) fn put_string(buf: &mut Vec<u8>, s: &[u8]) {
+    buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
+    buf.extend_from_slice(s);
+}
+
+fn take_string(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    let len = u32::from_be_bytes(data.get(..4)?.try_into().ok()?) as usize;
+    let s = data.get(4..4 + len)?;
+    Some((s, &data[4 + len..]))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sum_output() {
+        assert_eq!(
+            parse_sum_output("D41D8CD98F00B204E9800998ECF8427E  /tmp/empty\n").as_deref(),
+            Some("d41d8cd98f00b204e9800998ecf8427e")
+        );
+        assert_eq!(parse_sum_output("sha256sum: /nope: No such file\n"), None);
+        assert_eq!(parse_sum_output(""), None);
+    }
+
+    #[test]
+    fn test_parse_check_file_reply() {
+        let mut data = Vec::new();
+        put_string(&mut data, b"check-file");
+        put_string(&mut data, b"md5");
+        data.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(
+            parse_check_file_reply(&data),
+            Some(Checksum {
+                algorithm: HashAlgorithm::Md5,
+                hex: "deadbeef".to_string(),
+            })
+        );
+        assert_eq!(parse_check_file_reply(&data[..6]), None);
+    }
+}
@@ -0,0 +1,894 @@
+//! The transfer engine: copies files between the remote host and the local
+//! disk, optionally verifying every file with a checksum afterwards.
+pub mod checksum;
+pub mod rate_limit;
+pub mod tar_stream;
+
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use color_eyre::eyre::{self, Result, bail};
+use russh_sftp::protocol::FileAttributes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, warn};
+
+use self::checksum::{Checksum, HashAlgorithm, local_checksum};
+use self::rate_limit::{Throttle, parse_rate};
+use crate::config::TransferSettings;
+use crate::remote_fs::RemoteFs;
+
+const CHUNK_SIZE: usize = 32 * 1024;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransferOptions {
+    /// Verify every file with a checksum after it was transferred.
+    pub verify: bool,
+    /// Retries after a checksum mismatch before giving up.
+    pub retries: u32,
+    /// Initial bandwidth cap in bytes per second shared by all transfers.
+    pub limit_rate: Option<u64>,
+    /// Initial bandwidth cap in bytes per second for each job.
+    pub job_limit_rate: Option<u64>,
+    /// Transfer directories as a single tar stream when the remote has `tar`.
+    pub tar_mode: bool,
+    /// Copy through a `.part` file and continue it if a copy was interrupted.
+    pub resume: bool,
+}
+
+impl From<&TransferSettings> for TransferOptions {
+    fn from(settings: &TransferSettings) -> Self {
+        let rate = |key: &str, value: &Option<String>| {
+            value
+                .as_deref()
+                .map(parse_rate)
+                .transpose()
+                .unwrap_or_else(|err| {
+                    warn!("Ignoring transfers.{}: {}", key, err);
+                    None
+                })
+                .flatten()
+        };
+        Self {
+            verify: settings.verify,
+            retries: settings.retries,
+            limit_rate: rate("limit_rate", &settings.limit_rate),
+            job_limit_rate: rate("job_limit_rate", &settings.job_limit_rate),
+            tar_mode: settings.tar_mode,
+            resume: settings.resume,
+        }
+    }
+}
+
+/// Outcome of verifying a single transferred file.
+#[derive(Debug, Clone)]
+pub enum Verification {
+    /// Verification is disabled.
+    Skipped,
+    /// The remote host could not produce a checksum.
+    Unavailable,
+    Matched(Checksum),
+}
+
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    pub remote: String,
+    pub attempts: u32,
+    pub verification: Verification,
+}
+
+/// Aggregated result of a batch of transfers, shown once the batch is done.
+#[derive(Debug, Clone, Default)]
+pub struct TransferSummary {
+    pub transferred: usize,
+    pub verified: usize,
+    pub unverified: usize,
+    /// Files that only matched after being transferred again.
+    pub retried: Vec<String>,
+    /// Files that could not be transferred, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+impl TransferSummary {
+    pub fn record(&mut self, remote: &str, result: Result<FileReport>) {
+        match result {
+            Ok(report) => {
+                self.transferred += 1;
+                match report.verification {
+                    Verification::Matched(checksum) => {
+                        debug!(remote = report.remote, %checksum, "Verified");
+                        self.verified += 1
+                    }
+                    Verification::Unavailable => self.unverified += 1,
+                    Verification::Skipped => {}
+                }
+                if report.attempts > 1 {
+                    self.retried.push(report.remote);
+                }
+            }
+            Err(err) => self.failed.push((remote.to_string(), err.to_string())),
+        }
+    }
+
+    /// Records `count` files that were transferred in one go, such as a tar
+    /// stream, and could not be verified one by one.
+    pub fn record_batch(&mut self, count: usize, verify: bool) {
+        self.transferred += count;
+        if verify {
+            warn!(count, "Files in a tar stream are not verified");
+            self.unverified += count;
+        }
+    }
+
+    /// Whether anything happened that the user should be told about.
+    pub fn needs_attention(&self) -> bool {
+        !self.failed.is_empty() || !self.retried.is_empty() || self.unverified > 0
+    }
+}
+
+impl Display for TransferSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Transferred {} file(s)", self.transferred)?;
+        if self.verified > 0 || self.unverified > 0 {
+            write!(
+                f,
+                ", {} verified, {} unverified",
+                self.verified, self.unverified
+            )?;
+        }
+        if !self.retried.is_empty() {
+            write!(
+                f,
+                "\nRetried after checksum mismatch: {}",
+                self.retried.join(", ")
+            )?;
+        }
+        if !self.failed.is_empty() {
+            write!(f, "\nFailed:")?;
+            for (path, reason) in &self.failed {
+                write!(f, "\n  {}: {}", path, reason)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies `reader` into `writer` chunk by chunk, returning the number of bytes copied.
+///
+/// Every chunk has to pass the `throttle` first.
+pub async fn copy_stream<R, W>(reader: &mut R, writer: &mut W, throttle: &Throttle) -> Result<u64>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut total = 0;
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        throttle.acquire(n).await;
+        writer.write_all(&buf[..n]).await?;
+        total += n as u64;
+    }
+    writer.flush().await?;
+    Ok(total)
+}
+
+/// Downloads a single remote file to `local`, creating parent directories as needed.
+pub async fn download_file(
+    fs: &dyn RemoteFs,
+    remote: &str,
+    local: &Path,
+    throttle: &Throttle,
+) -> Result<u64> {
+    let mut remote_file = fs.open(remote).await?;
+    if let Some(parent) = local.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut file = tokio::fs::File::create(local).await?;
+    let len = copy_stream(&mut remote_file, &mut file, throttle).await?;
+    file.sync_all().await?;
+    info!(remote, local = ?local.display(), len, "Downloaded");
+    Ok(len)
+}
+
+/// Writes `data` to the remote file `remote`, replacing its contents.
+pub async fn upload_bytes(
+    fs: &dyn RemoteFs,
+    remote: &str,
+    mut data: &[u8],
+    throttle: &Throttle,
+) -> Result<u64> {
+    let mut remote_file = fs.create(remote).await?;
+    let len = copy_stream(&mut data, &mut remote_file, throttle).await?;
+    remote_file.shutdown().await?;
+    info!(remote, len, "Uploaded");
+    Ok(len)
+}
+
+/// Replaces the remote file `remote` with `data` without leaving it half
+/// written if the connection drops: the data goes to a temporary file next
+/// to it, which takes the mode of the old file and is renamed over it in one
+/// step once complete. If `remote` is a symlink, the file it points to is
+/// replaced and the link kept. The old contents are kept as a `~` backup
+/// next to that file, whose path is returned.
+///
+/// Backends without renames get the file written in place.
+pub async fn replace_file(
+    fs: &dyn RemoteFs,
+    remote: &str,
+    data: &[u8],
+    attributes: &FileAttributes,
+    throttle: &Throttle,
+) -> Result<Option<String>> {
+    if fs.whole_file_writes() {
+        fs.write(remote, data).await?;
+        return Ok(None);
+    }
+    let target = resolve(fs, remote).await;
+    let (temp, backup) = (temp_path(&target), format!("{}~", target));
+    let written = async {
+        upload_bytes(fs, &temp, data, throttle).await?;
+        adopt_attributes(fs, &temp, attributes).await?;
+        backup_file(fs, &target, &backup, attributes, throttle).await?;
+        fs.replace(&temp, &target).await
+    };
+    if let Err(err) = written.await {
+        let _ = fs.remove_file(&temp).await;
+        return Err(err);
+    }
+    info!(remote, target, backup, "Replaced");
+    Ok(Some(backup))
+}
+
+/// Copies `from` over the existing file `to` the way [`replace_file`] saves
+/// a file, with a `~` backup of the old contents and its mode kept. The copy
+/// is verified before it replaces `to` and never resumes, a partial file
+/// left behind would belong to another version. Returns the backup along
+/// with the report.
+pub async fn overwrite_verified(
+    src: &dyn RemoteFs,
+    from: &str,
+    dst: &dyn RemoteFs,
+    to: &str,
+    opts: TransferOptions,
+    throttle: &Throttle,
+) -> Result<(FileReport, Option<String>)> {
+    let opts = TransferOptions {
+        resume: false,
+        ..opts
+    };
+    if dst.whole_file_writes() {
+        let report = copy_verified(src, from, dst, to, opts, throttle).await?;
+        return Ok((report, None));
+    }
+    let target = resolve(dst, to).await;
+    let attributes = dst.stat(&target).await?;
+    let (temp, backup) = (temp_path(&target), format!("{}~", target));
+    let copied = async {
+        let report = copy_verified(src, from, dst, &temp, opts, throttle).await?;
+        adopt_attributes(dst, &temp, &attributes).await?;
+        backup_file(dst, &target, &backup, &attributes, throttle).await?;
+        dst.replace(&temp, &target).await?;
+        Ok(report)
+    };
+    match copied.await {
+        Ok(report) => {
+            info!(from, to, backup, "Overwrote");
+            let report = FileReport {
+                remote: to.to_string(),
+                ..report
+            };
+            Ok((report, Some(backup)))
+        }
+        Err(err) => {
+            let _ = dst.remove_file(&temp).await;
+            Err(err)
+        }
+    }
+}
+
+/// The file `path` points to, or `path` itself if it can't be resolved.
+async fn resolve(fs: &dyn RemoteFs, path: &str) -> String {
+    fs.canonicalize(path)
+        .await
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// A hidden file next to `target` to write its new contents to.
+fn temp_path(target: &str) -> String {
+    let path = Path::new(target);
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path
+        .parent()
+        .map_or(String::new(), |dir| dir.display().to_string());
+    format!("{}/.{}.filessh-{}", dir, name, std::process::id())
+}
+
+/// Gives the new file `temp` the mode and, where allowed, the owner of the
+/// file it replaces.
+async fn adopt_attributes(
+    fs: &dyn RemoteFs,
+    temp: &str,
+    attributes: &FileAttributes,
+) -> Result<()> {
+    fs.setstat(temp, mode_of(attributes)).await?;
+    // Only root or the owner can hand the file back, not worth failing for
+    let owner = FileAttributes {
+        uid: attributes.uid,
+        gid: attributes.gid,
+        ..FileAttributes::empty()
+    };
+    if owner.uid.is_some() && owner.gid.is_some() && fs.setstat(temp, owner).await.is_err() {
+        debug!(temp, "Could not keep the owner of the file");
+    }
+    Ok(())
+}
+
+fn mode_of(attributes: &FileAttributes) -> FileAttributes {
+    FileAttributes {
+        permissions: attributes.permissions.map(|mode| mode & 0o7777),
+        ..FileAttributes::empty()
+    }
+}
+
+/// Keeps the current contents of `target` as `backup`, as a hard link where
+/// the backend can make one and as a copy with the same mode otherwise.
+async fn backup_file(
+    fs: &dyn RemoteFs,
+    target: &str,
+    backup: &str,
+    attributes: &FileAttributes,
+    throttle: &Throttle,
+) -> Result<()> {
+    if fs.exists(backup).await? {
+        fs.remove_file(backup).await?;
+    }
+    if let Err(err) = fs.hard_link(target, backup).await {
+        debug!(target, "Copying the backup instead of linking it: {}", err);
+        copy_file(fs, target, fs, backup, false, throttle).await?;
+        fs.setstat(backup, mode_of(attributes)).await?;
+    }
+    Ok(())
+}
+
+/// Uploads the local file `local` to `remote`, replacing its contents.
+pub async fn upload_file(
+    fs: &dyn RemoteFs,
+    local: &Path,
+    remote: &str,
+    throttle: &Throttle,
+) -> Result<u64> {
+    let mut file = tokio::fs::File::open(local).await?;
+    let mut remote_file = fs.create(remote).await?;
+    let len = copy_stream(&mut file, &mut remote_file, throttle).await?;
+    remote_file.shutdown().await?;
+    info!(local = ?local.display(), remote, len, "Uploaded");
+    Ok(len)
+}
+
+/// Compares the local file with the remote one, `None` meaning that the
+/// remote host could not produce a checksum.
+async fn verify(
+    fs: &dyn RemoteFs,
+    remote: &str,
+    local: &Path,
+) -> Result<Option<(Checksum, Checksum)>> {
+    let Some(expected) = fs.checksum(remote, &HashAlgorithm::ALL).await? else {
+        warn!(
+            remote,
+            "No checksum available on the remote, skipping verification"
+        );
+        return Ok(None);
+    };
+    let actual = local_checksum(local, expected.algorithm).await?;
+    Ok(Some((expected, actual)))
+}
+
+/// Uploads `local` to `remote` and, if enabled, verifies the result against
+/// the local checksum, uploading it again on a mismatch.
+pub async fn upload_verified(
+    fs: &dyn RemoteFs,
+    local: &Path,
+    remote: &str,
+    opts: TransferOptions,
+    throttle: &Throttle,
+) -> Result<FileReport> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        upload_file(fs, local, remote, throttle).await?;
+        let verification = if opts.verify {
+            match verify(fs, remote, local).await? {
+                None => Verification::Unavailable,
+                Some((remote_sum, local_sum)) if remote_sum == local_sum => {
+                    Verification::Matched(local_sum)
+                }
+                Some((remote_sum, local_sum)) => {
+                    error!(remote, %local_sum, %remote_sum, attempts, "Checksum mismatch");
+                    if attempts > opts.retries {
+                        bail!(
+                            "checksum mismatch after {} attempt(s): expected {}, got {}",
+                            attempts,
+                            local_sum,
+                            remote_sum
+                        );
+                    }
+                    continue;
+                }
+            }
+        } else {
+            Verification::Skipped
+        };
+        return Ok(FileReport {
+            remote: remote.to_string(),
+            attempts,
+            verification,
+        });
+    }
+}
+
+/// Uploads a local directory tree file by file, the fallback when the remote
+/// has no `tar`.
+pub async fn upload_dir(
+    fs: &dyn RemoteFs,
+    local_dir: &Path,
+    remote_dir: &str,
+    opts: TransferOptions,
+    throttle: &Throttle,
+    summary: &mut TransferSummary,
+) -> Result<()> {
+    let mut stack = vec![(local_dir.to_path_buf(), remote_dir.to_string())];
+    while let Some((local, remote)) = stack.pop() {
+        if !fs.exists(&remote).await? {
+            fs.mkdir(&remote).await?;
+        }
+        let mut entries = tokio::fs::read_dir(&local).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let remote_child = format!("{}/{}", remote.trim_end_matches('/'), name);
+            if entry.file_type().await?.is_dir() {
+                stack.push((entry.path(), remote_child));
+            } else {
+                let result =
+                    upload_verified(fs, &entry.path(), &remote_child, opts, throttle).await;
+                summary.record(&remote_child, result);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Downloads `remote` to `local` and, if enabled, verifies the copy against
+/// the remote checksum, downloading it again on a mismatch.
+pub async fn download_verified(
+    fs: &dyn RemoteFs,
+    remote: &str,
+    local: &Path,
+    opts: TransferOptions,
+    throttle: &Throttle,
+) -> Result<FileReport> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        download_file(fs, remote, local, throttle).await?;
+        if !opts.verify {
+            return Ok(FileReport {
+                remote: remote.to_string(),
+                attempts,
+                verification: Verification::Skipped,
+            });
+        }
+
+        let Some((expected, actual)) = verify(fs, remote, local).await? else {
+            return Ok(FileReport {
+                remote: remote.to_string(),
+                attempts,
+                verification: Verification::Unavailable,
+            });
+        };
+        if actual == expected {
+            return Ok(FileReport {
+                remote: remote.to_string(),
+                attempts,
+                verification: Verification::Matched(actual),
+            });
+        }
+
+        error!(remote, %expected, %actual, attempts, "Checksum mismatch");
+        if attempts > opts.retries {
+            bail!(
+                "checksum mismatch after {} attempt(s): expected {}, got {}",
+                attempts,
+                expected,
+                actual
+            );
+        }
+    }
+}
+
+/// A file or directory scheduled by [`plan_copy`].
+#[derive(Debug, Clone)]
+pub struct CopyItem {
+    pub from: String,
+    pub to: String,
+    pub is_dir: bool,
+}
+
+/// Lists everything below `from` that has to be copied to `to`, parents
+/// before their children.
+pub async fn plan_copy(src: &dyn RemoteFs, from: &str, to: &str) -> Result<Vec<CopyItem>> {
+    let mut items = Vec::new();
+    let is_dir = src.stat(from).await?.is_dir();
+    items.push(CopyItem {
+        from: from.to_string(),
+        to: to.to_string(),
+        is_dir,
+    });
+    let mut idx = 0;
+    while idx < items.len() {
+        if items[idx].is_dir {
+            let (dir_from, dir_to) = (items[idx].from.clone(), items[idx].to.clone());
+            for entry in src.list(&dir_from).await? {
+                items.push(CopyItem {
+                    from: format!("{}/{}", dir_from.trim_end_matches('/'), entry.name()),
+                    to: format!("{}/{}", dir_to.trim_end_matches('/'), entry.name()),
+                    is_dir: entry.is_dir(),
+                });
+            }
+        }
+        idx += 1;
+    }
+    Ok(items)
+}
+
+/// Size and modification time of the source of a partial copy, kept next
+/// to it in `<part>.info` to tell whether the source changed since.
+fn part_stamp(source: &FileAttributes) -> Option<String> {
+    Some(format!("{} {}\n", source.size?, source.mtime?))
+}
+
+/// How many bytes of `source` the partial copy `part` already holds, 0 if
+/// there is none or it was started from another version of the source.
+async fn resume_offset(dst: &dyn RemoteFs, part: &str, source: &FileAttributes) -> u64 {
+    let Ok(existing) = dst.stat(part).await else {
+        return 0;
+    };
+    let Some(stamp) = part_stamp(source) else {
+        return 0;
+    };
+    let started = dst.read(&format!("{}.info", part)).await.ok();
+    match (existing.size, source.size) {
+        (Some(existing), Some(size))
+            if existing <= size && started.as_deref() == Some(stamp.as_bytes()) =>
+        {
+            existing
+        }
+        _ => 0,
+    }
+}
+
+/// Copies a single file from one filesystem to another, streaming it
+/// through this machine. With `resume`, the data goes to `<to>.part` first,
+/// which is moved into place once complete. A `.part` file left behind by an
+/// interrupted copy is continued rather than started over, as long as the
+/// source has the size and modification time it had then; `to` itself is
+/// never appended to.
+pub async fn copy_file(
+    src: &dyn RemoteFs,
+    from: &str,
+    dst: &dyn RemoteFs,
+    to: &str,
+    resume: bool,
+    throttle: &Throttle,
+) -> Result<u64> {
+    if dst.whole_file_writes() {
+        // SCP takes whole files, announced with their size
+        let size = src
+            .stat(from)
+            .await?
+            .size
+            .ok_or_else(|| eyre::eyre!("The size of {} is unknown", from))?;
+        let mut reader = src.open(from).await?;
+        let len = dst.write_from(to, &mut reader, size, throttle).await?;
+        info!(from, to, len, "Copied");
+        return Ok(len);
+    }
+    if !resume {
+        let (mut reader, mut writer) = (src.open(from).await?, dst.create(to).await?);
+        let len = copy_stream(&mut reader, &mut writer, throttle).await?;
+        writer.shutdown().await?;
+        info!(from, to, len, "Copied");
+        return Ok(len);
+    }
+    let (part, source) = (format!("{}.part", to), src.stat(from).await?);
+    let info = format!("{}.info", part);
+    let offset = resume_offset(dst, &part, &source).await;
+    let (mut reader, mut writer) = if offset > 0 {
+        info!(from, part, offset, "Resuming copy");
+        (src.open_at(from, offset).await?, dst.append(&part).await?)
+    } else {
+        match part_stamp(&source) {
+            Some(stamp) => dst.write(&info, stamp.as_bytes()).await?,
+            None => debug!(from, "No size or time to resume the copy by"),
+        }
+        (src.open(from).await?, dst.create(&part).await?)
+    };
+    let len = copy_stream(&mut reader, &mut writer, throttle).await?;
+    writer.shutdown().await?;
+    if let Err(err) = dst.replace(&part, to).await {
+        debug!(to, "Replacing failed, renaming in two steps: {}", err);
+        rename_over(dst, &part, to).await?;
+    }
+    // Gone with the part if the source had no stamp
+    let _ = dst.remove_file(&info).await;
+    info!(from, to, offset, len, "Copied");
+    Ok(offset + len)
+}
+
+/// Renames `from` to `to` on backends that can't rename over a file: the
+/// old `to` is moved aside first and only removed once `from` took its
+/// place, or moved back if that fails.
+async fn rename_over(fs: &dyn RemoteFs, from: &str, to: &str) -> Result<()> {
+    if !fs.exists(to).await? {
+        return fs.rename(from, to).await;
+    }
+    let aside = format!("{}.filessh-old", to);
+    fs.rename(to, &aside).await?;
+    if let Err(err) = fs.rename(from, to).await {
+        if let Err(restore) = fs.rename(&aside, to).await {
+            error!(to, aside, "Could not restore the old file: {}", restore);
+        }
+        return Err(err);
+    }
+    if let Err(err) = fs.remove_file(&aside).await {
+        warn!(aside, "Could not remove the old file: {}", err);
+    }
+    Ok(())
+}
+
+/// Digests of `from` and `to` in the same algorithm, `None` if the two
+/// sides have none in common. The first side picks its preferred one and
+/// the other follows, or the other way around if it can't.
+pub async fn checksum_pair(
+    src: &dyn RemoteFs,
+    from: &str,
+    dst: &dyn RemoteFs,
+    to: &str,
+) -> Result<Option<(Checksum, Checksum)>> {
+    if let Some(first) = src.checksum(from, &HashAlgorithm::ALL).await?
+        && let Some(second) = dst.checksum(to, &[first.algorithm]).await?
+    {
+        return Ok(Some((first, second)));
+    }
+    let Some(second) = dst.checksum(to, &HashAlgorithm::ALL).await? else {
+        return Ok(None);
+    };
+    let first = src.checksum(from, &[second.algorithm]).await?;
+    Ok(first.map(|first| (first, second)))
+}
+
+/// Copies `from` to `to` and, if enabled, compares the checksums of both
+/// sides, copying it again on a mismatch. Only the first attempt resumes.
+pub async fn copy_verified(
+    src: &dyn RemoteFs,
+    from: &str,
+    dst: &dyn RemoteFs,
+    to: &str,
+    opts: TransferOptions,
+    throttle: &Throttle,
+) -> Result<FileReport> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        copy_file(src, from, dst, to, opts.resume && attempts == 1, throttle).await?;
+        let report = |verification| FileReport {
+            remote: to.to_string(),
+            attempts,
+            verification,
+        };
+        if !opts.verify {
+            return Ok(report(Verification::Skipped));
+        }
+        let Some((expected, actual)) = checksum_pair(src, from, dst, to).await? else {
+            warn!(to, "No common checksum available, skipping verification");
+            return Ok(report(Verification::Unavailable));
+        };
+        if expected == actual {
+            return Ok(report(Verification::Matched(actual)));
+        }
+        error!(to, %expected, %actual, attempts, "Checksum mismatch");
+        if attempts > opts.retries {
+            bail!(
+                "checksum mismatch after {} attempt(s): expected {}, got {}",
+                attempts,
+                expected,
+                actual
+            );
+        }
+    }
+}
+
+pub enum TransferCmd {
+    Download {
+        remote_path: String,
+        local_path: PathBuf,
+        reply: oneshot::Sender<Result<FileReport>>,
+    },
+}
+
+/// Starts a dedicated thread that executes transfer commands one at a time.
+pub fn start_worker(
+    fs: Arc<dyn RemoteFs>,
+    opts: TransferOptions,
+    throttle: Throttle,
+) -> mpsc::UnboundedSender<TransferCmd> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let _: Result<()> = rt.block_on(async move {
+            while let Some(cmd) = rx.recv().await {
+                match cmd {
+                    TransferCmd::Download {
+                        remote_path,
+                        local_path,
+                        reply,
+                    } => {
+                        info!("Opening remote file {:?}", remote_path);
+                        let result =
+                            download_verified(&*fs, &remote_path, &local_path, opts, &throttle)
+                                .await;
+                        if let Err(err) = &result {
+                            error!(remote_path, "Download failed: {:?}", err);
+                        }
+                        let _ = reply.send(result);
+                    }
+                }
+            }
+            eyre::Ok(())
+        });
+        eyre::Ok(())
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote_fs::LocalFs;
+
+    #[tokio::test]
+    async fn test_copy_file_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from").display().to_string();
+        let to = dir.path().join("to").display().to_string();
+        let (part, info) = (format!("{}.part", to), format!("{}.part.info", to));
+        std::fs::write(&from, b"hello world").unwrap();
+        let throttle = Throttle::default();
+        let stamp =
+            || part_stamp(&FileAttributes::from(&std::fs::metadata(&from).unwrap())).unwrap();
+
+        // An interrupted copy left the first half behind
+        std::fs::write(&part, b"HELLO").unwrap();
+        std::fs::write(&info, stamp()).unwrap();
+        let len = copy_file(&LocalFs, &from, &LocalFs, &to, true, &throttle)
+            .await
+            .unwrap();
+        assert_eq!(len, 11);
+        assert_eq!(std::fs::read(&to).unwrap(), b"HELLO world");
+        assert!(!std::fs::exists(&part).unwrap());
+        assert!(!std::fs::exists(&info).unwrap());
+
+        // The source changed between the attempts, the part is of no use
+        std::fs::write(&part, b"HELLO").unwrap();
+        std::fs::write(&info, stamp()).unwrap();
+        std::fs::write(&from, b"goodbye world").unwrap();
+        let file = std::fs::File::options().write(true).open(&from).unwrap();
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        file.set_modified(later).unwrap();
+        copy_file(&LocalFs, &from, &LocalFs, &to, true, &throttle)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&to).unwrap(), b"goodbye world");
+
+        // A part without a record of its source is started over too
+        std::fs::write(&part, b"HELLO").unwrap();
+        copy_file(&LocalFs, &from, &LocalFs, &to, true, &throttle)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&to).unwrap(), b"goodbye world");
+
+        // A stale destination is replaced, never appended to
+        for stale in [&b"GOODBYE"[..], b"GOODBYE WORLD", b"goodbye world, again"] {
+            std::fs::write(&to, stale).unwrap();
+            copy_file(&LocalFs, &from, &LocalFs, &to, true, &throttle)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read(&to).unwrap(), b"goodbye world");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rename_over_keeps_old_file_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from").display().to_string();
+        let to = dir.path().join("to").display().to_string();
+        std::fs::write(&to, b"old").unwrap();
+
+        // Nothing to rename, the old file stays where it was
+        assert!(rename_over(&LocalFs, &from, &to).await.is_err());
+        assert_eq!(std::fs::read(&to).unwrap(), b"old");
+
+        std::fs::write(&from, b"new").unwrap();
+        rename_over(&LocalFs, &from, &to).await.unwrap();
+        assert_eq!(std::fs::read(&to).unwrap(), b"new");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_replace_file_keeps_backup_and_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.conf");
+        std::fs::write(&path, b"old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let remote = path.display().to_string();
+
+        let attributes = LocalFs.stat(&remote).await.unwrap();
+        let backup = replace_file(&LocalFs, &remote, b"new", &attributes, &Throttle::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"new");
+        assert_eq!(std::fs::read(&backup).unwrap(), b"old");
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o640);
+        // Only the file and its backup are left
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // Saving through a link replaces the file it points to
+        let link = dir.path().join("link.conf");
+        std::os::unix::fs::symlink(&path, &link).unwrap();
+        let link = link.display().to_string();
+        replace_file(&LocalFs, &link, b"newer", &attributes, &Throttle::default())
+            .await
+            .unwrap();
+        assert!(std::fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(std::fs::read(&path).unwrap(), b"newer");
+        assert_eq!(std::fs::read(&backup).unwrap(), b"new");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_overwrite_keeps_backup_and_ignores_parts() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        std::fs::write(&from, b"new contents").unwrap();
+        std::fs::write(&to, b"old").unwrap();
+        std::fs::set_permissions(&to, std::fs::Permissions::from_mode(0o640)).unwrap();
+        // Left over from some other copy, resuming it would mix versions
+        std::fs::write(dir.path().join("to.part"), b"new ").unwrap();
+
+        let opts = TransferOptions {
+            resume: true,
+            ..TransferOptions::default()
+        };
+        let (from, to) = (from.display().to_string(), to.display().to_string());
+        let (report, backup) =
+            overwrite_verified(&LocalFs, &from, &LocalFs, &to, opts, &Throttle::default())
+                .await
+                .unwrap();
+        assert_eq!(report.remote, to);
+        assert_eq!(std::fs::read(&to).unwrap(), b"new contents");
+        assert_eq!(std::fs::read(backup.unwrap()).unwrap(), b"old");
+        let mode = std::fs::metadata(&to).unwrap().permissions().mode();
+        assert_eq!(mode & 0o7777, 0o640);
+    }
+}
@@ -0,0 +1,216 @@
+//! Token bucket used to cap the bandwidth of transfers.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use color_eyre::eyre::{Result, eyre};
+
+/// Rate the first [`RateLimiter::step`] down from unlimited lands on.
+pub const STEP_START: u64 = 16 * 1024 * 1024;
+const STEP_MIN: u64 = 16 * 1024;
+const STEP_MAX: u64 = 1024 * 1024 * 1024;
+
+/// A token bucket refilled at `rate` bytes per second. Cloning it yields a
+/// handle to the same bucket, so one limiter can be shared by many transfers.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiter {
+    inner: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes per second, `None` means unlimited.
+    rate: Option<u64>,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Default for Bucket {
+    fn default() -> Self {
+        Self {
+            rate: None,
+            tokens: 0.0,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: u64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.last = now;
+        // Allow bursts of at most one second worth of data
+        self.tokens = (self.tokens + elapsed * rate as f64).min(rate as f64);
+    }
+
+    /// Takes the tokens for `bytes` at time `now`, or returns how long to
+    /// wait before trying again.
+    fn take(&mut self, bytes: usize, now: Instant) -> Option<Duration> {
+        let rate = self.rate?;
+        self.refill(rate, now);
+        // Chunks larger than the bucket are let through once it is full
+        let needed = (bytes as f64).min(rate as f64);
+        if self.tokens >= needed {
+            self.tokens -= bytes as f64;
+            return None;
+        }
+        Some(Duration::from_secs_f64(
+            (needed - self.tokens) / rate as f64,
+        ))
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: Option<u64>) -> Self {
+        let limiter = Self::default();
+        limiter.set_rate(rate);
+        limiter
+    }
+
+    pub fn rate(&self) -> Option<u64> {
+        self.inner.lock().unwrap().rate
+    }
+
+    /// Changes the rate, taking effect for the next chunk of every transfer
+    /// sharing this limiter.
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.inner.lock().unwrap();
+        bucket.rate = rate.filter(|r| *r > 0);
+        bucket.tokens = 0.0;
+        bucket.last = Instant::now();
+    }
+
+    /// Raises or lowers the rate by a factor of two. Lowering an unlimited
+    /// rate starts at [`STEP_START`], raising past [`STEP_MAX`] removes the cap.
+    pub fn step(&self, faster: bool) {
+        let rate = match (self.rate(), faster) {
+            (None, true) => None,
+            (None, false) => Some(STEP_START),
+            (Some(rate), true) if rate.saturating_mul(2) > STEP_MAX => None,
+            (Some(rate), true) => Some(rate * 2),
+            (Some(rate), false) => Some((rate / 2).max(STEP_MIN)),
+        };
+        self.set_rate(rate);
+    }
+
+    /// Waits until `bytes` may be sent.
+    pub async fn acquire(&self, bytes: usize) {
+        loop {
+            let wait = self.inner.lock().unwrap().take(bytes, Instant::now());
+            match wait {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => return,
+            }
+        }
+    }
+}
+
+/// The limiters a single transfer has to respect: the global one shared by
+/// every transfer, and the one belonging to its job.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    pub global: RateLimiter,
+    pub job: RateLimiter,
+}
+
+impl Throttle {
+    pub fn new(global: RateLimiter, job: RateLimiter) -> Self {
+        Self { global, job }
+    }
+
+    pub async fn acquire(&self, bytes: usize) {
+        self.global.acquire(bytes).await;
+        self.job.acquire(bytes).await;
+    }
+}
+
+/// Parses rates like `500K`, `5M` or `1.5G` (per second, powers of 1024).
+/// `0` and the empty string mean unlimited.
+pub fn parse_rate(s: &str) -> Result<Option<u64>> {
+    let s = s.trim();
+    let s = s.strip_suffix("/s").unwrap_or(s);
+    let s = s.strip_suffix(['B', 'b']).unwrap_or(s);
+    if s.is_empty() {
+        return Ok(None);
+    }
+    let (number, multiplier) = match s.chars().last() {
+        Some('k' | 'K') => (&s[..s.len() - 1], 1024.0),
+        Some('m' | 'M') => (&s[..s.len() - 1], 1024.0 * 1024.0),
+        Some('g' | 'G') => (&s[..s.len() - 1], 1024.0 * 1024.0 * 1024.0),
+        _ => (s, 1.0),
+    };
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| eyre!("invalid rate: {}", s))?;
+    if number < 0.0 {
+        return Err(eyre!("invalid rate: {}", s));
+    }
+    let rate = (number * multiplier) as u64;
+    Ok((rate > 0).then_some(rate))
+}
+
+pub fn format_rate(rate: Option<u64>) -> String {
+    match rate {
+        None => "unlimited".to_string(),
+        Some(rate) => format!("{}/s", crate::files::human_readable_size(rate)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("5M").unwrap(), Some(5 * 1024 * 1024));
+        assert_eq!(parse_rate("500k").unwrap(), Some(500 * 1024));
+        assert_eq!(parse_rate("1.5G").unwrap(), Some(1536 * 1024 * 1024));
+        assert_eq!(parse_rate("2MB/s").unwrap(), Some(2 * 1024 * 1024));
+        assert_eq!(parse_rate("1000").unwrap(), Some(1000));
+        assert_eq!(parse_rate("0").unwrap(), None);
+        assert_eq!(parse_rate("").unwrap(), None);
+        assert!(parse_rate("fast").is_err());
+        assert!(parse_rate("-1M").is_err());
+    }
+
+    #[test]
+    fn test_bucket_waits_for_tokens() {
+        let start = Instant::now();
+        let mut bucket = Bucket {
+            rate: Some(100 * 1024),
+            tokens: 0.0,
+            last: start,
+        };
+        // The bucket starts empty, so 50 KiB need half a second
+        let wait = bucket.take(50 * 1024, start).unwrap();
+        assert_eq!(wait, Duration::from_millis(500));
+        let later = start + Duration::from_millis(250);
+        assert_eq!(
+            bucket.take(50 * 1024, later),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            bucket.take(50 * 1024, later + Duration::from_millis(250)),
+            None
+        );
+        assert_eq!(bucket.tokens, 0.0);
+
+        // Bursts are capped at one second worth of data
+        let idle = start + Duration::from_secs(10);
+        assert_eq!(bucket.take(100 * 1024, idle), None);
+        assert!(bucket.take(1, idle).is_some());
+
+        bucket.rate = None;
+        assert_eq!(bucket.take(usize::MAX, idle), None);
+    }
+
+    #[test]
+    fn test_step() {
+        let limiter = RateLimiter::new(Some(100 * 1024));
+        limiter.step(true);
+        assert_eq!(limiter.rate(), Some(200 * 1024));
+        limiter.set_rate(None);
+        limiter.step(false);
+        assert_eq!(limiter.rate(), Some(STEP_START));
+    }
+}
@@ -0,0 +1,174 @@
+//! Directory transfers as a single gzip compressed tar stream.
+//!
+//! `tar` runs on the remote host over an exec channel while the archive is
+//! packed or unpacked locally on the fly, which avoids one round trip per
+//! file when a tree contains many small files.
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{self, Result, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use russh::ChannelMsg;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::SyncIoBridge;
+use tracing::{info, warn};
+
+use super::copy_stream;
+use super::rate_limit::Throttle;
+use crate::ssh::{Session, wait_exit_status};
+
+const PIPE_SIZE: usize = 256 * 1024;
+
+fn escape(path: &str) -> String {
+    shell_escape::unix::escape(path.into()).into_owned()
+}
+
+/// Checks whether `tar` can be used on the remote host.
+pub async fn remote_has_tar(session: &Session) -> bool {
+    match session.exec("command -v tar").await {
+        Ok(output) => output.success(),
+        Err(err) => {
+            warn!(?err, "Could not probe the remote for tar");
+            false
+        }
+    }
+}
+
+/// Counts the entries `tar` will pack for `remote_dir`, the directory itself included.
+pub async fn remote_entry_count(session: &Session, remote_dir: &str) -> Result<usize> {
+    let output = session
+        .exec(&format!("find {} | wc -l", escape(remote_dir)))
+        .await?;
+    Ok(output.stdout_lossy().trim().parse()?)
+}
+
+/// Streams `remote_dir` into `local_dir`. `progress` is called from a
+/// blocking thread with the path of every unpacked entry.
+/// Returns the number of unpacked regular files.
+///
+/// The files are not checksummed afterwards, the gzip stream only guards
+/// against corruption in transit.
+pub async fn download_dir<F>(
+    session: &Session,
+    remote_dir: &str,
+    local_dir: &Path,
+    throttle: &Throttle,
+    mut progress: F,
+) -> Result<usize>
+where
+    F: FnMut(&Path) + Send + 'static,
+{
+    tokio::fs::create_dir_all(local_dir).await?;
+    let command = format!("tar -czf - -C {} .", escape(remote_dir));
+    let mut channel = session.exec_channel(&command).await?;
+
+    let (mut pipe_tx, pipe_rx) = tokio::io::duplex(PIPE_SIZE);
+    let reader = SyncIoBridge::new(pipe_rx);
+    let local_dir = local_dir.to_path_buf();
+    let unpack = tokio::task::spawn_blocking(move || -> Result<usize> {
+        let mut archive = tar::Archive::new(GzDecoder::new(reader));
+        let mut count = 0;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            entry.unpack_in(&local_dir)?;
+            progress(&path);
+            if entry.header().entry_type().is_file() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    });
+
+    // Read the channel by hand rather than as a stream, which would drop
+    // the exit status
+    let (mut bytes, mut stderr, mut status) = (0, Vec::new(), None);
+    let copied = async {
+        while let Some(msg) = channel.wait().await {
+            match msg {
+                ChannelMsg::Data { data } => {
+                    throttle.acquire(data.len()).await;
+                    pipe_tx.write_all(&data).await?;
+                    bytes += data.len();
+                }
+                ChannelMsg::ExtendedData { data, ext: 1 } => stderr.extend_from_slice(&data),
+                ChannelMsg::ExitStatus { exit_status } => status = Some(exit_status),
+                _ => {}
+            }
+        }
+        pipe_tx.shutdown().await?;
+        eyre::Ok(())
+    }
+    .await;
+    drop(pipe_tx);
+    let count = unpack.await??;
+    copied?;
+    match status {
+        Some(0) | None => {}
+        Some(status) => bail!(
+            "remote tar exited with status {}: {}",
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        ),
+    }
+    info!(remote_dir, bytes, count, "Unpacked tar stream");
+    Ok(count)
+}
+
+/// Streams the contents of `local_dir` into `remote_dir`, creating it if needed.
+/// Returns the number of packed regular files, which are not checksummed.
+pub async fn upload_dir(
+    session: &Session,
+    local_dir: &Path,
+    remote_dir: &str,
+    throttle: &Throttle,
+) -> Result<usize> {
+    let escaped = escape(remote_dir);
+    let command = format!("mkdir -p {} && tar -xzf - -C {}", escaped, escaped);
+    let mut channel = session.exec_channel(&command).await?;
+
+    let (pipe_tx, mut pipe_rx) = tokio::io::duplex(PIPE_SIZE);
+    let writer = SyncIoBridge::new(pipe_tx);
+    let local_dir: PathBuf = local_dir.to_path_buf();
+    let pack = tokio::task::spawn_blocking(move || -> Result<usize> {
+        let count = count_files(&local_dir)?;
+        let mut builder = tar::Builder::new(GzEncoder::new(writer, Compression::fast()));
+        builder.follow_symlinks(false);
+        builder.append_dir_all(".", &local_dir)?;
+        let mut writer = builder.into_inner()?.finish()?;
+        writer.shutdown()?;
+        Ok(count)
+    });
+
+    let mut remote = channel.make_writer();
+    let copied = copy_stream(&mut pipe_rx, &mut remote, throttle).await;
+    remote.shutdown().await?;
+    drop(remote);
+    channel.eof().await?;
+    let count = pack.await??;
+    let bytes = copied?;
+    match wait_exit_status(&mut channel).await {
+        Some(0) | None => {}
+        Some(status) => bail!("remote tar exited with status {}", status),
+    }
+    info!(remote_dir, bytes, count, "Packed tar stream");
+    Ok(count)
+}
+
+fn count_files(dir: &Path) -> Result<usize> {
+    let mut count = 0;
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                stack.push(entry.path());
+            } else if file_type.is_file() {
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,204 @@
+//! Named directories per host, kept in `bookmarks.toml` in the data dir,
+//! and the popup listing them.
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{AppEvent, Global};
+use crate::logging::get_data_dir;
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Bookmarks {
+    /// Bookmark name to path, per host label.
+    #[serde(default)]
+    pub hosts: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Bookmarks {
+    fn path() -> PathBuf {
+        get_data_dir().join("bookmarks.toml")
+    }
+
+    pub fn load() -> Result<Self> {
+        match std::fs::read_to_string(Self::path()) {
+            Ok(content) => Ok(toml::from_str(&content)?),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::create_dir_all(get_data_dir())?;
+        std::fs::write(Self::path(), toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The bookmarks of `host` as `(name, path)`, sorted by name.
+    pub fn for_host(&self, host: &str) -> Vec<(String, String)> {
+        self.hosts
+            .get(host)
+            .map(|marks| {
+                marks
+                    .iter()
+                    .map(|(name, path)| (name.clone(), path.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn add(&mut self, host: &str, name: String, path: String) {
+        self.hosts
+            .entry(host.to_string())
+            .or_default()
+            .insert(name, path);
+    }
+
+    pub fn remove(&mut self, host: &str, name: &str) {
+        if let Some(marks) = self.hosts.get_mut(host) {
+            marks.remove(name);
+            if marks.is_empty() {
+                self.hosts.remove(host);
+            }
+        }
+    }
+}
+
+/// Popup listing the bookmarks of the host a pane browses.
+#[derive(Debug, Default)]
+pub struct BookmarkPicker {
+    pub active: bool,
+    /// Pane of the shown tab that jumps to the picked bookmark.
+    pub pane: usize,
+    pub host: String,
+    pub marks: Vec<(String, String)>,
+    pub list_state: ListState,
+}
+
+impl BookmarkPicker {
+    pub fn open(&mut self, pane: usize, host: &str) -> Result<()> {
+        self.marks = Bookmarks::load()?.for_host(host);
+        self.host = host.to_string();
+        self.pane = pane;
+        self.list_state
+            .select((!self.marks.is_empty()).then_some(0));
+        self.active = true;
+        Ok(())
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut BookmarkPicker, ctx: &mut Global) {
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+    );
+    let items: Vec<ListItem> = if state.marks.is_empty() {
+        vec![ListItem::new("No bookmarks yet, add one with B")]
+    } else {
+        state
+            .marks
+            .iter()
+            .map(|(name, path)| ListItem::new(format!("{}  {}", name, path)))
+            .collect()
+    };
+    Clear.render(area, buf);
+    let list = List::new(items)
+        .style(ctx.theme.popup_base())
+        .highlight_style(ctx.theme.select())
+        .highlight_symbol("> ")
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title(format!("Bookmarks on {}", state.host))
+                .title_bottom("<Enter> Go  <x> Remove  <Esc> Cancel"),
+        );
+    StatefulWidget::render(list, area, buf, &mut state.list_state);
+}
+
+pub fn event(event: &Event, state: &mut BookmarkPicker) -> Result<Control<AppEvent>, Error> {
+    let selected = state
+        .list_state
+        .selected()
+        .filter(|idx| *idx < state.marks.len());
+    let r = match event {
+        ct_event!(keycode press Esc) => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Down) | ct_event!(key press 'j') => {
+            state.list_state.select_next();
+            Control::Changed
+        }
+        ct_event!(keycode press Up) | ct_event!(key press 'k') => {
+            state.list_state.select_previous();
+            Control::Changed
+        }
+        ct_event!(keycode press Enter) => match selected {
+            Some(idx) => {
+                state.close();
+                Control::Event(AppEvent::GoTo(state.pane, state.marks[idx].1.clone()))
+            }
+            None => Control::Continue,
+        },
+        ct_event!(key press 'x') => match selected {
+            Some(idx) => {
+                let (name, _) = state.marks.remove(idx);
+                let mut bookmarks = Bookmarks::load()?;
+                bookmarks.remove(&state.host, &name);
+                bookmarks.save()?;
+                Control::Changed
+            }
+            None => Control::Continue,
+        },
+        // The popup is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bookmarks_round_trip() {
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.add("web", "logs".to_string(), "/var/log".to_string());
+        bookmarks.add("web", "app".to_string(), "/srv/app".to_string());
+        bookmarks.add("local", "tmp".to_string(), "/tmp".to_string());
+        bookmarks.remove("local", "tmp");
+
+        let content = toml::to_string(&bookmarks).unwrap();
+        let bookmarks: Bookmarks = toml::from_str(&content).unwrap();
+        assert_eq!(
+            bookmarks.for_host("web"),
+            [
+                ("app".to_string(), "/srv/app".to_string()),
+                ("logs".to_string(), "/var/log".to_string())
+            ]
+        );
+        assert!(!bookmarks.hosts.contains_key("local"));
+    }
+}
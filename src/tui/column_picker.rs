@@ -0,0 +1,146 @@
+//! Popup toggling the columns of the file tables.
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+
+use super::{AppEvent, Global};
+use crate::files::Column;
+
+#[derive(Debug, Default)]
+pub struct ColumnPicker {
+    pub active: bool,
+    pub list_state: ListState,
+}
+
+impl ColumnPicker {
+    pub fn open(&mut self) {
+        self.list_state.select(Some(0));
+        self.active = true;
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+}
+
+/// Shows or hides `column`. New columns go to the right, the name can't be
+/// hidden.
+pub fn toggle(columns: &mut Vec<Column>, column: Column) {
+    if let Some(idx) = columns.iter().position(|c| *c == column) {
+        if column != Column::Name {
+            columns.remove(idx);
+        }
+    } else {
+        columns.push(column);
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut ColumnPicker, ctx: &mut Global) {
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(35),
+        Constraint::Percentage(35),
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+    );
+    let items: Vec<ListItem> = Column::ALL
+        .iter()
+        .map(|column| {
+            let mark = if ctx.cfg.columns.contains(column) {
+                "x"
+            } else {
+                " "
+            };
+            ListItem::new(format!("[{}] {}", mark, column.title()))
+        })
+        .collect();
+    Clear.render(area, buf);
+    let list = List::new(items)
+        .style(ctx.theme.popup_base())
+        .highlight_style(ctx.theme.select())
+        .highlight_symbol("> ")
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title("Columns")
+                .title_bottom("<Space> Toggle  <Esc> Close"),
+        );
+    StatefulWidget::render(list, area, buf, &mut state.list_state);
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut ColumnPicker,
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    let r = match event {
+        ct_event!(keycode press Esc) => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Down) | ct_event!(key press 'j') => {
+            state.list_state.select_next();
+            Control::Changed
+        }
+        ct_event!(keycode press Up) | ct_event!(key press 'k') => {
+            state.list_state.select_previous();
+            Control::Changed
+        }
+        ct_event!(key press ' ') | ct_event!(keycode press Enter) => {
+            match state
+                .list_state
+                .selected()
+                .and_then(|idx| Column::ALL.get(idx))
+            {
+                Some(column) => {
+                    toggle(&mut ctx.cfg.columns, *column);
+                    Control::Event(AppEvent::ColumnsChanged)
+                }
+                None => Control::Continue,
+            }
+        }
+        // The popup is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_and_fit() {
+        let mut columns = Column::DEFAULT.to_vec();
+        toggle(&mut columns, Column::Name);
+        toggle(&mut columns, Column::Size);
+        toggle(&mut columns, Column::Owner);
+        assert_eq!(
+            columns,
+            [
+                Column::Permissions,
+                Column::Name,
+                Column::Modified,
+                Column::Owner
+            ]
+        );
+
+        // Modified doesn't fit next to the name, owner still does
+        let width = Column::Name.width() + Column::Permissions.width() + 1 + 12;
+        assert_eq!(
+            Column::fit(&columns, width),
+            [Column::Permissions, Column::Name, Column::Owner]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Tab completion of paths in the input prompts.
+//!
+//! Listings are fetched in the background and cached for a while, so
+//! completing further down a tree or cycling through candidates doesn't ask
+//! the host again.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::pane::resolve_path;
+use crate::files::FileEntry;
+use crate::remote_fs::RemoteFs;
+
+/// How long a cached listing is trusted.
+const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// An entry a path segment can complete to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+impl From<FileEntry> for Candidate {
+    fn from(entry: FileEntry) -> Self {
+        Self {
+            // A link may point to a directory, let the user descend into it
+            is_dir: entry.is_dir() || entry.is_symlink(),
+            name: entry.name,
+        }
+    }
+}
+
+/// Candidates shown below a prompt, cycled with <Tab>.
+#[derive(Debug, Clone)]
+pub struct Popup {
+    /// Prompt text up to the completed segment.
+    pub dir: String,
+    pub candidates: Vec<Candidate>,
+    pub selected: Option<usize>,
+}
+
+impl Popup {
+    /// The prompt text for the selected candidate.
+    pub fn value(&self) -> Option<String> {
+        let candidate = &self.candidates[self.selected?];
+        Some(completed(&self.dir, candidate))
+    }
+}
+
+/// Where the listings of a prompt come from.
+#[derive(Clone)]
+pub struct Source {
+    pub fs: Arc<dyn RemoteFs>,
+    /// Host the listings come from, `local` for the local disk.
+    pub label: String,
+    /// Directory relative paths start from.
+    pub base: String,
+    /// Where `~` points.
+    pub home: String,
+    pub dirs_only: bool,
+}
+
+impl Source {
+    /// Identifies the listing of `dir` in the cache. The host and backend
+    /// kind outlive any one connection, unlike the address of `fs`.
+    pub fn key(&self, dir: &str) -> CacheKey {
+        (self.label.clone(), self.fs.kind(), dir.to_string())
+    }
+
+    /// The directory to list for the prompt text `dir`.
+    pub fn resolve(&self, dir: &str) -> String {
+        let dir = if dir.is_empty() { "." } else { dir };
+        resolve_path(&self.base, &self.home, dir)
+    }
+}
+
+pub type CacheKey = (String, &'static str, String);
+
+#[derive(Default)]
+pub struct Completer {
+    cache: HashMap<CacheKey, (Instant, Vec<Candidate>)>,
+    pub popup: Option<Popup>,
+}
+
+impl Completer {
+    pub fn cached(&self, key: &CacheKey) -> Option<&[Candidate]> {
+        self.cache
+            .get(key)
+            .filter(|(at, _)| at.elapsed() < CACHE_TTL)
+            .map(|(_, listing)| listing.as_slice())
+    }
+
+    pub fn store(&mut self, key: CacheKey, listing: Vec<Candidate>) {
+        self.cache.retain(|_, (at, _)| at.elapsed() < CACHE_TTL);
+        self.cache.insert(key, (Instant::now(), listing));
+    }
+
+    /// Selects the next (or previous) candidate of the popup and returns
+    /// the prompt text for it.
+    pub fn cycle(&mut self, forward: bool) -> Option<String> {
+        let popup = self.popup.as_mut()?;
+        let len = popup.candidates.len();
+        popup.selected = Some(match (popup.selected, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(idx), true) => (idx + 1) % len,
+            (Some(idx), false) => (idx + len - 1) % len,
+        });
+        popup.value()
+    }
+}
+
+/// Splits prompt text into the directory part and the segment to complete.
+pub fn split(input: &str) -> (&str, &str) {
+    match input.rfind('/') {
+        Some(idx) => input.split_at(idx + 1),
+        None => ("", input),
+    }
+}
+
+fn completed(dir: &str, candidate: &Candidate) -> String {
+    let slash = if candidate.is_dir { "/" } else { "" };
+    format!("{}{}{}", dir, candidate.name, slash)
+}
+
+/// Completes `input` from the listing of its directory. Returns the new
+/// prompt text and, if several entries match, a popup of them.
+pub fn complete(
+    input: &str,
+    listing: &[Candidate],
+    dirs_only: bool,
+) -> Option<(String, Option<Popup>)> {
+    let (dir, prefix) = split(input);
+    // Hidden entries only show up once a dot is typed
+    let mut candidates: Vec<Candidate> = listing
+        .iter()
+        .filter(|c| {
+            c.name.starts_with(prefix) && (prefix.starts_with('.') || !c.name.starts_with('.'))
+        })
+        .filter(|c| c.is_dir || !dirs_only)
+        .cloned()
+        .collect();
+    candidates.sort_by(|a, b| a.name.cmp(&b.name));
+    match candidates.as_slice() {
+        [] => None,
+        [candidate] => Some((completed(dir, candidate), None)),
+        [first, rest @ ..] => {
+            let mut common = first.name.clone();
+            for candidate in rest {
+                let len = common
+                    .char_indices()
+                    .zip(candidate.name.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map_or(0, |((idx, a), _)| idx + a.len_utf8());
+                common.truncate(len);
+            }
+            let popup = Popup {
+                dir: dir.to_string(),
+                candidates,
+                selected: None,
+            };
+            Some((format!("{}{}", dir, common), Some(popup)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(name: &str, is_dir: bool) -> Candidate {
+        Candidate {
+            name: name.to_string(),
+            is_dir,
+        }
+    }
+
+    #[test]
+    fn test_complete() {
+        let listing = [
+            candidate("src", true),
+            candidate("static", true),
+            candidate("setup.sh", false),
+            candidate(".secret", true),
+        ];
+        // A single match is completed with a slash for directories
+        let (value, popup) = complete("app/sr", &listing, false).unwrap();
+        assert_eq!(value, "app/src/");
+        assert!(popup.is_none());
+
+        // Several matches complete their common prefix and pop up
+        let (value, popup) = complete("s", &listing, false).unwrap();
+        assert_eq!(value, "s");
+        let mut completer = Completer {
+            popup,
+            ..Default::default()
+        };
+        assert_eq!(completer.cycle(true).as_deref(), Some("setup.sh"));
+        assert_eq!(completer.cycle(true).as_deref(), Some("src/"));
+        assert_eq!(completer.cycle(false).as_deref(), Some("setup.sh"));
+
+        let (value, _) = complete("st", &listing, true).unwrap();
+        assert_eq!(value, "static/");
+        assert_eq!(complete(".s", &listing, true).unwrap().0, ".secret/");
+        assert!(complete("x", &listing, false).is_none());
+    }
+}
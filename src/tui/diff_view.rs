@@ -0,0 +1,576 @@
+//! Differences between two files, side by side or unified. The files can be
+//! on any host a pane browses, so a deployed file can be compared with its
+//! local copy or with the same file on another server.
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use color_eyre::Report as Error;
+use color_eyre::eyre::{Result, bail};
+use rat_salsa::Control;
+use rat_theme3::Palette;
+use rat_widget::event::ct_event;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+use russh_sftp::protocol::FileAttributes;
+
+use super::editing::diff_style;
+use super::paging::Encoding;
+use super::{AppEvent, Global};
+use crate::diff::{self, Hunk};
+use crate::files::JoinablePaths;
+use crate::remote_fs::RemoteFs;
+use crate::transfer;
+
+/// Rows kept above a hunk jumped to.
+const LEAD: usize = 3;
+
+/// A file or directory to compare and where it lives.
+#[derive(Clone)]
+pub struct Source {
+    pub fs: Arc<dyn RemoteFs>,
+    pub path: String,
+    /// Host of the file, as the pane it was picked from shows it.
+    pub label: String,
+}
+
+impl Debug for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.label, self.path)
+    }
+}
+
+impl Source {
+    /// The entry at the relative `path` below this directory.
+    pub fn child(&self, path: &str) -> Source {
+        Source {
+            fs: Arc::clone(&self.fs),
+            path: self.path.join(path),
+            label: self.label.clone(),
+        }
+    }
+}
+
+/// A copy from one side over the other, done once its key is pressed again.
+#[derive(Debug, Default)]
+pub struct ConfirmCopy {
+    /// `true` for the left side over the right one.
+    pending: Option<bool>,
+}
+
+/// What a key meant to a [`ConfirmCopy`].
+pub enum CopyKey {
+    /// The copy was asked for or cancelled.
+    Handled,
+    /// The copy was confirmed, `true` for the left side over the right one.
+    Confirmed(bool),
+    /// Not a copy key, any copy waiting for confirmation is cancelled.
+    Other,
+}
+
+impl ConfirmCopy {
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn event(&mut self, event: &Event) -> CopyKey {
+        let pending = self.pending.take();
+        match event {
+            ct_event!(key press '>') | ct_event!(key press '<') => {
+                let to_right = matches!(event, ct_event!(key press '>'));
+                if pending == Some(to_right) {
+                    CopyKey::Confirmed(to_right)
+                } else {
+                    self.pending = Some(to_right);
+                    CopyKey::Handled
+                }
+            }
+            ct_event!(keycode press Esc) | ct_event!(key press 'q') if pending.is_some() => {
+                CopyKey::Handled
+            }
+            _ => CopyKey::Other,
+        }
+    }
+
+    /// Footer asking to confirm the copy, `action` says what happens to the
+    /// target.
+    pub fn prompt(&self, action: &str, left: &Source, right: &Source) -> Option<String> {
+        let (key, target) = if self.pending? {
+            ('>', right)
+        } else {
+            ('<', left)
+        };
+        Some(format!(
+            " Press {} again to {} {:?}, <Esc> to cancel ",
+            key, action, target
+        ))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Side {
+    pub source: Source,
+    /// Attributes from just before the file was read.
+    attributes: FileAttributes,
+    bytes: Vec<u8>,
+    text: String,
+    /// Lines of `text` without their line ends.
+    lines: Vec<String>,
+}
+
+impl Side {
+    async fn read(source: Source) -> Result<Self> {
+        let attributes = source.fs.stat(&source.path).await?;
+        let bytes = source.fs.read(&source.path).await?;
+        let Some(encoding) = Encoding::detect(&bytes) else {
+            bail!(
+                "{:?} is a binary file and can't be compared as text",
+                source
+            );
+        };
+        let text = encoding.decode(&bytes);
+        let text = text.strip_prefix('\u{feff}').unwrap_or(&text).to_string();
+        let lines = diff::lines(&text)
+            .into_iter()
+            .map(|line| line.trim_end_matches(['\n', '\r']).to_string())
+            .collect();
+        Ok(Self {
+            source,
+            attributes,
+            bytes,
+            text,
+            lines,
+        })
+    }
+}
+
+/// Both files, read.
+#[derive(Debug, Clone)]
+pub struct Loaded {
+    left: Side,
+    right: Side,
+}
+
+async fn load(left: Source, right: Source) -> Result<Loaded> {
+    let (left, right) = tokio::try_join!(Side::read(left), Side::read(right))?;
+    Ok(Loaded { left, right })
+}
+
+/// Reads both files in the background and shows their differences.
+pub fn spawn_load(left: Source, right: Source, ctx: &mut Global) {
+    ctx.spawn_tab_async(async move |_| {
+        let loaded = load(left, right).await?;
+        Ok(Control::Event(AppEvent::Diff(Box::new(loaded))))
+    });
+}
+
+/// A row of the view: a line of either file or of both. Rows of a unified
+/// diff have only one side unless the line is the same on both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Row {
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub changed: bool,
+}
+
+/// Rows showing files of `left_len` and `right_len` lines with `hunks`
+/// between them, and the row each hunk starts at.
+pub fn rows(
+    hunks: &[Hunk],
+    left_len: usize,
+    right_len: usize,
+    unified: bool,
+) -> (Vec<Row>, Vec<usize>) {
+    let mut rows = Vec::new();
+    let mut starts = Vec::new();
+    let (mut left, mut right) = (0, 0);
+    let same = |rows: &mut Vec<Row>, left: &mut usize, right: &mut usize, until: usize| {
+        while *left < until {
+            rows.push(Row {
+                left: Some(*left),
+                right: Some(*right),
+                changed: false,
+            });
+            *left += 1;
+            *right += 1;
+        }
+    };
+    for hunk in hunks {
+        same(&mut rows, &mut left, &mut right, hunk.before.start);
+        starts.push(rows.len());
+        let change = |left, right| Row {
+            left,
+            right,
+            changed: true,
+        };
+        if unified {
+            rows.extend(hunk.before.clone().map(|line| change(Some(line), None)));
+            rows.extend(hunk.after.clone().map(|line| change(None, Some(line))));
+        } else {
+            // Changed lines face each other, what one side has more of faces
+            // nothing
+            for idx in 0..hunk.before.len().max(hunk.after.len()) {
+                let line = |range: &std::ops::Range<usize>| {
+                    (idx < range.len()).then_some(range.start + idx)
+                };
+                rows.push(change(line(&hunk.before), line(&hunk.after)));
+            }
+        }
+        (left, right) = (hunk.before.end, hunk.after.end);
+    }
+    same(&mut rows, &mut left, &mut right, left_len);
+    debug_assert_eq!(right, right_len);
+    (rows, starts)
+}
+
+#[derive(Default)]
+pub struct DiffView {
+    pub active: bool,
+    loaded: Option<Loaded>,
+    hunks: Vec<Hunk>,
+    unified: bool,
+    rows: Vec<Row>,
+    /// Row each hunk starts at.
+    starts: Vec<usize>,
+    top: usize,
+    /// Rows shown at the last render.
+    page: usize,
+    /// Hunk last jumped to.
+    hunk: Option<usize>,
+    copy: ConfirmCopy,
+}
+
+impl DiffView {
+    /// Shows `loaded`, keeping the place if the same files are shown again.
+    pub fn open(&mut self, loaded: Loaded) {
+        let same = self.loaded.as_ref().is_some_and(|old| {
+            (&old.left.source.path, &old.right.source.path)
+                == (&loaded.left.source.path, &loaded.right.source.path)
+        });
+        if !same {
+            self.top = 0;
+            self.hunk = None;
+        }
+        self.hunks = diff::hunks(&loaded.left.text, &loaded.right.text);
+        self.loaded = Some(loaded);
+        self.active = true;
+        self.copy.cancel();
+        self.layout();
+    }
+
+    pub fn close(&mut self) {
+        *self = Self {
+            unified: self.unified,
+            ..Self::default()
+        };
+    }
+
+    fn layout(&mut self) {
+        let Some(loaded) = &self.loaded else {
+            return;
+        };
+        (self.rows, self.starts) = rows(
+            &self.hunks,
+            loaded.left.lines.len(),
+            loaded.right.lines.len(),
+            self.unified,
+        );
+        if let Some(hunk) = self.hunk.filter(|hunk| *hunk < self.starts.len()) {
+            self.top = self.starts[hunk].saturating_sub(LEAD);
+        }
+    }
+
+    fn jump(&mut self, forward: bool) {
+        let target = |start: &usize| start.saturating_sub(LEAD);
+        // Hunks close together share a target, so go by index from the one
+        // shown
+        let current = self
+            .hunk
+            .filter(|hunk| self.starts.get(*hunk).map(target) == Some(self.top));
+        let hunk = match (forward, current) {
+            (true, Some(hunk)) => Some(hunk + 1).filter(|next| *next < self.starts.len()),
+            (false, Some(hunk)) => hunk.checked_sub(1),
+            (true, None) => self
+                .starts
+                .iter()
+                .position(|start| target(start) >= self.top),
+            (false, None) => self
+                .starts
+                .iter()
+                .rposition(|start| target(start) < self.top),
+        };
+        if let Some(hunk) = hunk {
+            self.hunk = Some(hunk);
+            self.top = target(&self.starts[hunk]);
+        }
+    }
+
+    fn scroll(&mut self, delta: isize) {
+        self.top = self.top.saturating_add_signed(delta);
+    }
+
+    /// Writes one file over the other and compares them again.
+    fn copy_over(&mut self, to_right: bool, ctx: &mut Global) {
+        let Some(loaded) = &self.loaded else {
+            return;
+        };
+        let (from, to) = if to_right {
+            (&loaded.left, &loaded.right)
+        } else {
+            (&loaded.right, &loaded.left)
+        };
+        let data = from.bytes.clone();
+        let target = to.source.clone();
+        let (seen, seen_bytes) = (to.attributes.clone(), to.bytes.clone());
+        let (left, right) = (loaded.left.source.clone(), loaded.right.source.clone());
+        let throttle = ctx.cfg.job_throttle();
+        ctx.spawn_tab_async(async move |chan| {
+            // Only the version that was compared may be overwritten
+            let attributes = target.fs.stat(&target.path).await?;
+            let changed = attributes.size != seen.size
+                || attributes.mtime != seen.mtime
+                || target.fs.read(&target.path).await? != seen_bytes;
+            if changed {
+                bail!(
+                    "{:?} changed since it was compared, not overwriting it",
+                    target
+                );
+            }
+            let backup =
+                transfer::replace_file(&*target.fs, &target.path, &data, &attributes, &throttle)
+                    .await?;
+            let status = match backup {
+                Some(backup) => format!("Overwrote {:?}, the old version is at {}", target, backup),
+                None => format!("Overwrote {:?}", target),
+            };
+            chan.send(Ok(Control::Event(AppEvent::Status(1, status))))
+                .await?;
+            let loaded = load(left, right).await?;
+            Ok(Control::Event(AppEvent::Diff(Box::new(loaded))))
+        });
+    }
+
+    fn footer(&self) -> String {
+        let Some(loaded) = &self.loaded else {
+            return String::new();
+        };
+        let prompt = self
+            .copy
+            .prompt("overwrite", &loaded.left.source, &loaded.right.source);
+        match prompt {
+            Some(prompt) => prompt,
+            None if self.hunks.is_empty() => " The files are identical  <Esc> Close ".to_string(),
+            None => format!(
+                " Hunk {}/{}  <n/N> Hunk  <u> {}  <</>> Copy over  <Esc> Close ",
+                self.hunk.map_or(0, |hunk| hunk + 1),
+                self.hunks.len(),
+                if self.unified {
+                    "Side by side"
+                } else {
+                    "Unified"
+                },
+            ),
+        }
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut DiffView, ctx: &mut Global) {
+    let footer = state.footer();
+    let Some(loaded) = &state.loaded else {
+        return;
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(ctx.theme.popup_border())
+        .title(format!(
+            "Diff {:?}  {:?}",
+            loaded.left.source, loaded.right.source
+        ))
+        .title_bottom(footer);
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.style(ctx.theme.popup_base()).render(area, buf);
+
+    state.page = inner.height as usize;
+    state.top = state.top.min(state.rows.len().saturating_sub(state.page));
+    let shown = &state.rows[state.top..(state.top + state.page).min(state.rows.len())];
+    let palette = ctx.theme.palette();
+    let base = ctx.theme.popup_base();
+    let gutter = ctx.theme.line_nr_style().style;
+    let number = |line: Option<usize>| match line {
+        Some(line) => format!("{:>5} ", line + 1),
+        None => "      ".to_string(),
+    };
+
+    if state.unified {
+        let lines: Vec<Line> = shown
+            .iter()
+            .map(|row| {
+                let (sign, text) = match (row.left, row.right) {
+                    (Some(line), None) => ('-', &loaded.left.lines[line]),
+                    (None, Some(line)) => ('+', &loaded.right.lines[line]),
+                    (_, Some(line)) => (' ', &loaded.right.lines[line]),
+                    (None, None) => (' ', &String::new()),
+                };
+                let text = format!("{}{}", sign, text);
+                let style = diff_style(&text, palette).unwrap_or(base);
+                Line::from(vec![
+                    Span::styled(number(row.left), gutter),
+                    Span::styled(number(row.right), gutter),
+                    Span::styled(text, style),
+                ])
+            })
+            .collect();
+        Paragraph::new(lines).style(base).render(inner, buf);
+        return;
+    }
+
+    let &[left, divider, right] = Layout::horizontal([
+        Constraint::Fill(1),
+        Constraint::Length(1),
+        Constraint::Fill(1),
+    ])
+    .split(inner)
+    .as_ref() else {
+        unreachable!()
+    };
+    let filler = Style::default().bg(palette.gray[Palette::DARK_1]);
+    let side = |lines: &[String], pick: fn(&Row) -> Option<usize>, changed: Style| {
+        shown
+            .iter()
+            .map(|row| match pick(row) {
+                Some(line) => Line::from(vec![
+                    Span::styled(number(Some(line)), gutter),
+                    Span::styled(
+                        lines[line].clone(),
+                        if row.changed { changed } else { base },
+                    ),
+                ]),
+                None => Line::from("").style(filler),
+            })
+            .collect::<Vec<_>>()
+    };
+    let removed = diff_style("-", palette).unwrap_or(base);
+    let added = diff_style("+", palette).unwrap_or(base);
+    Paragraph::new(side(&loaded.left.lines, |row| row.left, removed))
+        .style(base)
+        .render(left, buf);
+    Paragraph::new(side(&loaded.right.lines, |row| row.right, added))
+        .style(base)
+        .render(right, buf);
+    Paragraph::new(vec![Line::from("│"); divider.height as usize])
+        .style(ctx.theme.popup_border())
+        .render(divider, buf);
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut DiffView,
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    match state.copy.event(event) {
+        CopyKey::Handled => return Ok(Control::Changed),
+        CopyKey::Confirmed(to_right) => {
+            state.copy_over(to_right, ctx);
+            return Ok(Control::Changed);
+        }
+        CopyKey::Other => {}
+    }
+    let page = state.page.max(1) as isize;
+    let r = match event {
+        ct_event!(keycode press Esc) | ct_event!(key press 'q') => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(key press 'u') => {
+            state.unified = !state.unified;
+            state.layout();
+            Control::Changed
+        }
+        ct_event!(key press 'n') | ct_event!(key press ']') => {
+            state.jump(true);
+            Control::Changed
+        }
+        ct_event!(key press 'N') | ct_event!(key press SHIFT-'N') | ct_event!(key press '[') => {
+            state.jump(false);
+            Control::Changed
+        }
+        ct_event!(key press 'j') | ct_event!(keycode press Down) => {
+            state.scroll(1);
+            Control::Changed
+        }
+        ct_event!(key press 'k') | ct_event!(keycode press Up) => {
+            state.scroll(-1);
+            Control::Changed
+        }
+        ct_event!(keycode press PageDown) | ct_event!(key press ' ') => {
+            state.scroll(page);
+            Control::Changed
+        }
+        ct_event!(keycode press PageUp) => {
+            state.scroll(-page);
+            Control::Changed
+        }
+        ct_event!(key press 'g') | ct_event!(keycode press Home) => {
+            state.top = 0;
+            Control::Changed
+        }
+        ct_event!(key press 'G')
+        | ct_event!(key press SHIFT-'G')
+        | ct_event!(keycode press End) => {
+            state.top = state.rows.len();
+            Control::Changed
+        }
+        // The view is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows() {
+        let (before, after) = ("a\nb\nc\nd\n", "a\nB\nB2\nc\n");
+        let hunks = diff::hunks(before, after);
+        let row = |left, right, changed| Row {
+            left,
+            right,
+            changed,
+        };
+
+        let (side_by_side, starts) = rows(&hunks, 4, 4, false);
+        assert_eq!(
+            side_by_side,
+            [
+                row(Some(0), Some(0), false),
+                row(Some(1), Some(1), true),
+                row(None, Some(2), true),
+                row(Some(2), Some(3), false),
+                row(Some(3), None, true),
+            ]
+        );
+        assert_eq!(starts, [1, 4]);
+
+        let (unified, starts) = rows(&hunks, 4, 4, true);
+        assert_eq!(
+            unified,
+            [
+                row(Some(0), Some(0), false),
+                row(Some(1), None, true),
+                row(None, Some(1), true),
+                row(None, Some(2), true),
+                row(Some(2), Some(3), false),
+                row(Some(3), None, true),
+            ]
+        );
+        assert_eq!(starts, [1, 5]);
+    }
+}
@@ -0,0 +1,901 @@
+//! Differences between two directory trees, on the same host or on two
+//! different ones, with a way to copy what differs from one to the other.
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use futures::StreamExt;
+use rat_salsa::Control;
+use rat_theme3::Palette;
+use rat_widget::event::ct_event;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::Rect;
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+
+use super::diff_view::{self, ConfirmCopy, CopyKey, Source};
+use super::editing::diff_style;
+use super::{AppEvent, Global};
+use crate::files::{FileEntry, JoinablePaths, human_readable_size};
+use crate::par_dir_traversal::{WalkParallel, WalkState};
+use crate::transfer::{CopyItem, checksum_pair};
+
+/// Files of the same size but different times whose contents are checked at
+/// once.
+const HASHING: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    OnlyLeft,
+    OnlyRight,
+    Identical,
+    Different,
+}
+
+const STATUSES: [Status; 4] = [
+    Status::OnlyLeft,
+    Status::OnlyRight,
+    Status::Different,
+    Status::Identical,
+];
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Status::OnlyLeft => "Only left",
+            Status::OnlyRight => "Only right",
+            Status::Identical => "Identical",
+            Status::Different => "Different",
+        }
+    }
+
+    fn sign(self) -> char {
+        match self {
+            Status::OnlyLeft => '<',
+            Status::OnlyRight => '>',
+            Status::Identical => '=',
+            Status::Different => '≠',
+        }
+    }
+
+    fn style(self, palette: &Palette, base: Style) -> Style {
+        match self {
+            Status::OnlyLeft => diff_style("-", palette).unwrap_or(base),
+            Status::OnlyRight => diff_style("+", palette).unwrap_or(base),
+            Status::Different => base.fg(palette.yellow[Palette::BRIGHT_2]),
+            Status::Identical => base,
+        }
+    }
+}
+
+/// Something found in either tree, or in both.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Relative to both roots, separated by '/'.
+    pub path: String,
+    pub left: Option<FileEntry>,
+    pub right: Option<FileEntry>,
+    pub status: Status,
+}
+
+impl Entry {
+    fn is_dir(&self) -> bool {
+        self.left.iter().chain(&self.right).all(FileEntry::is_dir)
+    }
+
+    fn depth(&self) -> usize {
+        self.path.matches('/').count()
+    }
+
+    fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or(&self.path)
+    }
+
+    /// Both files are there with the same size, only their contents can tell
+    /// whether they differ.
+    fn needs_hash(&self) -> bool {
+        match (&self.left, &self.right) {
+            (Some(left), Some(right)) => {
+                left.is_file() && right.is_file() && left.attributes.size == right.attributes.size
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether `path` is `dir` or below it.
+fn within(path: &str, dir: &str) -> bool {
+    path.strip_prefix(dir)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Pairs up the entries of both trees, keyed by their relative paths. Files
+/// differ by type or size, files of the same size are identical if they have
+/// the same modification time and different otherwise, until their contents
+/// say more. Directories differ if anything below them does.
+pub fn classify(
+    mut left: HashMap<String, FileEntry>,
+    mut right: HashMap<String, FileEntry>,
+) -> Vec<Entry> {
+    let paths: BTreeSet<String> = left.keys().chain(right.keys()).cloned().collect();
+    let mut entries: Vec<Entry> = paths
+        .into_iter()
+        .map(|path| {
+            let (left, right) = (left.remove(&path), right.remove(&path));
+            let status = match (&left, &right) {
+                (Some(_), None) => Status::OnlyLeft,
+                (None, Some(_)) => Status::OnlyRight,
+                (Some(left), Some(right)) if left.is_dir() != right.is_dir() => Status::Different,
+                (Some(left), Some(_)) if left.is_dir() => Status::Identical,
+                (Some(left), Some(right)) => {
+                    let (left, right) = (&left.attributes, &right.attributes);
+                    if (left.size, left.mtime) == (right.size, right.mtime) {
+                        Status::Identical
+                    } else {
+                        Status::Different
+                    }
+                }
+                (None, None) => unreachable!(),
+            };
+            Entry {
+                path,
+                left,
+                right,
+                status,
+            }
+        })
+        .collect();
+    // Children right after their parents
+    entries.sort_by(|a, b| a.path.split('/').cmp(b.path.split('/')));
+    roll_up(&mut entries);
+    entries
+}
+
+/// Marks directories on both sides as different if anything below them is.
+fn roll_up(entries: &mut [Entry]) {
+    let mut changed = HashSet::new();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.status != Status::Identical)
+    {
+        let mut path = entry.path.as_str();
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            if !changed.insert(parent.to_string()) {
+                break;
+            }
+            path = parent;
+        }
+    }
+    // Directories on one side only keep saying so
+    for entry in entries.iter_mut() {
+        let both = entry.left.is_some() && entry.right.is_some();
+        if both && entry.is_dir() && changed.contains(&entry.path) {
+            entry.status = Status::Different;
+        }
+    }
+}
+
+/// Every entry below the root of `tree` by relative path, and how many
+/// could not be read. Links are left out.
+async fn walk(tree: &Source) -> (HashMap<String, FileEntry>, usize) {
+    let walker = WalkParallel {
+        filter: Arc::new(|_| true),
+        path: tree.path.clone().into(),
+        max_depth: None,
+        min_depth: None,
+        threads: 4,
+        fs: Arc::clone(&tree.fs),
+    };
+    let collected = Arc::new(Mutex::new((HashMap::new(), 0)));
+    let collected_ref = Arc::clone(&collected);
+    walker
+        .run(|| {
+            let collected = Arc::clone(&collected_ref);
+            let root = tree.path.clone();
+            Box::new(move |entry: Result<FileEntry>| {
+                let mut collected = collected.lock().unwrap();
+                match entry {
+                    Ok(entry) if entry.is_symlink() => {}
+                    Ok(entry) => {
+                        let path = entry.name.strip_prefix(&root).unwrap_or(&entry.name);
+                        let path = path.trim_start_matches('/').to_string();
+                        // The root comes back without attributes
+                        if !path.is_empty() {
+                            collected.0.insert(path, entry);
+                        }
+                    }
+                    Err(_) => collected.1 += 1,
+                }
+                WalkState::Continue
+            })
+        })
+        .await;
+    std::mem::take(&mut *collected.lock().unwrap())
+}
+
+/// Whether two files have the same checksum, `false` if either can't tell.
+async fn same_content(left: &Source, right: &Source, path: &str) -> bool {
+    let (left_path, right_path) = (left.path.join(path), right.path.join(path));
+    match checksum_pair(&*left.fs, &left_path, &*right.fs, &right_path).await {
+        Ok(Some((left, right))) => left == right,
+        _ => false,
+    }
+}
+
+/// Both trees, compared.
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub left: Source,
+    pub right: Source,
+    pub entries: Vec<Entry>,
+    /// Directories or entries that could not be read on either side.
+    pub unreadable: usize,
+}
+
+pub async fn compare(left: Source, right: Source) -> Comparison {
+    let ((left_entries, left_errors), (right_entries, right_errors)) =
+        tokio::join!(walk(&left), walk(&right));
+    let mut entries = classify(left_entries, right_entries);
+
+    // Times differ after copies that didn't keep them, so look inside
+    let unsure: Vec<usize> = (0..entries.len())
+        .filter(|idx| entries[*idx].status == Status::Different && entries[*idx].needs_hash())
+        .collect();
+    if !unsure.is_empty() {
+        let same: Vec<(usize, bool)> = futures::stream::iter(unsure)
+            .map(|idx| {
+                let (left, right, path) = (&left, &right, &entries[idx].path);
+                async move { (idx, same_content(left, right, path).await) }
+            })
+            .buffer_unordered(HASHING)
+            .collect()
+            .await;
+        for (idx, same) in same {
+            if same {
+                entries[idx].status = Status::Identical;
+            }
+        }
+        // Directories only changed by those files are the same after all
+        for entry in entries.iter_mut().filter(|entry| entry.is_dir()) {
+            if entry.left.is_some() && entry.right.is_some() {
+                entry.status = Status::Identical;
+            }
+        }
+        roll_up(&mut entries);
+    }
+    Comparison {
+        left,
+        right,
+        entries,
+        unreadable: left_errors + right_errors,
+    }
+}
+
+/// Walks both trees in the background and shows how they differ.
+pub fn spawn_compare(left: Source, right: Source, ctx: &mut Global) {
+    ctx.spawn_tab_async(async move |chan| {
+        let status = format!("Comparing {:?} with {:?}", left, right);
+        chan.send(Ok(Control::Event(AppEvent::Status(1, status))))
+            .await?;
+        let comparison = compare(left, right).await;
+        chan.send(Ok(Control::Event(AppEvent::Status(1, String::new()))))
+            .await?;
+        Ok(Control::Event(AppEvent::Compared(Box::new(comparison))))
+    });
+}
+
+/// Differences to copy from one tree into the other.
+#[derive(Debug, Clone)]
+pub struct CopyPlan {
+    pub from: Source,
+    pub to: Source,
+    /// Parents before their children.
+    pub items: Vec<CopyItem>,
+    /// Entries that can't be copied over, with the reason.
+    pub skipped: Vec<(String, String)>,
+    /// The trees to compare again afterwards.
+    pub left: Source,
+    pub right: Source,
+}
+
+/// Entries shown for `shown` statuses, by their index in `entries`, along
+/// with the directories above them. Entries below `collapsed` directories
+/// are left out, `filter` keeps those whose path contains it.
+pub fn visible(
+    entries: &[Entry],
+    shown: [bool; 4],
+    filter: &str,
+    collapsed: &HashSet<String>,
+) -> Vec<usize> {
+    let filter = filter.to_lowercase();
+    let matches = |entry: &Entry| {
+        shown[STATUSES
+            .iter()
+            .position(|status| *status == entry.status)
+            .unwrap_or(0)]
+            && entry.path.to_lowercase().contains(&filter)
+    };
+    let mut parents = HashSet::new();
+    for entry in entries.iter().filter(|entry| matches(entry)) {
+        let mut path = entry.path.as_str();
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            if !parents.insert(parent) {
+                break;
+            }
+            path = parent;
+        }
+    }
+    let hidden = |entry: &Entry| {
+        let mut path = entry.path.as_str();
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            if collapsed.contains(parent) {
+                return true;
+            }
+            path = parent;
+        }
+        false
+    };
+    (0..entries.len())
+        .filter(|idx| {
+            let entry = &entries[*idx];
+            (matches(entry) || parents.contains(entry.path.as_str())) && !hidden(entry)
+        })
+        .collect()
+}
+
+pub struct DirCompare {
+    pub active: bool,
+    comparison: Option<Comparison>,
+    /// Statuses shown, in the order of [`STATUSES`].
+    shown: [bool; 4],
+    filter: String,
+    /// Whether keys go to the filter.
+    filtering: bool,
+    collapsed: HashSet<String>,
+    marked: HashSet<String>,
+    /// Indices into the entries of the comparison.
+    rows: Vec<usize>,
+    cursor: usize,
+    top: usize,
+    /// Rows shown at the last render.
+    page: usize,
+    copy: ConfirmCopy,
+}
+
+impl Default for DirCompare {
+    fn default() -> Self {
+        Self {
+            active: false,
+            comparison: None,
+            // What is the same is rarely what one is looking for
+            shown: [true, true, true, false],
+            filter: String::new(),
+            filtering: false,
+            collapsed: HashSet::new(),
+            marked: HashSet::new(),
+            rows: Vec::new(),
+            cursor: 0,
+            top: 0,
+            page: 0,
+            copy: ConfirmCopy::default(),
+        }
+    }
+}
+
+impl DirCompare {
+    /// Shows `comparison`, keeping the place and filters if the same trees
+    /// are compared again.
+    pub fn open(&mut self, comparison: Comparison) {
+        let same = self.comparison.as_ref().is_some_and(|old| {
+            (&old.left.path, &old.right.path) == (&comparison.left.path, &comparison.right.path)
+        });
+        let path = self.selected().map(|entry| entry.path.clone());
+        if !same {
+            *self = Self {
+                shown: self.shown,
+                ..Self::default()
+            };
+        }
+        self.marked.clear();
+        self.comparison = Some(comparison);
+        self.active = true;
+        self.refresh();
+        if let Some(path) = path {
+            self.select(&path);
+        }
+    }
+
+    pub fn close(&mut self) {
+        *self = Self {
+            shown: self.shown,
+            ..Self::default()
+        };
+    }
+
+    fn refresh(&mut self) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        self.rows = visible(
+            &comparison.entries,
+            self.shown,
+            &self.filter,
+            &self.collapsed,
+        );
+        self.cursor = self.cursor.min(self.rows.len().saturating_sub(1));
+    }
+
+    fn selected(&self) -> Option<&Entry> {
+        let comparison = self.comparison.as_ref()?;
+        comparison.entries.get(*self.rows.get(self.cursor)?)
+    }
+
+    fn select(&mut self, path: &str) {
+        let Some(comparison) = &self.comparison else {
+            return;
+        };
+        if let Some(row) = self
+            .rows
+            .iter()
+            .position(|idx| comparison.entries[*idx].path == path)
+        {
+            self.cursor = row;
+        }
+    }
+
+    fn step(&mut self, delta: isize) {
+        self.cursor = self
+            .cursor
+            .saturating_add_signed(delta)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    fn toggle_mark(&mut self) {
+        if let Some(path) = self.selected().map(|entry| entry.path.clone())
+            && !self.marked.remove(&path)
+        {
+            self.marked.insert(path);
+        }
+        self.step(1);
+    }
+
+    fn toggle_collapsed(&mut self) {
+        if let Some(entry) = self.selected().filter(|entry| entry.is_dir()) {
+            let path = entry.path.clone();
+            if !self.collapsed.remove(&path) {
+                self.collapsed.insert(path);
+            }
+            self.refresh();
+        }
+    }
+
+    /// Marked entries, or the selected one.
+    fn targets(&self) -> Vec<String> {
+        if self.marked.is_empty() {
+            self.selected()
+                .map(|entry| entry.path.clone())
+                .into_iter()
+                .collect()
+        } else {
+            self.marked.iter().cloned().collect()
+        }
+    }
+
+    /// Starts copying the marked entries, or the one under the cursor, over.
+    fn copy_over(&mut self, to_right: bool) -> Control<AppEvent> {
+        match self.plan(to_right) {
+            Some(plan) if plan.items.is_empty() && plan.skipped.is_empty() => {
+                Control::Event(AppEvent::Status(1, "Nothing to copy".to_string()))
+            }
+            Some(plan) => {
+                self.marked.clear();
+                Control::Event(AppEvent::CopyDifferences(Box::new(plan)))
+            }
+            None => Control::Changed,
+        }
+    }
+
+    /// What copying the targets with everything different below them into
+    /// the other tree takes, creating the directories above them that are
+    /// missing there.
+    fn plan(&self, to_right: bool) -> Option<CopyPlan> {
+        let comparison = self.comparison.as_ref()?;
+        let (from, to) = if to_right {
+            (&comparison.left, &comparison.right)
+        } else {
+            (&comparison.right, &comparison.left)
+        };
+        let sides = |entry: &Entry| {
+            if to_right {
+                (entry.left.clone(), entry.right.clone())
+            } else {
+                (entry.right.clone(), entry.left.clone())
+            }
+        };
+        let targets = self.targets();
+        let mut plan = CopyPlan {
+            from: from.clone(),
+            to: to.clone(),
+            items: Vec::new(),
+            skipped: Vec::new(),
+            left: comparison.left.clone(),
+            right: comparison.right.clone(),
+        };
+        for entry in &comparison.entries {
+            let (Some(source), target) = sides(entry) else {
+                continue;
+            };
+            let below = targets.iter().any(|path| within(&entry.path, path));
+            let above = targets
+                .iter()
+                .any(|path| path != &entry.path && within(path, &entry.path));
+            let wanted = if below {
+                entry.status != Status::Identical
+            } else {
+                above && source.is_dir() && target.is_none()
+            };
+            if !wanted {
+                continue;
+            }
+            let (from_path, to_path) = (from.path.join(&entry.path), to.path.join(&entry.path));
+            match target {
+                Some(target) if target.is_dir() != source.is_dir() => plan.skipped.push((
+                    to_path,
+                    "is a directory on one side and a file on the other".to_string(),
+                )),
+                Some(_) if source.is_dir() => {}
+                _ => plan.items.push(CopyItem {
+                    from: from_path,
+                    to: to_path,
+                    is_dir: source.is_dir(),
+                }),
+            }
+        }
+        Some(plan)
+    }
+
+    fn header(&self, palette: &Palette, base: Style) -> Line<'static> {
+        let Some(comparison) = &self.comparison else {
+            return Line::default();
+        };
+        let mut spans = Vec::new();
+        for (idx, status) in STATUSES.iter().enumerate() {
+            let count = comparison
+                .entries
+                .iter()
+                .filter(|entry| entry.status == *status && !entry.is_dir())
+                .count();
+            let mut style = status.style(palette, base);
+            if !self.shown[idx] {
+                style = style.crossed_out().dim();
+            }
+            spans.push(Span::styled(
+                format!(" {} {} {}", idx + 1, status.label(), count),
+                style,
+            ));
+            spans.push(Span::styled(" ", base));
+        }
+        if comparison.unreadable > 0 {
+            spans.push(Span::styled(
+                format!(" {} unreadable", comparison.unreadable),
+                diff_style("-", palette).unwrap_or(base),
+            ));
+        }
+        Line::from(spans)
+    }
+
+    fn footer(&self) -> String {
+        let Some(comparison) = &self.comparison else {
+            return String::new();
+        };
+        if self.filtering {
+            return format!(" Filter: {}_  <Enter> Done  <Esc> Clear ", self.filter);
+        }
+        let count = self.marked.len().max(1);
+        let action = format!(
+            "copy {} {} into",
+            count,
+            if count == 1 { "entry" } else { "entries" }
+        );
+        self.copy
+            .prompt(&action, &comparison.left, &comparison.right)
+            .unwrap_or_else(|| {
+                concat!(
+                    " <1-4> Show  </> Filter  <Space> Mark  <Enter> Diff/Fold",
+                    "  <</>> Copy over  <r> Rescan  <Esc> Close ",
+                )
+                .to_string()
+            })
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut DirCompare, ctx: &mut Global) {
+    let footer = state.footer();
+    let palette = ctx.theme.palette();
+    let base = ctx.theme.popup_base();
+    let header = state.header(palette, base);
+    let Some(comparison) = &state.comparison else {
+        return;
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(ctx.theme.popup_border())
+        .title(format!(
+            "Compare {:?}  {:?}",
+            comparison.left, comparison.right
+        ))
+        .title_bottom(footer);
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.style(base).render(area, buf);
+
+    // The header takes a row
+    state.page = (inner.height as usize).saturating_sub(1);
+    if state.cursor < state.top {
+        state.top = state.cursor;
+    } else if state.cursor >= state.top + state.page.max(1) {
+        state.top = state.cursor + 1 - state.page.max(1);
+    }
+    let gutter = ctx.theme.line_nr_style().style;
+    let size = |entry: &Option<FileEntry>| match entry {
+        Some(entry) if !entry.is_dir() => {
+            format!(
+                "{:>10} ",
+                human_readable_size(entry.attributes.size.unwrap_or(0))
+            )
+        }
+        _ => format!("{:>10} ", ""),
+    };
+    let mut lines = vec![header];
+    for (row, idx) in state
+        .rows
+        .iter()
+        .enumerate()
+        .skip(state.top)
+        .take(state.page)
+    {
+        let entry = &comparison.entries[*idx];
+        let mut style = entry.status.style(palette, base);
+        if state.marked.contains(&entry.path) {
+            style = style.bold();
+        }
+        if row == state.cursor {
+            style = ctx.theme.select();
+        }
+        let name = match (entry.is_dir(), state.collapsed.contains(&entry.path)) {
+            (true, true) => format!("{}/ …", entry.name()),
+            (true, false) => format!("{}/", entry.name()),
+            (false, _) => entry.name().to_string(),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(
+                if state.marked.contains(&entry.path) {
+                    "*"
+                } else {
+                    " "
+                },
+                style,
+            ),
+            Span::styled(format!("{} ", entry.status.sign()), style),
+            Span::styled(size(&entry.left), gutter),
+            Span::styled(size(&entry.right), gutter),
+            Span::styled(format!(" {}{}", "  ".repeat(entry.depth()), name), style),
+        ]));
+    }
+    if state.rows.is_empty() {
+        lines.push(Line::from(" Nothing to show").style(gutter));
+    }
+    Paragraph::new(lines).style(base).render(inner, buf);
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut DirCompare,
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    if state.filtering {
+        let r = match event {
+            ct_event!(keycode press Enter) => {
+                state.filtering = false;
+                Control::Changed
+            }
+            ct_event!(keycode press Esc) => {
+                state.filtering = false;
+                state.filter.clear();
+                state.refresh();
+                Control::Changed
+            }
+            ct_event!(keycode press Backspace) => {
+                state.filter.pop();
+                state.refresh();
+                Control::Changed
+            }
+            ct_event!(key press c) | ct_event!(key press SHIFT-c) => {
+                state.filter.push(*c);
+                state.refresh();
+                Control::Changed
+            }
+            _ => Control::Unchanged,
+        };
+        return Ok(r);
+    }
+
+    match state.copy.event(event) {
+        CopyKey::Handled => return Ok(Control::Changed),
+        CopyKey::Confirmed(to_right) => return Ok(state.copy_over(to_right)),
+        CopyKey::Other => {}
+    }
+    let page = state.page.max(1) as isize;
+    let r = match event {
+        ct_event!(keycode press Esc) | ct_event!(key press 'q') => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(key press c @ '1'..='4') => {
+            let idx = *c as usize - '1' as usize;
+            state.shown[idx] = !state.shown[idx];
+            state.refresh();
+            Control::Changed
+        }
+        ct_event!(key press '/') | ct_event!(key press 'f') => {
+            state.filtering = true;
+            Control::Changed
+        }
+        ct_event!(key press 'r') => {
+            if let Some(comparison) = &state.comparison {
+                spawn_compare(comparison.left.clone(), comparison.right.clone(), ctx);
+            }
+            Control::Changed
+        }
+        ct_event!(key press ' ') => {
+            state.toggle_mark();
+            Control::Changed
+        }
+        ct_event!(keycode press Enter) => {
+            if state.selected().is_some_and(Entry::is_dir) {
+                state.toggle_collapsed();
+            } else if let (Some(entry), Some(comparison)) = (state.selected(), &state.comparison)
+                && entry.left.is_some()
+                && entry.right.is_some()
+            {
+                diff_view::spawn_load(
+                    comparison.left.child(&entry.path),
+                    comparison.right.child(&entry.path),
+                    ctx,
+                );
+            }
+            Control::Changed
+        }
+        ct_event!(key press 'j') | ct_event!(keycode press Down) => {
+            state.step(1);
+            Control::Changed
+        }
+        ct_event!(key press 'k') | ct_event!(keycode press Up) => {
+            state.step(-1);
+            Control::Changed
+        }
+        ct_event!(keycode press PageDown) => {
+            state.step(page);
+            Control::Changed
+        }
+        ct_event!(keycode press PageUp) => {
+            state.step(-page);
+            Control::Changed
+        }
+        ct_event!(key press 'g') | ct_event!(keycode press Home) => {
+            state.cursor = 0;
+            Control::Changed
+        }
+        ct_event!(key press 'G')
+        | ct_event!(key press SHIFT-'G')
+        | ct_event!(keycode press End) => {
+            state.cursor = state.rows.len().saturating_sub(1);
+            Control::Changed
+        }
+        // The view is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use russh_sftp::protocol::{FileAttributes, FileType};
+
+    use super::*;
+
+    fn tree(entries: &[(&str, FileType, u64, u32)]) -> HashMap<String, FileEntry> {
+        entries
+            .iter()
+            .map(|(path, type_, size, mtime)| {
+                let attributes = FileAttributes {
+                    size: Some(*size),
+                    mtime: Some(*mtime),
+                    ..FileAttributes::empty()
+                };
+                let entry = FileEntry::from_file(format!("/root/{}", path), *type_, attributes);
+                (path.to_string(), entry)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify() {
+        let left = tree(&[
+            ("a", FileType::Dir, 0, 1),
+            ("a/same", FileType::File, 10, 1),
+            ("a/size", FileType::File, 10, 1),
+            ("b", FileType::Dir, 0, 1),
+            ("b/same", FileType::File, 5, 1),
+            ("left", FileType::File, 1, 1),
+            ("kind", FileType::File, 1, 1),
+        ]);
+        let right = tree(&[
+            ("a", FileType::Dir, 0, 2),
+            ("a/same", FileType::File, 10, 1),
+            ("a/size", FileType::File, 11, 1),
+            ("b", FileType::Dir, 0, 2),
+            ("b/same", FileType::File, 5, 1),
+            ("kind", FileType::Dir, 0, 1),
+            ("right", FileType::Dir, 0, 1),
+            ("right/new", FileType::File, 3, 1),
+        ]);
+        let entries = classify(left, right);
+        let status = |path: &str| {
+            entries
+                .iter()
+                .find(|entry| entry.path == path)
+                .map(|entry| entry.status)
+        };
+        assert_eq!(status("a"), Some(Status::Different));
+        assert_eq!(status("a/same"), Some(Status::Identical));
+        assert_eq!(status("a/size"), Some(Status::Different));
+        assert_eq!(status("b"), Some(Status::Identical));
+        assert_eq!(status("left"), Some(Status::OnlyLeft));
+        assert_eq!(status("kind"), Some(Status::Different));
+        assert_eq!(status("right"), Some(Status::OnlyRight));
+        assert_eq!(status("right/new"), Some(Status::OnlyRight));
+        // Children follow their parents
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "a",
+                "a/same",
+                "a/size",
+                "b",
+                "b/same",
+                "kind",
+                "left",
+                "right",
+                "right/new"
+            ]
+        );
+
+        // Identical entries are hidden, their parents stay to hold what is
+        // shown below them
+        let shown = |filter: &str, collapsed: &HashSet<String>| {
+            visible(&entries, [true, true, true, false], filter, collapsed)
+                .into_iter()
+                .map(|idx| entries[idx].path.as_str())
+                .collect::<Vec<_>>()
+        };
+        let none = HashSet::new();
+        assert_eq!(
+            shown("", &none),
+            ["a", "a/size", "kind", "left", "right", "right/new"]
+        );
+        assert_eq!(shown("NEW", &none), ["right", "right/new"]);
+        let collapsed = HashSet::from(["right".to_string()]);
+        assert_eq!(
+            shown("", &collapsed),
+            ["a", "a/size", "kind", "left", "right"]
+        );
+    }
+}
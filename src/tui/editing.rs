@@ -0,0 +1,410 @@
+//! Saving files edited in the external editor without losing changes made
+//! to them on the remote side meanwhile.
+use std::fmt::{self, Debug, Formatter};
+use std::io::Write;
+use std::process::Command;
+use std::sync::Arc;
+
+use color_eyre::Report as Error;
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use rat_salsa::{Control, SalsaContext};
+use rat_theme3::Palette;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+use russh_sftp::protocol::FileAttributes;
+use sha2::{Digest, Sha256};
+
+use super::paging::{Encoding, SNIFF_LEN};
+use super::{AppEvent, Global};
+use crate::config::EditorSettings;
+use crate::diff;
+use crate::remote_fs::RemoteFs;
+use crate::transfer::{self, rate_limit::Throttle};
+
+/// What a file looked like when it was read, to tell whether it changed
+/// since.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub path: String,
+    pub attributes: FileAttributes,
+    /// Hex encoded SHA-256 of the contents.
+    pub hash: String,
+    pub text: String,
+    /// Whether the contents are UTF-8, `text` is lossy otherwise.
+    pub utf8: bool,
+}
+
+impl Snapshot {
+    pub async fn take(fs: &dyn RemoteFs, path: &str) -> Result<Self> {
+        let attributes = fs.stat(path).await?;
+        let bytes = fs.read(path).await?;
+        let hash = format!("{:x}", Sha256::digest(&bytes));
+        let (text, utf8) = match String::from_utf8(bytes) {
+            Ok(text) => (text, true),
+            Err(err) => (String::from_utf8_lossy(err.as_bytes()).into_owned(), false),
+        };
+        Ok(Self {
+            path: path.to_string(),
+            attributes,
+            hash,
+            text,
+            utf8,
+        })
+    }
+
+    /// Whether both snapshots show the same version of the file.
+    pub fn matches(&self, other: &Snapshot) -> bool {
+        self.attributes.size == other.attributes.size
+            && self.attributes.mtime == other.attributes.mtime
+            && self.hash == other.hash
+    }
+}
+
+/// Refuses files that would not survive being edited as UTF-8 text, from
+/// their first bytes.
+pub async fn check_editable(fs: &dyn RemoteFs, path: &str) -> Result<()> {
+    let head = fs.read_at(path, 0, SNIFF_LEN as u64).await?;
+    match Encoding::detect(&head) {
+        Some(Encoding::Utf8) => Ok(()),
+        Some(encoding) => bail!(
+            "{} is {} text, only UTF-8 files can be edited",
+            path,
+            encoding.label()
+        ),
+        None => bail!("{} is a binary file and can't be edited as text", path),
+    }
+}
+
+/// Lets the user edit `text` in a temporary file named `name`, so the
+/// editor can tell its type. Blocks until the editor exits.
+pub fn run_editor(name: &str, text: &str, settings: &EditorSettings) -> Result<String> {
+    let dir = tempfile::Builder::new().prefix("filessh-").tempdir()?;
+    let name = if name.is_empty() { "file" } else { name };
+    let path = dir.path().join(name);
+    std::fs::write(&path, text)?;
+    match settings.command_for(name) {
+        Some(command) => {
+            let mut args = shell_words::split(command)
+                .wrap_err_with(|| format!("Invalid editor command {:?}", command))?;
+            if args.is_empty() {
+                bail!("The editor command for {} is empty", name);
+            }
+            let program = args.remove(0);
+            let status = Command::new(&program)
+                .args(args)
+                .arg(&path)
+                .status()
+                .wrap_err_with(|| format!("Could not run the editor {}", program))?;
+            if !status.success() {
+                bail!("The editor {} exited with {}", command, status);
+            }
+        }
+        // $VISUAL, $EDITOR or whatever editor is installed
+        None => edit::edit_file(&path).wrap_err("Could not run the editor")?,
+    }
+    String::from_utf8(std::fs::read(&path)?)
+        .map_err(|_| eyre!("The edited {} is no longer UTF-8 text", name))
+}
+
+/// A file to open in the editor with `template`, saved over `snapshot`.
+#[derive(Clone)]
+pub struct Edit {
+    pub fs: Arc<dyn RemoteFs>,
+    pub snapshot: Snapshot,
+    pub template: String,
+}
+
+impl Debug for Edit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Edit")
+            .field("snapshot", &self.snapshot)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Edits of `base` that could not be saved since the file became `current`.
+#[derive(Clone)]
+pub struct Conflict {
+    pub fs: Arc<dyn RemoteFs>,
+    pub base: Snapshot,
+    pub current: Snapshot,
+    pub edited: String,
+}
+
+impl Debug for Conflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conflict")
+            .field("base", &self.base)
+            .field("current", &self.current)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub enum Saved {
+    /// Saved, with the path of the backup of the old version if one was kept.
+    Done(Option<String>),
+    /// Not saved, the file is no longer the one that was edited.
+    Conflict(Snapshot),
+}
+
+/// Saves `edited` over the file of `base` if it has not changed since.
+pub async fn save(
+    fs: &dyn RemoteFs,
+    base: &Snapshot,
+    edited: &str,
+    throttle: &Throttle,
+) -> Result<Saved> {
+    let current = Snapshot::take(fs, &base.path).await?;
+    if !base.matches(&current) {
+        return Ok(Saved::Conflict(current));
+    }
+    let backup = transfer::replace_file(
+        fs,
+        &base.path,
+        edited.as_bytes(),
+        &base.attributes,
+        throttle,
+    )
+    .await?;
+    Ok(Saved::Done(backup))
+}
+
+/// Saves `edited` in the background, asking what to do on a conflict.
+pub fn spawn_save(fs: Arc<dyn RemoteFs>, base: Snapshot, edited: String, ctx: &mut Global) {
+    let throttle = ctx.cfg.job_throttle();
+    ctx.spawn_tab_async(async move |chan| {
+        let backup = match save(&*fs, &base, &edited, &throttle).await? {
+            Saved::Done(backup) => backup,
+            Saved::Conflict(current) => {
+                return Ok(Control::Event(AppEvent::EditConflict(Box::new(Conflict {
+                    fs,
+                    base,
+                    current,
+                    edited,
+                }))));
+            }
+        };
+        chan.send(Ok(Control::Event(AppEvent::Edited(base.path.clone()))))
+            .await?;
+        let status = match backup {
+            Some(backup) => format!("Saved {}, the old version is at {}", base.path, backup),
+            None => format!("Saved {}", base.path),
+        };
+        Ok(Control::Event(AppEvent::Status(1, status)))
+    });
+}
+
+/// Asks what to do with edits of a file that changed while editing.
+#[derive(Default)]
+pub struct ConflictDialog {
+    pub active: bool,
+    conflict: Option<Conflict>,
+    /// Shows the edits against the current file rather than the remote
+    /// changes.
+    edits: bool,
+    lines: Vec<String>,
+    scroll: u16,
+}
+
+impl ConflictDialog {
+    pub fn open(&mut self, conflict: Conflict) {
+        *self = Self {
+            active: true,
+            conflict: Some(conflict),
+            ..Default::default()
+        };
+        self.show(false);
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+        self.conflict = None;
+    }
+
+    fn show(&mut self, edits: bool) {
+        let Some(conflict) = &self.conflict else {
+            return;
+        };
+        self.edits = edits;
+        self.scroll = 0;
+        self.lines = if edits {
+            diff::unified(&conflict.current.text, &conflict.edited)
+        } else {
+            diff::unified(&conflict.base.text, &conflict.current.text)
+        };
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut ConflictDialog, ctx: &mut Global) {
+    let Some(conflict) = &state.conflict else {
+        return;
+    };
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(10),
+        Constraint::Percentage(10),
+        Constraint::Percentage(10),
+        Constraint::Percentage(10),
+    );
+    let palette = ctx.theme.palette();
+    let base = ctx.theme.popup_base();
+    let mut lines = vec![
+        Line::from(format!(
+            "{} changed while it was edited.",
+            conflict.base.path
+        ))
+        .style(base.add_modifier(Modifier::BOLD)),
+        Line::from(if state.edits {
+            "Your edits against the current file:"
+        } else {
+            "Changes made to the file meanwhile:"
+        }),
+        Line::from(""),
+    ];
+    lines.extend(state.lines.iter().map(|line| {
+        let style = diff_style(line, palette).unwrap_or(base);
+        Line::styled(line.as_str(), style)
+    }));
+
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .style(base)
+        .scroll((state.scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title("Conflict")
+                .title_bottom(
+                    "<d> Toggle diff  <m> Merge  <o> Overwrite  <Esc> Keep edits locally",
+                ),
+        )
+        .render(area, buf);
+}
+
+/// Colors a line of a unified diff.
+pub fn diff_style(line: &str, palette: &Palette) -> Option<Style> {
+    let fg = |colors: &[ratatui::style::Color; 8]| Style::default().fg(colors[Palette::BRIGHT_2]);
+    match line.chars().next() {
+        Some('+') => Some(fg(&palette.green)),
+        Some('-') => Some(fg(&palette.red)),
+        Some('@') => Some(fg(&palette.cyan)),
+        _ => None,
+    }
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut ConflictDialog,
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    let r = match event {
+        ct_event!(key press 'd') => {
+            state.show(!state.edits);
+            Control::Changed
+        }
+        ct_event!(key press 'j') | ct_event!(keycode press Down) => {
+            state.scroll = state.scroll.saturating_add(1);
+            Control::Changed
+        }
+        ct_event!(key press 'k') | ct_event!(keycode press Up) => {
+            state.scroll = state.scroll.saturating_sub(1);
+            Control::Changed
+        }
+        ct_event!(keycode press PageDown) => {
+            state.scroll = state.scroll.saturating_add(20);
+            Control::Changed
+        }
+        ct_event!(keycode press PageUp) => {
+            state.scroll = state.scroll.saturating_sub(20);
+            Control::Changed
+        }
+        ct_event!(key press 'm') => {
+            let Some(conflict) = state.conflict.take() else {
+                return Ok(Control::Unchanged);
+            };
+            state.close();
+            let merged = diff::merge(
+                &conflict.base.text,
+                &conflict.edited,
+                &conflict.current.text,
+                ("edited", "remote"),
+            );
+            if merged.conflicts > 0 {
+                ctx.queue_event(AppEvent::Status(
+                    1,
+                    format!("{} conflicting changes are marked", merged.conflicts),
+                ));
+            }
+            // The merge is edited again and saved over the current file
+            Control::Event(AppEvent::EditContent(Box::new(Edit {
+                fs: conflict.fs,
+                snapshot: conflict.current,
+                template: merged.text,
+            })))
+        }
+        ct_event!(key press 'o') => {
+            if let Some(conflict) = state.conflict.take() {
+                spawn_save(conflict.fs, conflict.current, conflict.edited, ctx);
+            }
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Esc) => {
+            if let Some(conflict) = state.conflict.take() {
+                // Nothing is saved, but the edits are not thrown away either
+                // A fresh file only the user can read, never one that exists already
+                let name = conflict.base.path.rsplit('/').next().unwrap_or_default();
+                let mut file = tempfile::Builder::new()
+                    .prefix(&format!("{}.", name))
+                    .suffix(".filessh-edit")
+                    .tempfile()?;
+                file.write_all(conflict.edited.as_bytes())?;
+                let (_, path) = file.keep()?;
+                ctx.queue_event(AppEvent::Status(
+                    1,
+                    format!("Not saved, the edits are in {}", path.display()),
+                ));
+            }
+            state.close();
+            Control::Changed
+        }
+        // The dialog is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote_fs::LocalFs;
+
+    #[tokio::test]
+    async fn test_check_editable() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = |name: &str, bytes: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, bytes).unwrap();
+            path.display().to_string()
+        };
+        let text = file("notes.md", "héllo\n".as_bytes());
+        let binary = file("app.bin", b"\x7fELF\x02\x01\x01\x00\x00\x00");
+        let latin1 = file("old.txt", b"caf\xe9\n");
+
+        assert!(check_editable(&LocalFs, &text).await.is_ok());
+        let err = check_editable(&LocalFs, &binary).await.unwrap_err();
+        assert!(err.to_string().contains("binary"));
+        let err = check_editable(&LocalFs, &latin1).await.unwrap_err();
+        assert!(err.to_string().contains("Latin-1"));
+    }
+}
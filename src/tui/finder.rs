@@ -0,0 +1,460 @@
+//! Ctrl-P finder jumping to any entry below the directory of a pane.
+//!
+//! The tree is indexed in the background and the index is kept per host and
+//! root, so opening the finder again shows the last index right away while a
+//! fresh walk streams in what changed. The walk stops when the finder closes.
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use rat_salsa::{Control, SalsaContext};
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+use tokio::sync::mpsc;
+use tokio::task::AbortHandle;
+
+use super::pane::Pane;
+use super::{AppEvent, Global};
+use crate::files::{FileEntry, JoinablePaths};
+use crate::par_dir_traversal::{WalkParallel, WalkState};
+use crate::remote_fs::RemoteFs;
+
+/// How many of the best matches are listed.
+const MAX_RESULTS: usize = 200;
+/// A running walk reports what it found in batches of at most this many
+/// paths, or whatever it has after [`BATCH_INTERVAL`].
+const BATCH_SIZE: usize = 2000;
+const BATCH_INTERVAL: Duration = Duration::from_millis(250);
+/// How deep below the root the walk descends.
+const MAX_DEPTH: usize = 16;
+/// The walk stops after this many entries.
+const MAX_ENTRIES: usize = 200_000;
+
+/// Host label and root directory of an index.
+pub type IndexKey = (String, String);
+
+/// Paths below a root, relative to it. Directories end with a slash.
+#[derive(Debug, Default)]
+pub struct Index {
+    pub paths: Vec<String>,
+    known: HashSet<String>,
+    /// What the walk in progress came across so far.
+    seen: HashSet<String>,
+}
+
+impl Index {
+    /// Adds what a running walk found.
+    pub fn extend(&mut self, batch: Vec<String>) {
+        for path in batch {
+            if self.known.insert(path.clone()) {
+                self.paths.push(path.clone());
+            }
+            self.seen.insert(path);
+        }
+    }
+
+    /// Forgets the paths the finished walk didn't come across.
+    pub fn finish(&mut self) {
+        let seen = std::mem::take(&mut self.seen);
+        self.paths.retain(|path| seen.contains(path));
+        self.known = seen;
+    }
+}
+
+/// A path of the shown index matching the query.
+#[derive(Debug, Clone)]
+pub struct Match {
+    score: i64,
+    pub idx: usize,
+    /// Positions of the matched chars.
+    pub positions: Vec<usize>,
+}
+
+#[derive(Default)]
+pub struct Finder {
+    pub active: bool,
+    /// Pane of the shown tab that jumps to the picked entry.
+    pub pane: usize,
+    key: IndexKey,
+    indexes: HashMap<IndexKey, Index>,
+    /// Indexes being walked right now.
+    walking: HashMap<IndexKey, AbortHandle>,
+    query: String,
+    /// Best first.
+    matches: Vec<Match>,
+    list_state: ListState,
+    matcher: SkimMatcherV2,
+}
+
+impl Finder {
+    /// Shows the finder for the directory of `pane` and refreshes its index
+    /// in the background.
+    pub fn open(&mut self, idx: usize, pane: &Pane, ctx: &Global) {
+        self.key = (pane.label.clone(), pane.current_path.clone());
+        self.pane = idx;
+        self.query.clear();
+        self.list_state.select(Some(0));
+        self.active = true;
+        if !self.walking.contains_key(&self.key) {
+            self.indexes
+                .entry(self.key.clone())
+                .or_default()
+                .seen
+                .clear();
+            let walk = index(self.key.clone(), Arc::clone(&pane.fs), ctx);
+            self.walking.insert(self.key.clone(), walk);
+        }
+        self.rank();
+    }
+
+    /// Hides the finder and stops the walks, keeping what they found.
+    pub fn close(&mut self) {
+        self.active = false;
+        for (_, walk) in self.walking.drain() {
+            walk.abort();
+        }
+    }
+
+    /// Takes in a batch of the walk of `key`.
+    pub fn indexed(&mut self, key: &IndexKey, batch: Vec<String>, done: bool) {
+        let index = self.indexes.entry(key.clone()).or_default();
+        let from = index.paths.len();
+        index.extend(batch);
+        if done {
+            index.finish();
+            self.walking.remove(key);
+        }
+        if self.active && *key == self.key {
+            // Finishing drops paths and moves the rest, new ones only get appended
+            if done {
+                self.rank();
+            } else {
+                self.rank_from(from);
+            }
+        }
+    }
+
+    fn paths(&self) -> &[String] {
+        self.indexes
+            .get(&self.key)
+            .map_or(&[][..], |index| index.paths.as_slice())
+    }
+
+    fn rank(&mut self) {
+        self.matches = rank(&self.matcher, self.paths(), &self.query);
+        self.clamp_selection();
+    }
+
+    /// Merges the matches among the paths from position `from` on into the
+    /// current ones.
+    fn rank_from(&mut self, from: usize) {
+        let mut matches = std::mem::take(&mut self.matches);
+        let paths = self.paths();
+        let new = score(&self.matcher, paths, from, &self.query);
+        merge(paths, &mut matches, new);
+        self.matches = matches;
+        self.clamp_selection();
+    }
+
+    fn clamp_selection(&mut self) {
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.list_state
+            .select((!self.matches.is_empty()).then(|| selected.min(self.matches.len() - 1)));
+    }
+
+    /// The absolute path of the selected match.
+    fn selected(&self) -> Option<(String, bool)> {
+        let found = self.matches.get(self.list_state.selected()?)?;
+        let path = &self.indexes.get(&self.key)?.paths[found.idx];
+        let is_dir = path.ends_with('/');
+        Some((self.key.1.join(path.trim_end_matches('/')), is_dir))
+    }
+}
+
+/// Scores the paths from position `from` on against `query`.
+fn score(matcher: &SkimMatcherV2, paths: &[String], from: usize, query: &str) -> Vec<Match> {
+    paths
+        .iter()
+        .enumerate()
+        .skip(from)
+        .filter_map(|(idx, path)| {
+            let (score, positions) = match query.is_empty() {
+                true => (0, Vec::new()),
+                false => matcher.fuzzy_indices(path, query)?,
+            };
+            Some(Match {
+                score,
+                idx,
+                positions,
+            })
+        })
+        .collect()
+}
+
+/// Merges `new` into the sorted `matches`, keeping the best ones. Ties go to
+/// the shorter path.
+fn merge(paths: &[String], matches: &mut Vec<Match>, new: Vec<Match>) {
+    let order = |a: &Match, b: &Match| -> Ordering {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| paths[a.idx].len().cmp(&paths[b.idx].len()))
+            .then_with(|| a.idx.cmp(&b.idx))
+    };
+    matches.extend(new);
+    matches.sort_by(order);
+    matches.truncate(MAX_RESULTS);
+}
+
+/// The best matches of `query` among `paths`.
+pub fn rank(matcher: &SkimMatcherV2, paths: &[String], query: &str) -> Vec<Match> {
+    let mut matches = Vec::new();
+    merge(paths, &mut matches, score(matcher, paths, 0, query));
+    matches
+}
+
+/// `entry` relative to `root`, `None` for the root itself.
+fn relative(root: &str, entry: &FileEntry) -> Option<String> {
+    let path = entry.name.strip_prefix(root)?.trim_start_matches('/');
+    if path.is_empty() {
+        return None;
+    }
+    Some(if entry.is_dir() {
+        format!("{}/", path)
+    } else {
+        path.to_string()
+    })
+}
+
+/// Walks the root of `key`, sending what it finds back in batches, until
+/// [`MAX_ENTRIES`] were found or the returned handle aborts it.
+fn index(key: IndexKey, fs: Arc<dyn RemoteFs>, ctx: &Global) -> AbortHandle {
+    let (walk, _) = ctx.spawn_async_ext(move |chan| async move {
+        let root = key.1.clone();
+        let walker = WalkParallel {
+            filter: Arc::new(|_| true),
+            path: root.clone().into(),
+            max_depth: Some(MAX_DEPTH),
+            min_depth: None,
+            threads: 4,
+            fs,
+        };
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let walk = walker.run(move || {
+            let tx = tx.clone();
+            Box::new(move |entry: Result<FileEntry>| {
+                // The workers outlive an aborted task, stop them once nobody listens
+                if let Ok(entry) = entry
+                    && tx.send(entry).is_err()
+                {
+                    return WalkState::Quit;
+                }
+                WalkState::Continue
+            })
+        });
+        let batch_key = key.clone();
+        let forward = async move {
+            let mut batch = Vec::new();
+            let mut flushed = Instant::now();
+            let mut found = 0;
+            while let Some(entry) = rx.recv().await {
+                found += 1;
+                if found > MAX_ENTRIES {
+                    break;
+                }
+                batch.extend(relative(&root, &entry));
+                if batch.len() >= BATCH_SIZE || flushed.elapsed() >= BATCH_INTERVAL {
+                    let batch = std::mem::take(&mut batch);
+                    chan.send(Ok(Control::Event(AppEvent::Indexed(
+                        batch_key.clone(),
+                        batch,
+                        false,
+                    ))))
+                    .await?;
+                    flushed = Instant::now();
+                }
+            }
+            Ok::<_, Error>(batch)
+        };
+        let ((), batch) = tokio::join!(walk, forward);
+        Ok(Control::Event(AppEvent::Indexed(key, batch?, true)))
+    });
+    walk
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut Finder, ctx: &mut Global) {
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+        Constraint::Percentage(15),
+        Constraint::Percentage(15),
+    );
+    let (root, total) = (
+        &state.key.1,
+        state
+            .indexes
+            .get(&state.key)
+            .map_or(0, |index| index.paths.len()),
+    );
+    let progress = if state.walking.contains_key(&state.key) {
+        ", indexing..."
+    } else {
+        ""
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .border_style(ctx.theme.popup_border())
+        .style(ctx.theme.popup_base())
+        .title(format!("Find in {}", root))
+        .title_bottom(format!(
+            "{}/{}{}  <Enter> Go  <Esc> Cancel",
+            state.matches.len(),
+            total,
+            progress
+        ));
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.render(area, buf);
+
+    let [query_area, list_area] =
+        Layout::vertical([Constraint::Length(2), Constraint::Fill(1)]).areas(inner);
+    Line::from(vec![
+        Span::styled("> ", ctx.theme.popup_base().add_modifier(Modifier::BOLD)),
+        Span::from(state.query.as_str()),
+        Span::styled(" ", ctx.theme.text_focus()),
+    ])
+    .render(query_area, buf);
+
+    let highlight = Style::default()
+        .fg(ctx.theme.palette().yellow[2])
+        .add_modifier(Modifier::BOLD);
+    let paths = state.paths();
+    let items: Vec<ListItem> = state
+        .matches
+        .iter()
+        .map(|found| {
+            let spans: Vec<Span> = paths[found.idx]
+                .chars()
+                .enumerate()
+                .map(|(pos, c)| {
+                    if found.positions.contains(&pos) {
+                        Span::styled(c.to_string(), highlight)
+                    } else {
+                        Span::from(c.to_string())
+                    }
+                })
+                .collect();
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let list = List::new(items)
+        .style(ctx.theme.popup_base())
+        .highlight_style(ctx.theme.select())
+        .highlight_symbol("> ");
+    StatefulWidget::render(list, list_area, buf, &mut state.list_state);
+}
+
+pub fn event(event: &Event, state: &mut Finder) -> Result<Control<AppEvent>, Error> {
+    let r = match event {
+        ct_event!(keycode press Esc) => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Down) | ct_event!(key press CONTROL-'n') => {
+            state.list_state.select_next();
+            Control::Changed
+        }
+        ct_event!(keycode press Up) | ct_event!(key press CONTROL-'p') => {
+            state.list_state.select_previous();
+            Control::Changed
+        }
+        ct_event!(keycode press Enter) => match state.selected() {
+            Some((path, true)) => {
+                state.close();
+                Control::Event(AppEvent::GoTo(state.pane, path))
+            }
+            Some((path, false)) => {
+                state.close();
+                Control::Event(AppEvent::Reveal(state.pane, path))
+            }
+            None => Control::Continue,
+        },
+        ct_event!(keycode press Backspace) => {
+            state.query.pop();
+            state.rank();
+            Control::Changed
+        }
+        ct_event!(key press c) | ct_event!(key press SHIFT-c) => {
+            state.query.push(*c);
+            state.list_state.select(Some(0));
+            state.rank();
+            Control::Changed
+        }
+        // The popup is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_and_rank() {
+        let mut index = Index::default();
+        index.extend(vec!["src/".into(), "src/main.rs".into(), "old.txt".into()]);
+        index.finish();
+
+        // A second walk adds new paths and drops the ones it didn't see
+        index.extend(vec!["src/".into(), "src/main.rs".into()]);
+        index.extend(vec!["src/tui/".into(), "src/tui/mod.rs".into()]);
+        assert_eq!(index.paths.len(), 5);
+        index.finish();
+        assert_eq!(
+            index.paths,
+            ["src/", "src/main.rs", "src/tui/", "src/tui/mod.rs"]
+        );
+
+        let matcher = SkimMatcherV2::default();
+        let best = |query: &str| -> Vec<String> {
+            rank(&matcher, &index.paths, query)
+                .iter()
+                .map(|found| index.paths[found.idx].clone())
+                .collect()
+        };
+        assert_eq!(best("mod"), ["src/tui/mod.rs"]);
+        // Equal scores go to the shorter path
+        assert_eq!(best("src")[0], "src/");
+        assert_eq!(rank(&matcher, &index.paths, "mod")[0].positions, [8, 9, 10]);
+        assert!(rank(&matcher, &index.paths, "xyz").is_empty());
+
+        // Ranking a new batch on top gives the same result as ranking it all
+        let mut matches = rank(&matcher, &index.paths[..2], "rs");
+        merge(
+            &index.paths,
+            &mut matches,
+            score(&matcher, &index.paths, 2, "rs"),
+        );
+        let ranked = |matches: &[Match]| matches.iter().map(|m| m.idx).collect::<Vec<_>>();
+        assert_eq!(
+            ranked(&matches),
+            ranked(&rank(&matcher, &index.paths, "rs"))
+        );
+    }
+}
@@ -0,0 +1,300 @@
+//! Hex and ASCII dump of binary files in the content view, and guessing what
+//! a file is from its first bytes.
+//!
+//! Only a window of the file is held, the preview reads the one around the
+//! shown rows when they leave it.
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::Rect;
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, BorderType, Clear, Padding, Widget};
+
+use super::paging::PAGE_LEN;
+use super::{AppEvent, Global};
+
+/// Magic numbers at the start of a file, with an offset for formats that
+/// keep it further in.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"\x7fELF", "ELF executable"),
+    (0, b"\x1f\x8b", "gzip archive"),
+    (0, b"BZh", "bzip2 archive"),
+    (0, b"\xfd7zXZ\x00", "xz archive"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd archive"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7-Zip archive"),
+    (0, b"Rar!\x1a\x07", "RAR archive"),
+    (0, b"PK\x03\x04", "zip archive"),
+    (257, b"ustar", "tar archive"),
+    (0, b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (0, b"\xff\xd8\xff", "JPEG image"),
+    (0, b"GIF8", "GIF image"),
+    (0, b"BM", "BMP image"),
+    (0, b"%PDF-", "PDF document"),
+    (0, b"SQLite format 3\x00", "SQLite database"),
+    (0, b"\x00asm", "WebAssembly module"),
+    (
+        0,
+        b"\xca\xfe\xba\xbe",
+        "Java class or Mach-O universal binary",
+    ),
+    (0, b"\xcf\xfa\xed\xfe", "Mach-O executable"),
+    (0, b"MZ", "Windows executable"),
+    (0, b"OggS", "Ogg media"),
+    (0, b"fLaC", "FLAC audio"),
+    (0, b"ID3", "MP3 audio"),
+    (0, b"RIFF", "RIFF media (WAV, AVI, WebP)"),
+    (0, b"-----BEGIN ", "PEM data"),
+    (0, b"#!", "script"),
+];
+
+/// A guess of what a file is from its first bytes, `None` for plain text
+/// and unknown formats.
+pub fn sniff(head: &[u8]) -> Option<&'static str> {
+    MAGIC
+        .iter()
+        .find(|(offset, magic, _)| head.get(*offset..offset + magic.len()) == Some(*magic))
+        .map(|(_, _, name)| *name)
+}
+
+#[derive(Debug, Default)]
+pub struct HexView {
+    /// Offset of the first byte of `bytes` in the file.
+    base: u64,
+    /// The window of the file that is loaded.
+    bytes: Vec<u8>,
+    pub size: u64,
+    pub file_type: Option<&'static str>,
+    cursor: u64,
+    /// First row shown.
+    top: u64,
+    /// Bytes per row and rows shown, from the last render.
+    per_row: usize,
+    rows: usize,
+    /// The offset typed after `:`, `None` unless jumping.
+    jump: Option<String>,
+}
+
+impl HexView {
+    /// A dump of the file of `size` bytes starting with `head`.
+    pub fn new(head: Vec<u8>, size: u64) -> Self {
+        Self {
+            file_type: sniff(&head),
+            bytes: head,
+            size,
+            per_row: 16,
+            rows: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Replaces the loaded window with `bytes` read at `base`.
+    pub fn load(&mut self, base: u64, bytes: Vec<u8>, size: u64) {
+        self.base = base;
+        self.bytes = bytes;
+        self.size = size;
+        self.move_to(self.cursor);
+    }
+
+    fn byte(&self, offset: u64) -> Option<u8> {
+        let idx = offset.checked_sub(self.base)?;
+        self.bytes.get(usize::try_from(idx).ok()?).copied()
+    }
+
+    /// Where to read a new window if some of the shown rows aren't loaded.
+    pub fn wanted(&self) -> Option<u64> {
+        let per_row = self.per_row as u64;
+        let first = self.top * per_row;
+        let last = ((self.top + self.rows as u64) * per_row).min(self.size);
+        let loaded = self.base..=self.base + self.bytes.len() as u64;
+        if first >= last || (loaded.contains(&first) && loaded.contains(&last)) {
+            return None;
+        }
+        Some(first / PAGE_LEN * PAGE_LEN)
+    }
+
+    fn move_to(&mut self, offset: u64) {
+        self.cursor = offset.min(self.size.saturating_sub(1));
+    }
+
+    fn move_by(&mut self, delta: i64) {
+        self.move_to(self.cursor.saturating_add_signed(delta));
+    }
+}
+
+/// An offset typed by the user, in hex with a `0x` prefix or decimal.
+pub fn parse_offset(input: &str) -> Option<u64> {
+    let input = input.trim();
+    match input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => input.parse().ok(),
+    }
+}
+
+/// Widest row of `width` columns: offset, hex bytes in groups of eight and
+/// the ASCII column.
+fn bytes_per_row(width: u16) -> usize {
+    [16, 8, 4]
+        .into_iter()
+        .find(|n| 10 + n * 3 + n / 8 + n + 2 <= width as usize)
+        .unwrap_or(4)
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut HexView, ctx: &mut Global) {
+    let title = match state.file_type {
+        Some(file_type) => format!("[2] Hex: {}", file_type),
+        None => "[2] Hex".to_string(),
+    };
+    let footer = match &state.jump {
+        Some(input) => format!("Go to offset: {}_", input),
+        None => format!("{:#x} of {:#x}  <:> Go to Offset", state.cursor, state.size),
+    };
+    let block = Block::bordered()
+        .border_type(BorderType::Rounded)
+        .title_top(title)
+        .title_bottom(footer)
+        .border_style(ctx.theme.container_border())
+        .padding(Padding::horizontal(1));
+    let inner = block.inner(area);
+    Clear.render(area, buf);
+    block.render(area, buf);
+
+    state.per_row = bytes_per_row(inner.width);
+    state.rows = (inner.height as usize).max(1);
+    let per_row = state.per_row as u64;
+    let row = state.cursor / per_row;
+    if row < state.top {
+        state.top = row;
+    } else if row >= state.top + state.rows as u64 {
+        state.top = row + 1 - state.rows as u64;
+    }
+
+    let offset_style = ctx.theme.line_nr_style().style;
+    let text_style = ctx.theme.paragraph_style().style;
+    let cursor_style = ctx.theme.select();
+    let style = |offset: u64| {
+        if offset == state.cursor {
+            cursor_style
+        } else {
+            Style::default()
+        }
+    };
+    for (line, row_area) in (state.top..).zip(inner.rows()) {
+        let start = line * per_row;
+        if start >= state.size {
+            break;
+        }
+        // Bytes past the end or not read yet are left blank
+        let chunk: Vec<_> = (start..(start + per_row).min(state.size))
+            .map(|offset| state.byte(offset))
+            .collect();
+        let mut spans = vec![Span::styled(format!("{:08x}  ", start), offset_style)];
+        for idx in 0..state.per_row {
+            let text = match chunk.get(idx).copied().flatten() {
+                Some(byte) => format!("{:02x}", byte),
+                None => "  ".to_string(),
+            };
+            spans.push(Span::styled(text, style(start + idx as u64)));
+            spans.push(Span::from(if idx % 8 == 7 { "  " } else { " " }));
+        }
+        spans.push(Span::from("|"));
+        for (idx, byte) in chunk.iter().enumerate() {
+            let c = match byte {
+                Some(byte) if byte.is_ascii_graphic() || *byte == b' ' => *byte as char,
+                Some(_) => '.',
+                None => ' ',
+            };
+            spans.push(Span::styled(c.to_string(), style(start + idx as u64)));
+        }
+        spans.push(Span::from("|"));
+        Line::from(spans).style(text_style).render(row_area, buf);
+    }
+}
+
+pub fn event(event: &Event, state: &mut HexView) -> Control<AppEvent> {
+    if let Some(input) = &mut state.jump {
+        return match event {
+            ct_event!(keycode press Esc) => {
+                state.jump = None;
+                Control::Changed
+            }
+            ct_event!(keycode press Enter) => {
+                if let Some(offset) = parse_offset(input) {
+                    state.move_to(offset);
+                }
+                state.jump = None;
+                Control::Changed
+            }
+            ct_event!(keycode press Backspace) => {
+                input.pop();
+                Control::Changed
+            }
+            ct_event!(key press c) | ct_event!(key press SHIFT-c)
+                if c.is_ascii_hexdigit() || *c == 'x' =>
+            {
+                input.push(*c);
+                Control::Changed
+            }
+            // The prompt takes every key
+            _ => Control::Unchanged,
+        };
+    }
+    let (per_row, page) = (state.per_row as i64, (state.per_row * state.rows) as i64);
+    match event {
+        ct_event!(keycode press Left) | ct_event!(key press 'h') => state.move_by(-1),
+        ct_event!(keycode press Right) | ct_event!(key press 'l') => state.move_by(1),
+        ct_event!(keycode press Up) | ct_event!(key press 'k') => state.move_by(-per_row),
+        ct_event!(keycode press Down) | ct_event!(key press 'j') => state.move_by(per_row),
+        ct_event!(keycode press PageUp) => state.move_by(-page),
+        ct_event!(keycode press PageDown) => state.move_by(page),
+        ct_event!(keycode press Home) | ct_event!(key press 'g') => state.move_to(0),
+        ct_event!(keycode press End) | ct_event!(key press SHIFT-'G') => state.move_to(u64::MAX),
+        ct_event!(key press ':') => state.jump = Some(String::new()),
+        _ => return Control::Continue,
+    }
+    Control::Changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_and_offsets() {
+        assert_eq!(sniff(b"\x7fELF\x02\x01\x01"), Some("ELF executable"));
+        assert_eq!(sniff(b"\x89PNG\r\n\x1a\n\0\0"), Some("PNG image"));
+        let mut tar = vec![0; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniff(&tar), Some("tar archive"));
+        assert_eq!(sniff(b"hello"), None);
+
+        assert_eq!(parse_offset("0x1f0"), Some(0x1f0));
+        assert_eq!(parse_offset("496"), Some(496));
+        assert_eq!(parse_offset("zz"), None);
+
+        let mut view = HexView::new(vec![0; 40], 40);
+        view.move_by(-1);
+        assert_eq!(view.cursor, 0);
+        view.move_to(u64::MAX);
+        assert_eq!(view.cursor, 39);
+        assert_eq!(view.wanted(), None);
+
+        // Rows past the loaded window ask for the page holding them
+        let mut view = HexView::new(vec![0; 64], 1 << 20);
+        view.rows = 4;
+        assert_eq!(view.wanted(), None);
+        view.top = (PAGE_LEN + 32) / 16;
+        assert_eq!(view.wanted(), Some(PAGE_LEN));
+        view.load(PAGE_LEN, vec![0; 2 * PAGE_LEN as usize], 1 << 20);
+        assert_eq!(view.wanted(), None);
+        assert_eq!(view.byte(PAGE_LEN + 1), Some(0));
+        assert_eq!(view.byte(1), None);
+        assert_eq!(bytes_per_row(80), 16);
+        assert_eq!(bytes_per_row(60), 8);
+    }
+}
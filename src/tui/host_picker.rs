@@ -0,0 +1,114 @@
+//! Popup listing the hosts from the ssh config, used to open a connection
+//! in a new tab.
+use std::path::PathBuf;
+
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+
+use super::{AppEvent, Global};
+use crate::cli::ResolvedConnectArgs;
+use crate::ssh_config::{self, Host};
+
+#[derive(Debug, Default)]
+pub struct HostPicker {
+    pub active: bool,
+    pub hosts: Vec<Host>,
+    pub list_state: ListState,
+}
+
+impl HostPicker {
+    /// Reloads the hosts from the ssh config and shows the popup.
+    pub fn open(&mut self) -> Result<()> {
+        self.hosts = ssh_config::read_hosts()?.0;
+        self.list_state
+            .select((!self.hosts.is_empty()).then_some(0));
+        self.active = true;
+        Ok(())
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut HostPicker, ctx: &mut Global) {
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+    );
+    let items: Vec<ListItem> = if state.hosts.is_empty() {
+        vec![ListItem::new("No hosts found in ~/.ssh/config")]
+    } else {
+        state
+            .hosts
+            .iter()
+            .map(|host| {
+                ListItem::new(format!(
+                    "{}  ({}@{}:{})",
+                    host.name, host.user, host.host_name, host.port
+                ))
+            })
+            .collect()
+    };
+    Clear.render(area, buf);
+    let list = List::new(items)
+        .style(ctx.theme.popup_base())
+        .highlight_style(ctx.theme.select())
+        .highlight_symbol("> ")
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title("Connect to")
+                .title_bottom("<Enter> Connect  <Esc> Cancel"),
+        );
+    StatefulWidget::render(list, area, buf, &mut state.list_state);
+}
+
+pub fn event(event: &Event, state: &mut HostPicker) -> Result<Control<AppEvent>, Error> {
+    let r = match event {
+        ct_event!(keycode press Esc) => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Down) | ct_event!(key press 'j') => {
+            state.list_state.select_next();
+            Control::Changed
+        }
+        ct_event!(keycode press Up) | ct_event!(key press 'k') => {
+            state.list_state.select_previous();
+            Control::Changed
+        }
+        ct_event!(keycode press Enter) => {
+            let host = state
+                .list_state
+                .selected()
+                .and_then(|idx| state.hosts.get(idx));
+            match host {
+                Some(host) => {
+                    let cli = ResolvedConnectArgs::from_host(host, PathBuf::from("."))?;
+                    state.close();
+                    Control::Event(AppEvent::Connect(Box::new(cli)))
+                }
+                None => Control::Continue,
+            }
+        }
+        // The popup is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
@@ -1,20 +1,47 @@
+use crate::cli::ResolvedConnectArgs;
+use crate::files::Column;
 use crate::files::FileDataSlice;
 use crate::files::FileEntry;
 use crate::files::JoinablePaths;
 use crate::files::MetadataSlice;
 use crate::files::ProgressDataSlice;
+use crate::files::SortKey;
 use crate::par_dir_traversal::WalkParallel;
 use crate::par_dir_traversal::WalkState;
 use crate::patched_line_gauge::LineGauge;
+use crate::remote_fs;
+use crate::remote_fs::Connection;
+use crate::remote_fs::LocalFs;
+use crate::remote_fs::RemoteFs;
+use crate::remote_fs::SudoFs;
+use crate::remote_fs::sudo;
+use crate::scp;
 use crate::ssh::Session;
+use crate::transfer;
+use crate::transfer::TransferCmd;
+use crate::transfer::TransferSummary;
+use crate::transfer::rate_limit::Throttle;
+use crate::transfer::rate_limit::format_rate;
+use crate::transfer::tar_stream;
 
 use super::AppEvent;
 use super::Global;
+use super::OpenHost;
+use super::TabId;
+use super::bookmarks::Bookmarks;
+use super::completion::{self, Candidate, Completer, Source};
+use super::diff_view;
+use super::dir_compare;
+use super::editing::{self, Edit, Snapshot};
+use super::hex_view;
+use super::pane::Pane;
+use super::preview::{self, Preview};
 
 use color_eyre::Report as Error;
-use color_eyre::eyre;
-use color_eyre::eyre::Result;
-use rat_focus::impl_has_focus;
+use color_eyre::eyre::{Result, WrapErr, bail};
+use rat_focus::FocusBuilder;
+use rat_focus::FocusFlag;
+use rat_focus::HasFocus;
 use rat_focus::match_focus;
 use rat_ftable::Table;
 use rat_ftable::TableState;
@@ -61,13 +88,17 @@ use ratatui::widgets::Block;
 use ratatui::widgets::BorderType;
 use ratatui::widgets::Borders;
 use ratatui::widgets::Clear;
+use ratatui::widgets::List;
+use ratatui::widgets::ListItem;
+use ratatui::widgets::ListState;
 use ratatui::widgets::Padding;
 use ratatui::widgets::StatefulWidget;
 use ratatui::widgets::Widget;
+use ratatui::widgets::Wrap;
 use ratatui::widgets::block;
-use russh_sftp::client::SftpSession;
 use russh_sftp::protocol::FileType;
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::f64;
 use std::io::stdout;
@@ -85,8 +116,8 @@ use throbber_widgets_tui::Throbber;
 use tracing::warn;
 
 use throbber_widgets_tui::ThrobberState;
-use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::oneshot;
 
 use tracing::debug;
 use tracing::{error, info};
@@ -109,17 +140,23 @@ const CHARSET: symbols::line::Set = symbols::line::Set {
 };
 
 pub struct MainUI {
-    pub current_path: String,
-    pub table_state: TableState<RowSelection>,
-    pub current_file_entries: Vec<FileEntry>,
+    pub id: TabId,
+    /// The host this tab is connected to.
+    pub cli: ResolvedConnectArgs,
+    /// The local pane and the remote pane, in that order at startup.
+    pub panes: [Pane; 2],
+    /// Index of the pane keyboard commands apply to.
+    pub active: usize,
+    /// The connected host, for panes switched back from the local disk.
+    pub remote_fs: Arc<dyn RemoteFs>,
     pub input_state: TextInputState,
     pub input_mode: InputMode,
-    pub sftp: Arc<SftpSession>,
     pub session: Arc<AsyncMutex<Session>>,
     pub log_state: TuiWidgetState,
     pub throbber: ThrobberState,
     pub is_downloading: bool,
-    pub filtered_file_entries: Vec<FileEntry>,
+    /// Throttle of the job shown in the progress panel.
+    pub job_throttle: Option<Throttle>,
     pub total_files_to_download: usize,
     pub downloaded_files: usize,
     pub download_progress: f64,
@@ -129,21 +166,33 @@ pub struct MainUI {
     pub elapsed: Instant,
     pub details_para_state: ParagraphState,
     pub detail_window_mode: DetailWindowMode,
-    pub current_file_content: Option<String>,
+    /// The file shown in the content view.
+    pub preview: Preview,
+    /// Types guessed from the first bytes of the files selected so far, by
+    /// path. `None` while the guess is pending or if there is none.
+    pub file_types: HashMap<String, Option<&'static str>>,
     pub in_multi_key_combo_new: bool,
     pub in_editor: bool,
-    pub hidden_files: bool,
+    /// Path completion of the prompts.
+    pub completer: Completer,
 }
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum InputMode {
     #[default]
     Filter,
     DownloadPath,
+    UploadPath,
     ConfirmDelete,
     MoveEntry,
     _CopyEntry,
     CreateNewFile,
     CreateNewFolder,
+    /// Path of a new symlink to the selected entry.
+    CreateSymlink,
+    /// Directory to open in the active pane.
+    GoTo,
+    /// Name of a bookmark for the current directory.
+    AddBookmark,
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
@@ -155,10 +204,12 @@ pub enum DetailWindowMode {
 
 impl MainUI {
     pub fn new(
+        id: TabId,
+        cli: ResolvedConnectArgs,
         current_path: String,
-        sftp: Arc<SftpSession>,
-        session: Arc<AsyncMutex<Session>>,
+        connection: Connection,
     ) -> Self {
+        let Connection { session, fs } = connection;
         let mut effects: EffectManager<()> = EffectManager::default();
         let fx = fx::expand(
             fx::ExpandDirection::Vertical,
@@ -169,18 +220,29 @@ impl MainUI {
             ),
         );
         effects.add_effect(fx);
+        let local_path = std::env::current_dir()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| ".".to_string());
+        let host = OpenHost {
+            label: cli.host.clone(),
+            connection: Connection {
+                session: Arc::clone(&session),
+                fs: Arc::clone(&fs),
+            },
+        };
         Self {
-            current_path,
-            table_state: TableState::default(),
-            current_file_entries: Vec::new(),
+            id,
+            cli,
+            panes: [Pane::local(local_path), Pane::remote(&host, current_path)],
+            active: 1,
+            remote_fs: fs,
             input_state: TextInputState::default(),
             input_mode: InputMode::default(),
-            sftp,
             log_state: TuiWidgetState::new(),
             throbber: ThrobberState::default(),
             is_downloading: false,
+            job_throttle: None,
             download_progress: 0.0,
-            filtered_file_entries: Vec::new(),
             session,
             total_files_to_download: 0,
             downloaded_files: 0,
@@ -190,35 +252,148 @@ impl MainUI {
             elapsed: Instant::now(),
             details_para_state: ParagraphState::default(),
             detail_window_mode: DetailWindowMode::default(),
-            current_file_content: None,
+            preview: Preview::default(),
+            file_types: HashMap::new(),
             in_multi_key_combo_new: false,
+            completer: Completer::default(),
             in_editor: false,
-            hidden_files: false,
         }
     }
 
+    /// The host this tab was opened for.
+    pub fn host(&self) -> OpenHost {
+        OpenHost {
+            label: self.cli.host.clone(),
+            connection: Connection {
+                session: Arc::clone(&self.session),
+                fs: Arc::clone(&self.remote_fs),
+            },
+        }
+    }
+
+    /// Session for commands that act on the active pane's host.
+    fn pane_session(&self) -> Arc<AsyncMutex<Session>> {
+        Arc::clone(self.pane().session.as_ref().unwrap_or(&self.session))
+    }
+
+    pub fn pane(&self) -> &Pane {
+        &self.panes[self.active]
+    }
+
+    pub fn pane_mut(&mut self) -> &mut Pane {
+        &mut self.panes[self.active]
+    }
+
+    /// The pane copies and moves go to.
+    pub fn other_pane(&self) -> &Pane {
+        &self.panes[1 - self.active]
+    }
+
+    /// Makes pane `idx` the active one, restoring its filter into the prompt.
+    pub fn activate(&mut self, idx: usize) {
+        if self.active == idx {
+            return;
+        }
+        self.active = idx;
+        if self.input_mode == InputMode::Filter {
+            let filter = self.pane().filter.clone();
+            self.input_state.set_value(filter);
+        }
+    }
+
+    /// Whether the active pane is limited to listing and whole file
+    /// transfers (SCP mode).
+    pub fn transfers_only(&self) -> bool {
+        self.pane().fs.whole_file_writes()
+    }
+
+    /// Warns and returns `true` if `action` can't be done in SCP mode.
+    fn refuse_transfers_only(&self, action: &str) -> bool {
+        if self.transfers_only() {
+            warn!("{} is not supported over {}", action, self.pane().fs.kind());
+        }
+        self.transfers_only()
+    }
+
     #[inline]
     pub fn get_file_entries(&self) -> Cow<'_, [FileEntry]> {
-        let entries = if self.filtered_file_entries.is_empty() {
-            &self.current_file_entries
-        } else {
-            &self.filtered_file_entries
-        };
+        self.pane().get_file_entries()
+    }
+}
 
-        if self.hidden_files {
-            Cow::Owned(
-                entries
-                    .iter()
-                    .filter(|entry| !entry.name.starts_with('.'))
-                    .cloned()
-                    .collect(),
-            )
-        } else {
-            Cow::Borrowed(entries.as_slice())
+/// Compares the two files or directories marked in the active pane, or the
+/// files selected in both panes, or else the directories both panes show.
+fn compare(state: &MainUI, ctx: &mut Global) {
+    let tree = |pane: &Pane, path: String| diff_view::Source {
+        fs: pane.file_fs(),
+        path,
+        label: pane.label.clone(),
+    };
+    let source =
+        |pane: &Pane, entry: &FileEntry| tree(pane, pane.current_path.clone().join(entry.name()));
+    let marked = state.pane().targets();
+    let (left, right) = (state.panes[0].selected(), state.panes[1].selected());
+    match (&marked[..], &left, &right) {
+        ([first, second], _, _) if first.is_file() && second.is_file() => {
+            diff_view::spawn_load(
+                source(state.pane(), first),
+                source(state.pane(), second),
+                ctx,
+            );
+        }
+        ([first, second], _, _) if first.is_dir() && second.is_dir() => {
+            let dir = &state.pane().current_path;
+            dir_compare::spawn_compare(
+                tree(state.pane(), dir.join(first.name())),
+                tree(state.pane(), dir.join(second.name())),
+                ctx,
+            );
+        }
+        (_, Some(left), Some(right)) if left.is_file() && right.is_file() => {
+            diff_view::spawn_load(
+                source(&state.panes[0], left),
+                source(&state.panes[1], right),
+                ctx,
+            );
         }
+        // Otherwise the directories both panes show
+        _ => dir_compare::spawn_compare(
+            tree(&state.panes[0], state.panes[0].current_path.clone()),
+            tree(&state.panes[1], state.panes[1].current_path.clone()),
+            ctx,
+        ),
     }
 }
 
+/// Switches the active pane in or out of sudo mode, asking for the password
+/// if sudo wants one.
+fn toggle_sudo(state: &mut MainUI, ctx: &mut Global) -> Control<AppEvent> {
+    if state.pane_mut().sudo.take().is_some() {
+        return Control::Event(AppEvent::Status(1, "Left sudo mode".to_string()));
+    }
+    let Some(session) = state.pane().session.clone() else {
+        return Control::Event(AppEvent::Status(
+            1,
+            "sudo mode is only available on remote hosts".to_string(),
+        ));
+    };
+    let (idx, fs) = (state.active, Arc::clone(&state.pane().fs));
+    ctx.spawn_tab_async(async move |_| {
+        if sudo::needs_password(&session.lock().await.clone()).await? {
+            return Ok(Control::Event(AppEvent::AskSudoPassword(idx)));
+        }
+        let sudo = SudoFs::elevate(session, fs, None).await?;
+        Ok(Control::Event(AppEvent::Elevated(idx, Arc::new(sudo))))
+    });
+    Control::Changed
+}
+
+/// Moves keyboard focus to the other pane.
+pub fn switch_pane(state: &mut MainUI, ctx: &mut Global) {
+    state.activate(1 - state.active);
+    ctx.focus().focus(&state.pane().table_state);
+}
+
 pub fn render(
     area: Rect,
     buf: &mut Buffer,
@@ -235,11 +410,11 @@ pub fn render(
     )
     .split(area);
 
-    if state.detail_window_mode == DetailWindowMode::Details {
-        state.current_file_content = None;
+    if state.detail_window_mode == DetailWindowMode::Details && state.preview.is_open() {
+        state.preview.close();
     }
 
-    let current_path = state.current_path.clone();
+    let current_path = format!("{}: {}", state.pane().label, state.pane().current_path);
     let current_path_line = Line::from(current_path).style(ctx.theme.container_base());
     let current_path_line_block = Block::default()
         .borders(Borders::ALL)
@@ -252,7 +427,7 @@ pub fn render(
 
     let &[left, right] = Layout::new(
         Direction::Horizontal,
-        [Constraint::Percentage(60), Constraint::Percentage(40)],
+        [Constraint::Percentage(65), Constraint::Percentage(35)],
     )
     .split(r[1])
     .as_ref() else {
@@ -281,7 +456,7 @@ pub fn render(
         Direction::Vertical,
         [
             Constraint::Fill(1),
-            Constraint::Length(if state.is_downloading { 9 } else { 4 }),
+            Constraint::Length(if state.is_downloading { 9 } else { 7 }),
         ],
     )
     .split(right_bottom)
@@ -326,7 +501,7 @@ pub fn render(
         };
         let throbber = Throbber::default()
             .label(format!(
-                "Downloaded {:.0}/{}  ",
+                "Transferred {:.0}/{}  ",
                 state.downloaded_files, state.total_files_to_download
             ))
             .throbber_set(throbber_widgets_tui::ASCII);
@@ -354,7 +529,18 @@ pub fn render(
             .styles(ctx.theme.table_style());
 
         table.render(para_area, buf, &mut TableState::<NoSelection>::default());
-        gauge_block.title("Progress").render(rb_bottom, buf);
+        let (global_rate, job_rate) = match &state.job_throttle {
+            Some(throttle) => (throttle.global.rate(), throttle.job.rate()),
+            None => (ctx.cfg.limiter.rate(), None),
+        };
+        gauge_block
+            .title(format!(
+                "Progress [limit {} | job {}]",
+                format_rate(global_rate),
+                format_rate(job_rate)
+            ))
+            .title_bottom(Line::from(keybind("T", "Transfers")))
+            .render(rb_bottom, buf);
 
         gauge.render(progress_area, buf);
         state.elapsed = Instant::now();
@@ -363,10 +549,16 @@ pub fn render(
         state.effects.process_effects(el.into(), buf, rb_bottom);
     } else {
         let hints = [
-            keybind("Tab", "Focus  "),
+            keybind("Tab", "Pane  "),
             keybind("h/j/k/l", "Navigate Table  "),
             keybind("d", "Download  "),
+            keybind("u", "Upload  "),
             keybind("f", "Filter  "),
+            keybind("c", "Copy  "),
+            keybind("M", "Move Across  "),
+            keybind("o", "Switch Host  "),
+            keybind("s/S/D", "Sort  "),
+            keybind("C", "Columns"),
         ]
         .iter()
         .flatten()
@@ -376,7 +568,20 @@ pub fn render(
             keybind("Enter", "View Content  "),
             keybind("x", "Delete  "),
             keybind("m", "Move  "),
-            keybind("Enter e", "Edit  "),
+            keybind("e", "Edit  "),
+            keybind("U", "Sudo  "),
+            keybind("=", "Diff  "),
+            keybind("Space", "Mark  "),
+            keybind("p", "Permissions  "),
+            keybind("L", "Follow Link  "),
+            keybind("[/]", "Back/Forward  "),
+            keybind("g", "Go To  "),
+            keybind("~/r", "Home/Root  "),
+            keybind("b/B", "Bookmarks  "),
+            keybind("C-p", "Find  "),
+            keybind("T", "Transfers  "),
+            keybind("C-t/C-w", "Open/Close Tab  "),
+            keybind("C-←/→", "Switch Tab"),
         ]
         .iter()
         .flatten()
@@ -385,6 +590,7 @@ pub fn render(
         Paragraph::new(vec![Line::from(hints), Line::from(hints_2)])
             .styles(ctx.theme.paragraph_style())
             .alignment(ratatui::layout::Alignment::Center)
+            .wrap(Wrap { trim: true })
             .block(
                 gauge_block
                     .border_style(ctx.theme.container_border())
@@ -396,19 +602,34 @@ pub fn render(
         //           .process_effects(el.mul_f64(2.0).into(), buf, rb_bottom);
     }
 
-    if let Some(row) = state.table_state.selected() {
+    if let Some(row) = state.pane().table_state.selected() {
         let file = state.get_file_entries();
         let file = file.get(row);
         if let Some(file) = file {
-            if let Some(content) = &state.current_file_content
+            if state.preview.hex.is_some() && state.detail_window_mode == DetailWindowMode::Content
+            {
+                // Keeps <2> focusing the view
+                state.details_para_state.area = right_top;
+                if let Some(hex) = &mut state.preview.hex {
+                    hex_view::render(right_top, buf, hex, ctx);
+                }
+            } else if state.preview.doc.is_some()
                 && state.detail_window_mode == DetailWindowMode::Content
             {
                 Clear.render(right_top, buf);
-                Paragraph::new(content.clone())
+                let text = state
+                    .preview
+                    .text(ctx.theme.line_nr_style().style, ctx.theme.palette());
+                let mut paragraph = Paragraph::new(text);
+                if state.preview.wrap {
+                    paragraph = paragraph.wrap(Wrap { trim: false });
+                }
+                paragraph
                     .block(
                         Block::bordered()
                             .border_type(BorderType::Rounded)
-                            .title_top("[2] Content")
+                            .title_top(state.preview.title())
+                            .title_bottom(state.preview.footer())
                             .border_style(ctx.theme.container_border())
                             .padding(Padding::uniform(1)),
                     )
@@ -417,9 +638,10 @@ pub fn render(
                     .styles(ctx.theme.paragraph_style())
                     .render(right_top, buf, &mut state.details_para_state);
             } else {
-                let metadata = file.attributes();
                 let mut buffer = Vec::new();
-                let metadata_table = MetadataSlice::from_attributes(metadata.clone(), &mut buffer);
+                let path = state.pane().current_path.clone().join(file.name());
+                let file_type = state.file_types.get(&path).copied().flatten();
+                let metadata_table = MetadataSlice::from_entry(file, file_type, &mut buffer);
                 let table = Table::<NoSelection>::new()
                     .data(metadata_table)
                     .widths([Constraint::Percentage(50), Constraint::Percentage(50)])
@@ -451,27 +673,54 @@ pub fn render(
     let input_block_title = match state.input_mode {
         InputMode::Filter => "[3] Filter".to_string(),
         InputMode::DownloadPath => {
-            let current_item = state.table_state.selected_checked().unwrap_or_default();
+            let current_item = state
+                .pane()
+                .table_state
+                .selected_checked()
+                .unwrap_or_default();
             let file = state.get_file_entries()[current_item].clone();
 
             format!(
                 "[3] Download [{}/{}] to Path",
-                state.current_path,
+                state.pane().current_path,
                 file.name()
             )
         }
+        InputMode::UploadPath => format!("[3] Upload Path to [{}]", state.pane().current_path),
         InputMode::ConfirmDelete => {
-            let current_item = state.table_state.selected_checked().unwrap_or_default();
+            let current_item = state
+                .pane()
+                .table_state
+                .selected_checked()
+                .unwrap_or_default();
             let file = state.get_file_entries()[current_item].clone();
 
-            format!("[3] rm -rf [{}/{}]", state.current_path, file.name())
+            format!("[3] rm -rf [{}/{}]", state.pane().current_path, file.name())
         }
         InputMode::MoveEntry => {
-            let current_item = state.table_state.selected_checked().unwrap_or_default();
+            let current_item = state
+                .pane()
+                .table_state
+                .selected_checked()
+                .unwrap_or_default();
             let file = state.get_file_entries()[current_item].clone();
 
-            format!("[3] mv [{}/{}] to Path", state.current_path, file.name())
+            format!(
+                "[3] mv [{}/{}] to Path",
+                state.pane().current_path,
+                file.name()
+            )
         }
+        InputMode::GoTo => "[3] Go to Path".to_string(),
+        InputMode::AddBookmark => format!("[3] Bookmark [{}] as", state.pane().current_path),
+        InputMode::CreateSymlink => match state.pane().selected() {
+            Some(file) => format!(
+                "[3] ln -s [{}/{}] at Path",
+                state.pane().current_path,
+                file.name()
+            ),
+            None => String::new(),
+        },
         _ => String::new(),
     };
     let input = TextInput::new().style(ctx.theme.container_base()).block(
@@ -484,72 +733,324 @@ pub fn render(
         );
     input.render(left_bottom, buf, &mut state.input_state);
 
-    let files = state.get_file_entries().into_owned();
-    let data = FileDataSlice(&files.clone());
+    let &[left_pane, right_pane] = Layout::new(
+        Direction::Horizontal,
+        [Constraint::Percentage(50), Constraint::Percentage(50)],
+    )
+    .split(left_top)
+    .as_ref() else {
+        unreachable!()
+    };
+    for (idx, area) in [left_pane, right_pane].into_iter().enumerate() {
+        let title = format!(
+            "{}{}: {}",
+            if idx == state.active { "[1] " } else { "" },
+            state.panes[idx].label,
+            state.panes[idx].current_path
+        );
+        let border_style = if idx == state.active {
+            ctx.theme.container_border().fg(Color::Yellow)
+        } else {
+            ctx.theme.container_border()
+        };
+        let pane = &mut state.panes[idx];
+        let files = pane.get_file_entries().into_owned();
+        // Borders and padding take four cells
+        pane.columns = Column::fit(&ctx.cfg.columns, area.width.saturating_sub(4));
+        let data = FileDataSlice(&files, &pane.columns, &pane.marked);
+
+        // Stands out while files are read and written as root
+        let sudo_badge = if pane.sudo.is_some() {
+            Line::from(" SUDO ")
+                .style(Style::default().fg(Color::White).bg(Color::Red).bold())
+                .right_aligned()
+        } else {
+            Line::default()
+        };
+        let table = Table::<RowSelection>::default()
+            .block(
+                Block::bordered()
+                    .padding(Padding::horizontal(1))
+                    .border_type(block::BorderType::Rounded)
+                    .title_top(title)
+                    .title_bottom(format!(
+                        "sort: {}{}{}",
+                        pane.sort.label(),
+                        if pane.sort_reverse { " (desc)" } else { "" },
+                        if pane.dirs_first { ", dirs first" } else { "" }
+                    ))
+                    .border_style(border_style)
+                    .title_top(sudo_badge),
+            )
+            .data(data)
+            .widths(pane.columns.iter().map(|column| match column {
+                Column::Name => Constraint::Fill(1),
+                column => Constraint::Length(column.width()),
+            }))
+            .column_spacing(1)
+            .header(rat_ftable::textdata::Row::new(pane.columns.iter().map(
+                |column| {
+                    let sorted = SortKey::for_column(*column) == Some(pane.sort);
+                    let arrow = match (sorted, pane.sort_reverse) {
+                        (false, _) => "",
+                        (true, false) => " ▲",
+                        (true, true) => " ▼",
+                    };
+                    let title = column.title();
+                    let prefix = if *column == Column::Name {
+                        ROUNDED_TOP_LEFT.to_string() + &HORIZONTAL.repeat(3)
+                    } else {
+                        String::new()
+                    };
+                    Cell::from(format!("{prefix}{title}{arrow}"))
+                },
+            )))
+            .vscroll(Scroll::new())
+            .flex(Flex::Start)
+            .styles(ctx.theme.table_style());
+        table.render(area, buf, &mut pane.table_state);
+    }
 
-    let table_style = ctx.theme.table_style();
-    let table = Table::<RowSelection>::default()
-        .block(
-            Block::bordered()
-                .padding(Padding::horizontal(1))
-                .border_type(block::BorderType::Rounded)
-                .title_top("[1]")
-                .border_style(ctx.theme.container_border()),
-        )
-        .data(data)
-        .widths([
-            Constraint::Length(12),
-            Constraint::Length(40),
-            Constraint::Length(15),
-            Constraint::Length(30),
-        ])
-        .column_spacing(1)
-        .header(rat_ftable::textdata::Row::new([
-            Cell::from("Permissions"),
-            Cell::from(ROUNDED_TOP_LEFT.to_string() + &HORIZONTAL.repeat(3) + "Path"),
-            Cell::from("Size"),
-            Cell::from("Modified At"),
-        ]))
-        .vscroll(Scroll::new())
-        .flex(Flex::Start)
-        .styles(table_style);
-    table.render(left_top, buf, &mut state.table_state);
+    // Completion candidates pop up over the tables, right above the prompt
+    if let Some(popup) = &state.completer.popup
+        && state.input_state.is_focused()
+    {
+        let height = (popup.candidates.len() as u16 + 2)
+            .min(10)
+            .min(left_bottom.y);
+        let area = Rect::new(
+            left_bottom.x,
+            left_bottom.y - height,
+            left_bottom.width,
+            height,
+        );
+        let items = popup.candidates.iter().map(|candidate| {
+            let slash = if candidate.is_dir { "/" } else { "" };
+            ListItem::new(format!("{}{}", candidate.name, slash))
+        });
+        let list = List::new(items)
+            .style(ctx.theme.popup_base())
+            .highlight_style(ctx.theme.select())
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(ctx.theme.popup_border())
+                    .title("<Tab>/<S-Tab> Cycle"),
+            );
+        Clear.render(area, buf);
+        let mut list_state = ListState::default().with_selected(popup.selected);
+        StatefulWidget::render(list, area, buf, &mut list_state);
+    }
 
     Ok(())
 }
 
-impl_has_focus!(table_state, input_state, details_para_state for MainUI);
+impl HasFocus for MainUI {
+    fn build(&self, builder: &mut FocusBuilder) {
+        for pane in &self.panes {
+            builder.widget(&pane.table_state);
+        }
+        builder.widget(&self.input_state);
+        builder.widget(&self.details_para_state);
+    }
+
+    // Only a list of widgets, not a container of its own
+    fn focus(&self) -> FocusFlag {
+        FocusFlag::default()
+    }
+
+    fn area(&self) -> Rect {
+        Rect::default()
+    }
+}
 
 pub fn init(
     state: &mut MainUI, //
     ctx: &mut Global,
 ) -> Result<(), Error> {
-    let path = state.current_path.clone();
-    let cli = ctx.cfg.cli.clone();
-    let _ = ctx.spawn_async_ext(|chan| async move {
-        info!("connecting to {}:{}", cli.host, cli.port);
-        let mut ssh = Session::connect(
-            cli.private_key,
-            cli.username.unwrap_or("root".to_string()),
-            cli.openssh_certificate,
-            (cli.host, cli.port),
-        )
-        .await?;
-        info!("Connected");
+    for idx in 0..state.panes.len() {
+        let pane = &mut state.panes[idx];
+        pane.sort = ctx.cfg.sort.key;
+        pane.sort_reverse = ctx.cfg.sort.reverse;
+        pane.dirs_first = ctx.cfg.sort.dirs_first;
+        load_pane(state, idx, ctx);
+    }
 
-        let sftp = ssh.sftp().await?;
-        let files = sftp.read_dir(path.clone()).await?;
-        let files = files.into_iter().map(FileEntry::from).collect::<Vec<_>>();
-        let full_path = sftp.canonicalize(path).await?;
-        chan.send(Ok(Control::Event(AppEvent::UpdateCurrentPath(full_path))))
-            .await?;
-        chan.send(Ok(Control::Event(AppEvent::UpdateFiles(files))))
+    ctx.focus().focus(&state.pane().table_state);
+    Ok(())
+}
+
+/// Resolves the path of pane `idx` and lists it.
+pub fn load_pane(state: &MainUI, idx: usize, ctx: &mut Global) {
+    let path = state.panes[idx].current_path.clone();
+    let fs = Arc::clone(&state.panes[idx].fs);
+    let columns = ctx.cfg.columns.clone();
+    ctx.spawn_tab_async(move |chan| async move {
+        let full_path = fs.canonicalize(&path).await?;
+        let files = remote_fs::list_for(&fs, &full_path, &columns).await?;
+        chan.send(Ok(Control::Event(AppEvent::UpdateCurrentPath(
+            idx, full_path,
+        ))))
+        .await?;
+        chan.send(Ok(Control::Event(AppEvent::UpdateFiles(idx, files))))
             .await?;
         Ok(Control::Event(AppEvent::AsyncTick(300)))
     });
+}
 
-    ctx.focus().first();
-    Ok(())
+/// How many guessed file types are kept before starting over.
+const FILE_TYPES_CACHED: usize = 4096;
+
+/// Guesses the type of the selected file from its first bytes, once per
+/// file. Files that can't be read are simply left without a type.
+fn sniff_selected(state: &mut MainUI, ctx: &mut Global) {
+    let Some(entry) = state.pane().selected() else {
+        return;
+    };
+    if !entry.is_file() || entry.attributes.size == Some(0) {
+        return;
+    }
+    let path = state.pane().current_path.clone().join(entry.name());
+    if state.file_types.contains_key(&path) {
+        return;
+    }
+    if state.file_types.len() >= FILE_TYPES_CACHED {
+        state.file_types.clear();
+    }
+    state.file_types.insert(path.clone(), None);
+    let fs = Arc::clone(&state.pane().fs);
+    ctx.spawn_tab_async(move |_| async move {
+        let head = fs.read_at(&path, 0, 512).await.ok();
+        let file_type = head.and_then(|head| hex_view::sniff(&head));
+        Ok(Control::Event(AppEvent::FileType(path, file_type)))
+    });
+}
+
+/// Opens where the symlink `link` of the active pane points: the directory
+/// itself or the one holding the file.
+fn follow_link(state: &mut MainUI, link: &FileEntry, ctx: &mut Global) {
+    let pane = state.active;
+    let path = state.pane().current_path.clone().join(link.name());
+    let fs = Arc::clone(&state.pane().fs);
+    ctx.spawn_tab_async(move |_| async move {
+        let resolved = match fs.canonicalize(&path).await {
+            Ok(target) => fs
+                .stat(&target)
+                .await
+                .map(|attributes| (target, attributes)),
+            Err(err) => Err(err),
+        };
+        let (target, attributes) =
+            resolved.wrap_err_with(|| format!("{} is a broken link", path))?;
+        let dir = if attributes.is_dir() {
+            target
+        } else {
+            PathBuf::from(&target)
+                .parent()
+                .map_or(target.clone(), |parent| parent.display().to_string())
+        };
+        Ok(Control::Event(AppEvent::GoTo(pane, dir)))
+    });
+}
+
+/// Where completions for the current prompt come from: the active pane for
+/// paths on its host, the local disk for downloads and uploads.
+pub fn completion_source(state: &MainUI) -> Option<Source> {
+    let pane = state.pane();
+    let local = || {
+        let home = directories::BaseDirs::new().map_or(".".to_string(), |dirs| {
+            dirs.home_dir().display().to_string()
+        });
+        let cwd = std::env::current_dir().map_or(".".to_string(), |p| p.display().to_string());
+        let fs = Arc::new(LocalFs) as Arc<dyn RemoteFs>;
+        (fs, "local".to_string(), cwd, home)
+    };
+    let in_pane = || {
+        let fs = Arc::clone(&pane.fs);
+        (
+            fs,
+            pane.label.clone(),
+            pane.current_path.clone(),
+            pane.home(),
+        )
+    };
+    let ((fs, label, base, home), dirs_only) = match state.input_mode {
+        InputMode::GoTo => (in_pane(), true),
+        InputMode::MoveEntry
+        | InputMode::CreateNewFile
+        | InputMode::CreateNewFolder
+        | InputMode::CreateSymlink => (in_pane(), false),
+        InputMode::DownloadPath => (local(), true),
+        InputMode::UploadPath => (local(), false),
+        _ => return None,
+    };
+    Some(Source {
+        fs,
+        label,
+        base,
+        home,
+        dirs_only,
+    })
+}
+
+/// Completes the last path segment of the prompt, or cycles through the
+/// candidates if several matched last time. Listings not in the cache come
+/// back as [`AppEvent::Listing`].
+pub fn complete(state: &mut MainUI, forward: bool, ctx: &mut Global) {
+    if let Some(value) = state.completer.cycle(forward) {
+        state.input_state.set_value(value);
+        state.input_state.move_to_line_end(false);
+        return;
+    }
+    let Some(source) = completion_source(state) else {
+        return;
+    };
+    let input: String = state.input_state.value();
+    let dir = source.resolve(completion::split(&input).0);
+    let key = source.key(&dir);
+    if let Some(listing) = state.completer.cached(&key) {
+        let listing = listing.to_vec();
+        apply_completion(state, &input, &listing, source.dirs_only);
+        return;
+    }
+    ctx.spawn_tab_async(move |_| async move {
+        let listing = source.fs.list(&dir).await?;
+        let listing = listing.into_iter().map(Candidate::from).collect();
+        Ok(Control::Event(AppEvent::Listing(key, input, listing)))
+    });
+}
+
+fn apply_completion(state: &mut MainUI, input: &str, listing: &[Candidate], dirs_only: bool) {
+    if let Some((value, popup)) = completion::complete(input, listing, dirs_only) {
+        state.input_state.set_value(value);
+        state.input_state.move_to_line_end(false);
+        state.completer.popup = popup;
+    }
+}
+
+/// Points the active pane at the next place to browse: the local disk,
+/// then the host of every open tab.
+fn cycle_pane_host(state: &mut MainUI, ctx: &mut Global) {
+    let current = ctx
+        .hosts
+        .iter()
+        .position(|host| state.pane().is_on(Some(host)));
+    let target = match current {
+        None => ctx.hosts.first(),
+        Some(idx) => ctx.hosts.get(idx + 1),
+    }
+    .cloned();
+    // Start where the other pane is if it already browses the same place
+    let other = state.other_pane();
+    let path = if other.is_on(target.as_ref()) {
+        other.current_path.clone()
+    } else if target.is_none() {
+        std::env::current_dir().map_or(".".to_string(), |p| p.display().to_string())
+    } else {
+        ".".to_string()
+    };
+    state.pane_mut().browse(target.as_ref(), path);
+    load_pane(state, state.active, ctx);
 }
 
 pub fn event(
@@ -559,12 +1060,44 @@ pub fn event(
 ) -> Result<Control<AppEvent>, Error> {
     let r = match event {
         AppEvent::Event(event) => {
+            // Clicking into a pane makes it the active one
+            if let Some(idx) = state
+                .panes
+                .iter()
+                .position(|pane| pane.table_state.is_focused())
+            {
+                state.activate(idx);
+            }
+            // Clicking a column header sorts by it
+            if let ct_event!(mouse down Left for x, y) = event
+                && let Some((idx, pane)) = state
+                    .panes
+                    .iter_mut()
+                    .enumerate()
+                    .find(|(_, pane)| pane.table_state.header_area.contains((*x, *y).into()))
+                && let Some(column) = pane
+                    .table_state
+                    .column_areas
+                    .iter()
+                    .position(|area| (area.left()..area.right()).contains(x))
+                && let Some(key) = pane
+                    .columns
+                    .get(column)
+                    .copied()
+                    .and_then(SortKey::for_column)
+            {
+                pane.sort_by(key);
+                state.activate(idx);
+                ctx.focus().focus(&state.pane().table_state);
+                return Ok(Control::Changed);
+            }
             if let Some(t) = ctx.focus().focused()
                 && t != state.input_state.focus
             {
                 try_flow!(match event {
                     ct_event!(key press '.') => {
-                        state.hidden_files = !state.hidden_files;
+                        let pane = state.pane_mut();
+                        pane.hidden_files = !pane.hidden_files;
                         Control::Changed
                     }
                     _ => Control::Continue,
@@ -582,6 +1115,11 @@ pub fn event(
                         ctx.focus().focus(&state.input_state);
                         Control::Changed
                     }
+                    ct_event!(key press 's') if state.pane().selected().is_some() => {
+                        state.input_mode = InputMode::CreateSymlink;
+                        ctx.focus().focus(&state.input_state);
+                        Control::Changed
+                    }
                     _ => {
                         if state.input_mode != InputMode::CreateNewFolder
                             || state.input_mode != InputMode::CreateNewFile
@@ -592,17 +1130,24 @@ pub fn event(
                     }
                 })
             }
+            if let Some(hex) = &mut state.preview.hex
+                && state.details_para_state.is_focused()
+            {
+                try_flow!(hex_view::event(event, hex));
+            } else if state.preview.doc.is_some() && state.details_para_state.is_focused() {
+                try_flow!(preview::event(
+                    event,
+                    &mut state.preview,
+                    &mut state.details_para_state,
+                    ctx
+                ));
+            }
             try_flow!(match event {
-                ct_event!(key press CONTROL-'q') => {
-                    if let Some(cancel) = state.throbber_cancel.take() {
-                        cancel.cancel();
-                    }
-                    Control::Quit
-                }
                 ct_event!(keycode press Esc) => {
-                    ctx.focus().focus(&state.table_state);
-                    state.filtered_file_entries.clear();
+                    ctx.focus().focus(&state.pane().table_state);
+                    state.pane_mut().set_filter(String::new());
                     state.input_state.clear();
+                    state.completer.popup = None;
                     Control::Changed
                 }
                 _ => Control::Continue,
@@ -612,7 +1157,7 @@ pub fn event(
             {
                 match event {
                     ct_event!(key press '1') => {
-                        ctx.focus().focus(&state.table_state);
+                        ctx.focus().focus(&state.pane().table_state);
                         Control::Changed
                     }
                     ct_event!(key press '2') => {
@@ -623,6 +1168,18 @@ pub fn event(
                         ctx.focus().focus(&state.input_state);
                         Control::Changed
                     }
+                    ct_event!(key press '#')
+                        if state.detail_window_mode == DetailWindowMode::Content =>
+                    {
+                        state.preview.line_numbers = !state.preview.line_numbers;
+                        Control::Changed
+                    }
+                    ct_event!(key press 'w')
+                        if state.detail_window_mode == DetailWindowMode::Content =>
+                    {
+                        state.preview.wrap = !state.preview.wrap;
+                        Control::Changed
+                    }
                     _ => Control::Continue,
                 }
             } else {
@@ -631,10 +1188,10 @@ pub fn event(
             try_flow!(state.details_para_state.handle(event, Regular));
 
             try_flow!(match_focus!(
-                state.table_state => {
+                state.pane().table_state => {
                 try_flow!(
                     match rowselection::handle_events(
-                        &mut state.table_state,
+                        &mut state.pane_mut().table_state,
                         true,
                         event
                     ) {
@@ -647,56 +1204,53 @@ pub fn event(
                 );
                     match event {
                         ct_event!(key press 'j') => {
-                            state.current_file_content = None;
                             state.detail_window_mode = DetailWindowMode::Details;
 
-                            state.table_state.move_down(1);
+                            state.pane_mut().table_state.move_down(1);
                             Control::<AppEvent>::Changed
                         }
                         ct_event!(key press 'k') => {
-                            state.current_file_content = None;
                         state.detail_window_mode = DetailWindowMode::Details;
-                            state.table_state.move_up(1);
+                            state.pane_mut().table_state.move_up(1);
                             Control::Changed
                         }
+                        ct_event!(key press 'x') if state.refuse_transfers_only("Deleting") => Control::Changed,
                         ct_event!(key press 'x') => {
                             state.input_mode = InputMode::ConfirmDelete;
                             state.input_state.set_value("Delete file [Y/n]?".to_string());
                             ctx.focus().focus(&state.input_state);
                             Control::Changed
                         }
+                        ct_event!(key press 'n') if state.refuse_transfers_only("Creating entries") => Control::Changed,
                         ct_event!(key press 'n') => {
                             state.in_multi_key_combo_new = true;
                             Control::Changed
                         }
+                        ct_event!(key press 'm') if state.refuse_transfers_only("Moving") => Control::Changed,
                         ct_event!(key press 'm') => {
                             state.input_mode = InputMode::MoveEntry;
                             ctx.focus().focus(&state.input_state);
                             Control::Changed
                         }
                         ct_event!(key press 'e') => {
-                            Control::Event(AppEvent::SpawnExternalEditor(state.current_path.clone().join(state.get_file_entries()[state.table_state.selected_checked().unwrap_or_default()].name())))
+                            Control::Event(AppEvent::SpawnExternalEditor(state.pane().current_path.clone().join(state.get_file_entries()[state.pane().table_state.selected_checked().unwrap_or_default()].name())))
+                        }
+                        ct_event!(key press 'U') => toggle_sudo(state, ctx),
+                        ct_event!(key press '=') => {
+                            compare(state, ctx);
+                            Control::Changed
                         }
                         ct_event!(key press CONTROL-'o' )  => {
                             return Ok(Control::Event(AppEvent::SpawnSSHCommand));
 
                         }
                         ct_event!(keycode press Enter) => {
-                        if let Some(row_idx) = state.table_state.selected() && let Some(row) = state.get_file_entries().get(row_idx) && row.is_file() {
-
-                                let sftp = Arc::clone(&state.sftp);
-                                let current_path = state.current_path.clone();
-                                let row = row.clone();
-                                ctx.spawn_async_ext(async move |_| {
-                                    let mut file = sftp
-                                        .open(current_path.clone().join(row.name()))
-                                    .await?;
-                                    let mut buf = Vec::new();
-                                    file.read_to_end(&mut buf).await?;
-                                    let content = String::from_utf8(buf).ok();
-
-                                    Ok(Control::Event(AppEvent::UpdateContent(content)))
-                                });
+                        if let Some(row_idx) = state.pane().table_state.selected() && let Some(row) = state.get_file_entries().get(row_idx) && row.is_file() {
+
+                                let fs = state.pane().file_fs();
+                                let path = state.pane().current_path.clone().join(row.name());
+                                let name = row.name().clone();
+                                state.preview.open(fs, path, &name, ctx);
 
                                 state.detail_window_mode = DetailWindowMode::Content;
                             }
@@ -705,13 +1259,12 @@ pub fn event(
                         ct_event!(keycode press Left ) | ct_event!(key press 'h')=> {
 
                         state.detail_window_mode = DetailWindowMode::Details;
-                            let path = PathBuf::from(state.current_path.clone());
+                            let path = PathBuf::from(state.pane().current_path.clone());
                             let parent = path.parent();
                             if let Some(parent) = parent {
                                 let parent = parent.display();
-                                state.current_path = parent.to_string();
-                                state.filtered_file_entries.clear();
-                                Control::Event(AppEvent::ChangeDir(parent.to_string()))
+                                state.pane_mut().navigate(parent.to_string());
+                                Control::Event(AppEvent::ChangeDir(state.active, parent.to_string()))
                             } else {
                                 Control::Continue
                             }
@@ -719,8 +1272,8 @@ pub fn event(
 
                         ct_event!(key press 'l') | ct_event!(keycode press Right) => {
                         state.detail_window_mode = DetailWindowMode::Details;
-                            let path = PathBuf::from(state.current_path.clone());
-                            let selected = state.table_state.selected();
+                            let path = PathBuf::from(state.pane().current_path.clone());
+                            let selected = state.pane().table_state.selected();
                             if let Some(selected) = selected {
                                 let files = state.get_file_entries();
                                 let Some(file) = files.get(selected) else {
@@ -728,13 +1281,95 @@ pub fn event(
                                 };
                                 if file.is_dir() {
                                     let path = path.join(file.name());
-                                    state.current_path = path.display().to_string();
-                                    state.filtered_file_entries.clear();
-                                    return Ok(Control::Event(AppEvent::ChangeDir(path.display().to_string())));
+                                    state.pane_mut().navigate(path.display().to_string());
+                                    return Ok(Control::Event(AppEvent::ChangeDir(state.active, path.display().to_string())));
                                 }
                             }
                             Control::Continue
                         }
+                        ct_event!(key press '[') => match state.pane_mut().go_back() {
+                            Some(_) => {
+                                load_pane(state, state.active, ctx);
+                                Control::Changed
+                            }
+                            None => Control::Continue,
+                        },
+                        ct_event!(key press ']') => match state.pane_mut().go_forward() {
+                            Some(_) => {
+                                load_pane(state, state.active, ctx);
+                                Control::Changed
+                            }
+                            None => Control::Continue,
+                        },
+                        ct_event!(key press '~') => Control::Event(AppEvent::GoTo(state.active, state.pane().home())),
+                        ct_event!(key press 'r') => Control::Event(AppEvent::GoTo(state.active, "/".to_string())),
+                        ct_event!(key press 'g') => {
+                            state.input_mode = InputMode::GoTo;
+                            state.input_state.clear();
+                            ctx.focus().focus(&state.input_state);
+                            Control::Changed
+                        }
+                        ct_event!(key press 'b') => Control::Event(AppEvent::OpenBookmarks(state.active)),
+                        ct_event!(key press 'B') => {
+                            state.input_mode = InputMode::AddBookmark;
+                            state.input_state.clear();
+                            ctx.focus().focus(&state.input_state);
+                            Control::Changed
+                        }
+                        ct_event!(key press 'L') => {
+                            match state.pane().selected() {
+                                Some(file) if file.is_symlink() => {
+                                    follow_link(state, &file, ctx);
+                                    Control::Changed
+                                }
+                                _ => Control::Continue,
+                            }
+                        }
+                        ct_event!(key press 'c') => {
+                            match state.pane().selected() {
+                                Some(file) => Control::Event(AppEvent::CopyToPane(state.active, file, false)),
+                                None => Control::Continue,
+                            }
+                        }
+                        ct_event!(key press 'M') if state.refuse_transfers_only("Moving") => Control::Changed,
+                        ct_event!(key press 'M') => {
+                            match state.pane().selected() {
+                                Some(file) => Control::Event(AppEvent::CopyToPane(state.active, file, true)),
+                                None => Control::Continue,
+                            }
+                        }
+                        ct_event!(key press 'o') => {
+                            cycle_pane_host(state, ctx);
+                            Control::Changed
+                        }
+                        ct_event!(key press 's') => {
+                            let pane = state.pane_mut();
+                            pane.sort = pane.sort.next();
+                            pane.sort_entries();
+                            Control::Changed
+                        }
+                        ct_event!(key press 'S') => {
+                            let pane = state.pane_mut();
+                            pane.sort_reverse = !pane.sort_reverse;
+                            pane.sort_entries();
+                            Control::Changed
+                        }
+                        ct_event!(key press 'C') => Control::Event(AppEvent::EditColumns),
+                        ct_event!(key press 'T') => Control::Event(AppEvent::OpenTransfers),
+                        ct_event!(key press ' ') => {
+                            let pane = state.pane_mut();
+                            pane.toggle_mark();
+                            pane.table_state.move_down(1);
+                            Control::Changed
+                        }
+                        ct_event!(key press 'p') if state.refuse_transfers_only("Changing permissions") => Control::Changed,
+                        ct_event!(key press 'p') => Control::Event(AppEvent::EditPermissions(state.active)),
+                        ct_event!(key press 'D') => {
+                            let pane = state.pane_mut();
+                            pane.dirs_first = !pane.dirs_first;
+                            pane.sort_entries();
+                            Control::Changed
+                        }
                         ct_event!(key press 'd') => {
                             state.input_mode = InputMode::DownloadPath;
                             state.input_state.clear();
@@ -742,6 +1377,13 @@ pub fn event(
 
                             Control::Changed
                         }
+                        ct_event!(key press 'u') => {
+                            state.input_mode = InputMode::UploadPath;
+                            state.input_state.clear();
+                            ctx.focus().focus(&state.input_state);
+
+                            Control::Changed
+                        }
                         ct_event!(key press 'f') => {
                             state.input_mode = InputMode::Filter;
                             ctx.focus().focus(&state.input_state);
@@ -751,12 +1393,15 @@ pub fn event(
                     }
                 },
                 state.input_state => {
+                    if let ct_event!(keycode press Enter) = event {
+                        state.completer.popup = None;
+                    }
                     match state.input_mode {
                         InputMode::ConfirmDelete => {
                         try_flow!(
                             match event {
                                 ct_event!(key press 'y') => {
-                                    if let Some(idx) = state.table_state.clone().selected() && let Some(file) = state.get_file_entries().get(idx){
+                                    if let Some(idx) = state.pane().table_state.clone().selected() && let Some(file) = state.get_file_entries().get(idx){
                                         let file = file.clone();
                                         state.input_state.clear();
                                         ctx.focus().first();
@@ -768,7 +1413,7 @@ pub fn event(
                                 }
                                 ct_event!(key press 'n') => {
                                     state.input_state.clear();
-                                    ctx.focus().focus(&state.table_state);
+                                    ctx.focus().focus(&state.pane().table_state);
                                     state.input_mode = InputMode::Filter;
                                     Control::Changed
                                 }
@@ -784,14 +1429,14 @@ pub fn event(
                                 let path = PathBuf::from(path);
                                 std::fs::create_dir_all(path.clone())?;
                                 let path = path.canonicalize()?;
-                                let selected = state.table_state.selected();
+                                let selected = state.pane().table_state.selected();
                                 if let Some(selected) = selected {
                                         let files = state.get_file_entries();
                                     let Some(file) = files.get(selected) else {
                                         return Ok(Control::Continue);
                                     };
                                     let path = path.join(file.name());
-                                    let name = state.current_path.clone().join(file.name());
+                                    let name = state.pane().current_path.clone().join(file.name());
                                     if file.is_dir() {
                                         return Ok(Control::Event(AppEvent::DownloadFolder(name, path)));
                                     }
@@ -801,11 +1446,31 @@ pub fn event(
                             _ => {}
                         }
                         }
+                        InputMode::UploadPath => {
+                        match event {
+                            ct_event!(keycode press Enter) => {
+                                let path: String = state.input_state.value();
+                                let path = PathBuf::from(shellexpand::tilde(&path).as_ref()).canonicalize()?;
+                                let Some(name) = path.file_name() else {
+                                    return Ok(Control::Continue);
+                                };
+                                let remote = state.pane().current_path.clone().join(&name.to_string_lossy());
+                                ctx.focus().first();
+                                state.input_state.clear();
+                                state.input_mode = InputMode::default();
+                                if path.is_dir() {
+                                    return Ok(Control::Event(AppEvent::UploadFolder(path, remote)));
+                                }
+                                return Ok(Control::Event(AppEvent::UploadFile(path, remote)));
+                            }
+                            _ => {}
+                        }
+                        }
                         InputMode::MoveEntry => {
                             match event {
                                 ct_event!(keycode press Enter) => {
-                                    let old_path = state.current_path.clone().join(state.get_file_entries()[state.table_state.selected_checked().unwrap_or_default()].name());
-                                    let new_path:String = state.current_path.clone().join(&state.input_state.value::<String>());
+                                    let old_path = state.pane().current_path.clone().join(state.get_file_entries()[state.pane().table_state.selected_checked().unwrap_or_default()].name());
+                                    let new_path = state.pane().resolve(&state.input_state.value::<String>());
                                     ctx.focus().first();
                                     state.input_state.clear();
                                     state.input_mode = InputMode::default();
@@ -818,21 +1483,67 @@ pub fn event(
                         InputMode::CreateNewFile => {
                             match event {
                                 ct_event!(keycode press Enter) => {
-                                    let curr_dir = state.current_path.clone();
-                                    let file_name = state.input_state.value::<String>();
-                                    let path = curr_dir.join(&file_name);
-                                    let session = Arc::clone(&state.session);
-                                    ctx.spawn_async_ext(|chan| async move {
-                                        let mut session = session.lock().await;
-                                        let sftp = session.sftp().await?;
-                                        let path = sftp.canonicalize(path.clone()).await?;
+                                    let curr_dir = state.pane().current_path.clone();
+                                    let path = state.pane().resolve(&state.input_state.value::<String>());
+                                    let fs = Arc::clone(&state.pane().fs);
+                                    let pane = state.active;
+                                    ctx.spawn_tab_async(move |chan| async move {
+                                        let path = fs.canonicalize(&path).await.unwrap_or(path);
                                         info!(path, "Creating file");
-                                        let mut file = sftp.create(path.clone()).await?;
+                                        let mut file = fs.create(&path).await?;
                                         file.write_all(b"").await?;
-                                        file.flush().await?;
-                                        file.sync_all().await?;
+                                        file.shutdown().await?;
                                         info!(path, "Created file");
-                                        chan.send(Ok(Control::Event(AppEvent::ChangeDir(curr_dir)))).await?;
+                                        chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, curr_dir)))).await?;
+                                        Ok(Control::Event(AppEvent::AsyncTick(300)))
+                                    });
+                                    state.input_state.clear();
+                                    state.input_mode = InputMode::default();
+                                    state.in_multi_key_combo_new = false;
+                                }
+                                _ => {}
+                            }
+                        }
+                        InputMode::GoTo => {
+                            if let ct_event!(keycode press Enter) = event {
+                                let path = state.pane().resolve(&state.input_state.value::<String>());
+                                ctx.focus().focus(&state.pane().table_state);
+                                state.input_state.clear();
+                                state.input_mode = InputMode::default();
+                                return Ok(Control::Event(AppEvent::GoTo(state.active, path)));
+                            }
+                        }
+                        InputMode::AddBookmark => {
+                            if let ct_event!(keycode press Enter) = event {
+                                let name = state.input_state.value::<String>().trim().to_string();
+                                ctx.focus().focus(&state.pane().table_state);
+                                state.input_state.clear();
+                                state.input_mode = InputMode::default();
+                                if !name.is_empty() {
+                                    let pane = state.pane();
+                                    let mut bookmarks = Bookmarks::load()?;
+                                    bookmarks.add(&pane.label, name.clone(), pane.current_path.clone());
+                                    bookmarks.save()?;
+                                    info!(name, path = pane.current_path, "Added bookmark");
+                                }
+                                return Ok(Control::Changed);
+                            }
+                        }
+                        InputMode::CreateSymlink => {
+                            match event {
+                                ct_event!(keycode press Enter) => {
+                                    let curr_dir = state.pane().current_path.clone();
+                                    let Some(file) = state.pane().selected() else {
+                                        return Ok(Control::Continue);
+                                    };
+                                    let target = curr_dir.join(file.name());
+                                    let link = state.pane().resolve(&state.input_state.value::<String>());
+                                    let fs = Arc::clone(&state.pane().fs);
+                                    let pane = state.active;
+                                    ctx.spawn_tab_async(move |chan| async move {
+                                        info!(target, link, "Creating symlink");
+                                        fs.symlink(&target, &link).await?;
+                                        chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, curr_dir)))).await?;
                                         Ok(Control::Event(AppEvent::AsyncTick(300)))
                                     });
                                     state.input_state.clear();
@@ -845,16 +1556,14 @@ pub fn event(
                         InputMode::CreateNewFolder => {
                             match event {
                                 ct_event!(keycode press Enter) => {
-                                    let curr_dir = state.current_path.clone();
-                                    let file_name = state.input_state.value::<String>();
-                                    let path = curr_dir.join(&file_name);
-                                    let session = Arc::clone(&state.session);
-                                    ctx.spawn_async_ext(|chan| async move {
-                                        let mut session = session.lock().await;
-                                        let sftp = session.sftp().await?;
-                                        let path = sftp.canonicalize(path.clone()).await?;
-                                        sftp.create_dir(path.clone()).await?;
-                                        chan.send(Ok(Control::Event(AppEvent::ChangeDir(curr_dir)))).await?;
+                                    let curr_dir = state.pane().current_path.clone();
+                                    let path = state.pane().resolve(&state.input_state.value::<String>());
+                                    let fs = Arc::clone(&state.pane().fs);
+                                    let pane = state.active;
+                                    ctx.spawn_tab_async(move |chan| async move {
+                                        let path = fs.canonicalize(&path).await.unwrap_or(path);
+                                        fs.mkdir(&path).await?;
+                                        chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, curr_dir)))).await?;
                                         Ok(Control::Event(AppEvent::AsyncTick(300)))
                                     });
                                     state.input_state.clear();
@@ -874,14 +1583,11 @@ pub fn event(
             ));
             try_flow!(match state.input_state.handle(event, Regular) {
                 TextOutcome::TextChanged => {
+                    // The candidates were for the old text
+                    state.completer.popup = None;
                     if state.input_mode == InputMode::Filter {
                         let filter: String = state.input_state.value();
-                        state.filtered_file_entries = state
-                            .current_file_entries
-                            .iter()
-                            .filter(|file| file.name().contains(&filter))
-                            .cloned()
-                            .collect();
+                        state.pane_mut().set_filter(filter);
                     }
                     Control::Changed
                 }
@@ -898,41 +1604,68 @@ pub fn event(
             state.throbber.calc_next();
             Control::Changed
         }
-        AppEvent::SpawnExternalEditor(_) => {
-            if let Some(template) = state.current_file_content.clone() {
-                state.in_editor = true;
-                stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
-                disable_raw_mode()?;
-                let edited = edit::edit(template)?;
-                let session = Arc::clone(&state.session);
-                let current_path = state.current_path.clone();
-                let current_file = state.get_file_entries()
-                    [state.table_state.selected_checked().unwrap_or_default()]
-                .clone();
-
-                let path = current_path.join(current_file.name());
-                ctx.spawn_async_ext(async move |chan| {
-                    chan.send(Ok(Control::Event(AppEvent::UpdateContent(Some(
-                        edited.clone(),
-                    )))))
-                    .await?;
-                    let mut session = session.lock().await;
-                    let sftp = session.sftp().await?;
-                    let mut f = sftp.create(path.clone()).await?;
-                    f.write_all(edited.as_bytes()).await?;
-                    sftp.close().await?;
-                    Ok(Control::Event(AppEvent::AsyncTick(300)))
-                });
-                stdout().execute(EnterAlternateScreen)?;
-                enable_raw_mode()?;
-                ctx.terminal().borrow_mut().clear()?;
-                state.in_editor = false;
+        AppEvent::SpawnExternalEditor(path) => {
+            let fs = state.pane().file_fs();
+            let path = path.clone();
+            ctx.spawn_tab_async(async move |_| {
+                editing::check_editable(&*fs, &path).await?;
+                // Kept to tell whether the file changed by the time it is saved
+                let snapshot = Snapshot::take(&*fs, &path).await?;
+                if !snapshot.utf8 {
+                    bail!("{} is not UTF-8 text", path);
+                }
+                let template = snapshot.text.clone();
+                Ok(Control::Event(AppEvent::EditContent(Box::new(Edit {
+                    fs,
+                    snapshot,
+                    template,
+                }))))
+            });
+            Control::Continue
+        }
+        AppEvent::EditContent(edit) => {
+            state.in_editor = true;
+            stdout().execute(crossterm::terminal::LeaveAlternateScreen)?;
+            disable_raw_mode()?;
+            let name = edit.snapshot.path.rsplit('/').next().unwrap_or_default();
+            let edited = editing::run_editor(name, &edit.template, &ctx.cfg.editor);
+            // Back to the UI before reporting a failed editor
+            stdout().execute(EnterAlternateScreen)?;
+            enable_raw_mode()?;
+            ctx.terminal().borrow_mut().clear()?;
+            state.in_editor = false;
+            let edited = edited?;
+            if edited == edit.snapshot.text {
+                ctx.queue_event(AppEvent::Status(1, "No changes to save".to_string()));
+            } else {
+                let Edit { fs, snapshot, .. } = (**edit).clone();
+                editing::spawn_save(fs, snapshot, edited, ctx);
+            }
+            Control::Changed
+        }
+        AppEvent::Elevated(idx, sudo) => {
+            state.panes[*idx].sudo = Some(Arc::clone(sudo));
+            info!(host = state.panes[*idx].label, "Entered sudo mode");
+            Control::Event(AppEvent::Status(
+                1,
+                "sudo mode: files are previewed and edited as root".to_string(),
+            ))
+        }
+        AppEvent::Edited(path) => {
+            // Show the saved text if the file is still previewed
+            if state.preview.path() == Some(path.as_str()) {
+                state.preview.reload(ctx);
             }
             Control::Changed
         }
         AppEvent::SpawnSSHCommand => {
-            let path = PathBuf::from(state.current_path.clone());
-            let cli = ctx.cfg.cli.clone();
+            // Open the shell in the remote pane, preferring the active one
+            let pane = [state.pane(), state.other_pane()]
+                .into_iter()
+                .find(|pane| Arc::ptr_eq(&pane.fs, &state.remote_fs))
+                .unwrap_or(state.pane());
+            let path = PathBuf::from(pane.current_path.clone());
+            let cli = state.cli.clone();
             let mut cmd = cli.build_ssh_with_path(path);
             state.in_editor = true;
             stdout().execute(LeaveAlternateScreen)?;
@@ -951,12 +1684,16 @@ pub fn event(
         }
         AppEvent::DownloadStart => {
             state.is_downloading = true;
-            let cancel = ctx.spawn_ext(|cancel, send| {
+            let tab = ctx.tab;
+            let cancel = ctx.spawn_ext(move |cancel, send| {
                 loop {
                     if cancel.is_canceled() {
                         break;
                     }
-                    send.send(Ok(Control::Event(AppEvent::Throb)))?;
+                    send.send(Ok(Control::Event(AppEvent::Tab(
+                        tab,
+                        Box::new(AppEvent::Throb),
+                    ))))?;
                     send.send(Ok(Control::Changed))?;
                     thread::sleep(Duration::from_millis(500));
                 }
@@ -965,8 +1702,27 @@ pub fn event(
             state.throbber_cancel = Some(cancel.0);
             Control::Changed
         }
-        AppEvent::UpdateContent(content) => {
-            state.current_file_content = content.clone();
+        AppEvent::Page(page) => state
+            .preview
+            .paged(page, &mut state.details_para_state, ctx),
+        AppEvent::Polled(poll) => state.preview.polled(poll, ctx),
+        AppEvent::FileType(path, file_type) => {
+            state.file_types.insert(path.clone(), *file_type);
+            Control::Changed
+        }
+        AppEvent::Rendered => {
+            match state.detail_window_mode {
+                DetailWindowMode::Details => sniff_selected(state, ctx),
+                DetailWindowMode::Content => {
+                    if state.preview.check(&mut state.details_para_state, ctx) {
+                        return Ok(Control::Changed);
+                    }
+                }
+            }
+            Control::Continue
+        }
+        AppEvent::Highlighted(generation, start, lines) => {
+            state.preview.highlighted(*generation, *start, lines);
             Control::Changed
         }
         AppEvent::DownloadEnd => {
@@ -974,6 +1730,7 @@ pub fn event(
             if let Some(cancel) = state.throbber_cancel.take() {
                 cancel.cancel();
             }
+            state.job_throttle = None;
             state.download_progress = 0.0;
             state.downloaded_files = 0;
             state.total_files_to_download = 0;
@@ -989,73 +1746,102 @@ pub fn event(
             Control::Changed
         }
         AppEvent::MoveEntry(oldpath, newpath) => {
-            let session = Arc::clone(&state.session);
+            let fs = Arc::clone(&state.pane().fs);
             let oldpath = oldpath.clone();
             let newpath = newpath.clone();
-            let current_path = state.current_path.clone();
-            ctx.spawn_async_ext(|_| async move {
-                let mut session = session.lock().await;
-                let sftp = session.sftp().await?;
-                let newpath = sftp.canonicalize(newpath.clone()).await.unwrap_or(newpath);
+            let current_path = state.pane().current_path.clone();
+            let pane = state.active;
+            ctx.spawn_tab_async(move |_| async move {
+                let newpath = fs.canonicalize(&newpath).await.unwrap_or(newpath);
                 info!(oldpath, newpath, "Moving");
-                sftp.rename(oldpath, newpath).await?;
-                Ok(Control::Event(AppEvent::ChangeDir(current_path)))
+                fs.rename(&oldpath, &newpath).await?;
+                Ok(Control::Event(AppEvent::ChangeDir(pane, current_path)))
             });
             Control::Changed
         }
         AppEvent::DownloadFile(name, path, filename) => {
             state.throbber.calc_next();
             info!(name, path = ?path.display(), filename = ?filename.clone(), "File Details");
-            let session = Arc::clone(&state.session);
+            let session = state.pane_session();
+            let fs = Arc::clone(&state.pane().fs);
             let path = path.clone();
             let name = name.clone();
-
-            info!(name, path = ?path.display(), "File Details");
-            ctx.spawn_async_ext(|_| async move {
-                let sftp = {
-                    let mut session = session.lock().await;
-                    session.sftp().await?
-                };
-                let mut remote_file = sftp.open(name.clone()).await?;
-                let mut buf = Vec::new();
-                remote_file.read_to_end(&mut buf).await?;
-                info!(len = ?buf.len(), "Read file");
-                if let Some(parent) = path.parent() {
-                    tokio::fs::create_dir_all(parent).await?;
+            let opts = ctx.cfg.transfers;
+            let throttle = ctx.cfg.job_throttle();
+
+            ctx.spawn_tab_async(|_| async move {
+                let mut summary = TransferSummary::default();
+                if fs.whole_file_writes() {
+                    let session = session.lock().await.clone();
+                    match scp::download(&session, &name, &path, &throttle).await {
+                        Ok(count) => summary.transferred += count,
+                        Err(err) => summary.failed.push((name.clone(), err.to_string())),
+                    }
+                    return Ok(Control::Event(AppEvent::TransferSummary(summary)));
                 }
-                info!(path = ?path.display(),"Writing file to" );
-                let mut file = tokio::fs::File::create(path).await?;
-                let _ = file.write(&buf).await?;
-                file.flush().await?;
-                file.sync_all().await?;
-                Ok(Control::Event(AppEvent::AsyncTick(300)))
+                let result = transfer::download_verified(&*fs, &name, &path, opts, &throttle).await;
+                summary.record(&name, result);
+                Ok(Control::Event(AppEvent::TransferSummary(summary)))
             });
             Control::Continue
         }
         AppEvent::DownloadFolder(file, path) => {
-            ctx.queue_event(AppEvent::DownloadStart);
+            ctx.queue_tab_event(AppEvent::DownloadStart);
 
             info!("Downloading folder {}", file);
 
-            let session = Arc::clone(&state.session);
+            let session = state.pane_session();
             let dirname = file.split('/').next_back().unwrap_or("");
             std::fs::create_dir_all(path.clone())?;
             let path = path.clone().canonicalize()?;
             info!(path =?path.display(), dirname, "Path and dirname");
 
             let file = file.clone();
-            ctx.spawn_async_ext(|chan| async move {
-                    let sftp = {
-                        let mut session = session.lock().await;
-                        session.sftp().await?
-                    };
+            let opts = ctx.cfg.transfers;
+            let throttle = ctx.cfg.job_throttle();
+            state.job_throttle = Some(throttle.clone());
+            let fs = Arc::clone(&state.pane().fs);
+            ctx.spawn_tab_async(|chan| async move {
+                    if fs.whole_file_writes() {
+                        let session = session.lock().await.clone();
+                        let mut summary = TransferSummary::default();
+                        match scp::download(&session, &file, &path, &throttle).await {
+                            Ok(count) => summary.transferred += count,
+                            Err(err) => summary.failed.push((file.clone(), err.to_string())),
+                        }
+                        chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
+                        return Ok(Control::Event(AppEvent::TransferSummary(summary)));
+                    }
+                    if opts.tar_mode && !fs.is_local() {
+                        let session = session.lock().await.clone();
+                        if tar_stream::remote_has_tar(&session).await {
+                            let total = tar_stream::remote_entry_count(&session, &file).await.unwrap_or(0);
+                            chan.send(Ok(Control::Event(AppEvent::SetTotalFilesToDownload(total)))).await?;
+                            let progress_chan = chan.clone();
+                            let mut done = 0.0;
+                            let result = tar_stream::download_dir(&session, &file, &path, &throttle, move |_| {
+                                done += 1.0;
+                                let ratio = if total > 0 { (done / total as f64).min(1.0) } else { 0.0 };
+                                let _ = progress_chan.blocking_send(Ok(Control::Event(AppEvent::Gauge(ratio))));
+                            })
+                            .await;
+                            let mut summary = TransferSummary::default();
+                            match result {
+                                Ok(count) => summary.record_batch(count, opts.verify),
+                                Err(err) => summary.failed.push((file.clone(), err.to_string())),
+                            }
+                            chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
+                            return Ok(Control::Event(AppEvent::TransferSummary(summary)));
+                        }
+                        warn!("tar is not available on the remote, falling back to SFTP");
+                    }
                     let walker = WalkParallel {
                         filter: Arc::new(|_| true),
                         path: file.clone().into(),
                         max_depth: Some(3),
                         min_depth: None,
                         threads: 4,
-                        sftp: sftp.into(),
+                        fs: Arc::clone(&fs),
                     };
                     let collected = Arc::new(Mutex::new(Vec::<FileEntry>::new()));
                     let collected_ref = Arc::clone(&collected);
@@ -1085,7 +1871,8 @@ pub fn event(
                     }; // lock dropped here
 
                     // 2️⃣ Process outside of the lock
-                    let tx = start_sftp_worker(session.clone());
+                    let tx = transfer::start_worker(fs, opts, throttle);
+                    let mut summary = TransferSummary::default();
                     let total = collected_snapshot.len() as f64;
                     chan.send(Ok(Control::Event(AppEvent::SetTotalFilesToDownload(total as usize)))).await?;
                     let mut progress = 0.0;
@@ -1101,15 +1888,20 @@ pub fn event(
                         let file = file.clone();
                         let filename = entry.name().strip_prefix(&file).unwrap_or(entry.name()).replacen("/", "", 1).to_string();
                         let target_path = path.join(&filename);
-                        info!(file, filename, target_path = ?target_path.display().to_string(), "Downloading");
-
-                        let (reply_tx, reply_rx) = oneshot::channel();
-                        tx.send(SftpCmd::ReadFile {
-                            remote_path: file.clone().join(&filename),
-                            local_path: target_path,
-                            reply: reply_tx,
-                        })?;
-                        let _ = reply_rx.await?;
+                        if entry.is_dir() {
+                            tokio::fs::create_dir_all(&target_path).await?;
+                        } else {
+                            info!(file, filename, target_path = ?target_path.display().to_string(), "Downloading");
+
+                            let remote_path = file.clone().join(&filename);
+                            let (reply_tx, reply_rx) = oneshot::channel();
+                            tx.send(TransferCmd::Download {
+                                remote_path: remote_path.clone(),
+                                local_path: target_path,
+                                reply: reply_tx,
+                            })?;
+                            summary.record(&remote_path, reply_rx.await?);
+                        }
                         progress += 1.0;
                         if let Some(window) = windows.next() {
                             chan.send(Ok(Control::Event(AppEvent::UpdateNextFiveFiles(window.to_vec())))).await?;
@@ -1124,51 +1916,279 @@ pub fn event(
                         //sleep(Duration::from_millis(50)).await;
                     }
                     chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
-                    Ok(Control::Event(AppEvent::AsyncTick(300)))
+                    Ok(Control::Event(AppEvent::TransferSummary(summary)))
                 });
             Control::Continue
         }
+        AppEvent::UploadFile(local, remote) => {
+            info!(local = ?local.display(), remote, "Uploading file");
+            let session = state.pane_session();
+            let local = local.clone();
+            let remote = remote.clone();
+            let current_path = state.pane().current_path.clone();
+            let opts = ctx.cfg.transfers;
+            let throttle = ctx.cfg.job_throttle();
+            let fs = Arc::clone(&state.pane().fs);
+            let pane = state.active;
+
+            ctx.spawn_tab_async(move |chan| async move {
+                let mut summary = TransferSummary::default();
+                if fs.whole_file_writes() {
+                    let session = session.lock().await.clone();
+                    match scp::upload(&session, &local, &current_path, &throttle).await {
+                        Ok(count) => summary.transferred += count,
+                        Err(err) => summary.failed.push((remote.clone(), err.to_string())),
+                    }
+                } else {
+                    let result =
+                        transfer::upload_verified(&*fs, &local, &remote, opts, &throttle).await;
+                    summary.record(&remote, result);
+                }
+                chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, current_path))))
+                    .await?;
+                Ok(Control::Event(AppEvent::TransferSummary(summary)))
+            });
+            Control::Continue
+        }
+        AppEvent::UploadFolder(local, remote) => {
+            ctx.queue_tab_event(AppEvent::DownloadStart);
+            info!(local = ?local.display(), remote, "Uploading folder");
+
+            let session = state.pane_session();
+            let local = local.clone();
+            let remote = remote.clone();
+            let current_path = state.pane().current_path.clone();
+            let opts = ctx.cfg.transfers;
+            let throttle = ctx.cfg.job_throttle();
+            state.job_throttle = Some(throttle.clone());
+            let fs = Arc::clone(&state.pane().fs);
+            let pane = state.active;
+
+            ctx.spawn_tab_async(move |chan| async move {
+                let session = session.lock().await.clone();
+                let mut summary = TransferSummary::default();
+                if fs.whole_file_writes() {
+                    match scp::upload(&session, &local, &current_path, &throttle).await {
+                        Ok(count) => summary.transferred += count,
+                        Err(err) => summary.failed.push((remote.clone(), err.to_string())),
+                    }
+                } else if opts.tar_mode
+                    && !fs.is_local()
+                    && tar_stream::remote_has_tar(&session).await
+                {
+                    match tar_stream::upload_dir(&session, &local, &remote, &throttle).await {
+                        Ok(count) => summary.record_batch(count, opts.verify),
+                        Err(err) => summary.failed.push((remote.clone(), err.to_string())),
+                    }
+                } else {
+                    if opts.tar_mode && !fs.is_local() {
+                        warn!("tar is not available on the remote, falling back to SFTP");
+                    }
+                    if let Err(err) =
+                        transfer::upload_dir(&*fs, &local, &remote, opts, &throttle, &mut summary)
+                            .await
+                    {
+                        summary.failed.push((remote.clone(), err.to_string()));
+                    }
+                }
+                chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
+                chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, current_path))))
+                    .await?;
+                Ok(Control::Event(AppEvent::TransferSummary(summary)))
+            });
+            Control::Continue
+        }
+        AppEvent::CopyToPane(src, file, remove_source) => {
+            let (src, dst) = (*src, 1 - *src);
+            let remove_source = *remove_source;
+            let src_fs = Arc::clone(&state.panes[src].fs);
+            let dst_fs = Arc::clone(&state.panes[dst].fs);
+            let src_dir = state.panes[src].current_path.clone();
+            let dst_dir = state.panes[dst].current_path.clone();
+            let from = src_dir.join(file.name());
+            let to = dst_dir.join(file.name());
+            info!(from, to, remove_source, "Copying to the other pane");
+
+            let same_fs = Arc::ptr_eq(&src_fs, &dst_fs) || (src_fs.is_local() && dst_fs.is_local());
+            // Creating the target would truncate the source before it is read
+            if same_fs && from == to {
+                return Ok(Control::Event(AppEvent::Status(
+                    1,
+                    "Both panes show the same directory".to_string(),
+                )));
+            }
+            if same_fs && to.starts_with(&format!("{}/", from.trim_end_matches('/'))) {
+                return Ok(Control::Event(AppEvent::Status(
+                    1,
+                    format!("Cannot copy {} into itself", from),
+                )));
+            }
+            if remove_source && same_fs {
+                ctx.spawn_tab_async(move |chan| async move {
+                    src_fs.rename(&from, &to).await?;
+                    chan.send(Ok(Control::Event(AppEvent::ChangeDir(src, src_dir))))
+                        .await?;
+                    Ok(Control::Event(AppEvent::ChangeDir(dst, dst_dir)))
+                });
+                return Ok(Control::Changed);
+            }
+
+            ctx.queue_tab_event(AppEvent::DownloadStart);
+            let opts = ctx.cfg.transfers;
+            let throttle = ctx.cfg.job_throttle();
+            state.job_throttle = Some(throttle.clone());
+            ctx.spawn_tab_async(move |chan| async move {
+                let mut summary = TransferSummary::default();
+                let items = match transfer::plan_copy(&*src_fs, &from, &to).await {
+                    Ok(items) => items,
+                    Err(err) => {
+                        summary.failed.push((from.clone(), err.to_string()));
+                        chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
+                        return Ok(Control::Event(AppEvent::TransferSummary(summary)));
+                    }
+                };
+                let total = items.len();
+                chan.send(Ok(Control::Event(AppEvent::SetTotalFilesToDownload(total))))
+                    .await?;
+                for (done, item) in items.iter().enumerate() {
+                    if item.is_dir {
+                        if !dst_fs.exists(&item.to).await.unwrap_or(false)
+                            && let Err(err) = dst_fs.mkdir(&item.to).await
+                        {
+                            summary.failed.push((item.to.clone(), err.to_string()));
+                        }
+                    } else {
+                        let result = transfer::copy_verified(
+                            &*src_fs, &item.from, &*dst_fs, &item.to, opts, &throttle,
+                        )
+                        .await;
+                        summary.record(&item.to, result);
+                    }
+                    let ratio = (done + 1) as f64 / total as f64;
+                    chan.send(Ok(Control::Event(AppEvent::Gauge(ratio))))
+                        .await?;
+                }
+                if remove_source && summary.failed.is_empty() {
+                    let removed = if items.first().is_some_and(|item| item.is_dir) {
+                        remote_fs::remove_all(&*src_fs, &from).await
+                    } else {
+                        src_fs.remove_file(&from).await
+                    };
+                    if let Err(err) = removed {
+                        summary.failed.push((from.clone(), err.to_string()));
+                    }
+                }
+                chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
+                chan.send(Ok(Control::Event(AppEvent::ChangeDir(dst, dst_dir))))
+                    .await?;
+                if remove_source {
+                    chan.send(Ok(Control::Event(AppEvent::ChangeDir(src, src_dir))))
+                        .await?;
+                }
+                Ok(Control::Event(AppEvent::TransferSummary(summary)))
+            });
+            Control::Changed
+        }
+        AppEvent::CopyDifferences(plan) => {
+            let plan = (**plan).clone();
+            info!(from = ?plan.from, to = ?plan.to, items = plan.items.len(), "Copying differences");
+            let panes: Vec<String> = state
+                .panes
+                .iter()
+                .map(|pane| pane.current_path.clone())
+                .collect();
+            ctx.queue_tab_event(AppEvent::DownloadStart);
+            let opts = ctx.cfg.transfers;
+            let throttle = ctx.cfg.job_throttle();
+            state.job_throttle = Some(throttle.clone());
+            ctx.spawn_tab_async(move |chan| async move {
+                let (src_fs, dst_fs) = (&plan.from.fs, &plan.to.fs);
+                let mut summary = TransferSummary::default();
+                summary.failed.extend(plan.skipped.iter().cloned());
+                let total = plan.items.len();
+                chan.send(Ok(Control::Event(AppEvent::SetTotalFilesToDownload(total))))
+                    .await?;
+                for (done, item) in plan.items.iter().enumerate() {
+                    if item.is_dir {
+                        if let Err(err) = dst_fs.mkdir(&item.to).await {
+                            summary.failed.push((item.to.clone(), err.to_string()));
+                        }
+                    } else if dst_fs.exists(&item.to).await.unwrap_or(true) {
+                        // A file that differs, replaced keeping the old one
+                        let result = transfer::overwrite_verified(
+                            &**src_fs, &item.from, &**dst_fs, &item.to, opts, &throttle,
+                        )
+                        .await;
+                        summary.record(&item.to, result.map(|(report, _)| report));
+                    } else {
+                        let result = transfer::copy_verified(
+                            &**src_fs, &item.from, &**dst_fs, &item.to, opts, &throttle,
+                        )
+                        .await;
+                        summary.record(&item.to, result);
+                    }
+                    let ratio = (done + 1) as f64 / total as f64;
+                    chan.send(Ok(Control::Event(AppEvent::Gauge(ratio))))
+                        .await?;
+                }
+                chan.send(Ok(Control::Event(AppEvent::DownloadEnd))).await?;
+                for (pane, path) in panes.into_iter().enumerate() {
+                    chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, path))))
+                        .await?;
+                }
+                chan.send(Ok(Control::Event(AppEvent::TransferSummary(summary))))
+                    .await?;
+                let comparison = dir_compare::compare(plan.left, plan.right).await;
+                Ok(Control::Event(AppEvent::Compared(Box::new(comparison))))
+            });
+            Control::Changed
+        }
         AppEvent::DeleteEntry(file) => {
-            let session = Arc::clone(&state.session);
+            let fs = Arc::clone(&state.pane().fs);
             let file = file.clone();
-            let curr_path = state.current_path.clone();
+            let curr_path = state.pane().current_path.clone();
             let fname = curr_path.join(file.name());
-            ctx.spawn_async_ext(|chan| async move {
-                let mut session = session.lock().await;
-                let sftp = session.sftp().await?;
+            let pane = state.active;
+            ctx.spawn_tab_async(move |chan| async move {
                 info!(fname, "Deleting");
                 match file.type_() {
                     FileType::File => {
-                        sftp.remove_file(fname).await?;
+                        fs.remove_file(&fname).await?;
                     }
                     FileType::Dir => {
-                        remove_dir_recursive(&sftp, &fname).await?;
+                        remote_fs::remove_all(&*fs, &fname).await?;
                     }
                     _ => {}
                 }
-                chan.send(Ok(Control::Event(AppEvent::ChangeDir(curr_path.clone()))))
-                    .await?;
+                chan.send(Ok(Control::Event(AppEvent::ChangeDir(
+                    pane,
+                    curr_path.clone(),
+                ))))
+                .await?;
 
                 Ok(Control::Changed)
             });
 
             Control::Changed
         }
-        AppEvent::ChangeDir(path) => {
+        AppEvent::ChangeDir(pane, path) => {
             let path = if !path.is_empty() {
                 path.clone()
             } else {
                 ".".to_string()
             };
             info!("changing dir to {}", path);
-            let sftp = Arc::clone(&state.sftp);
-            state.input_state.clear();
-            state.input_mode = InputMode::default();
-
-            ctx.spawn_async_ext(|chan| async move {
-                let files = sftp.read_dir(path).await?;
-                let files = files.into_iter().map(FileEntry::from).collect::<Vec<_>>();
-                chan.send(Ok(Control::Event(AppEvent::UpdateFiles(files))))
+            let pane = *pane;
+            let fs = Arc::clone(&state.panes[pane].fs);
+            let columns = ctx.cfg.columns.clone();
+            if state.input_mode != InputMode::Filter {
+                state.input_state.clear();
+                state.input_mode = InputMode::default();
+            }
+
+            ctx.spawn_tab_async(move |chan| async move {
+                let files = remote_fs::list_for(&fs, &path, &columns).await?;
+                chan.send(Ok(Control::Event(AppEvent::UpdateFiles(pane, files))))
                     .await?;
 
                 Ok(Control::Event(AppEvent::AsyncTick(300)))
@@ -1179,76 +2199,58 @@ pub fn event(
             state.total_files_to_download = *total;
             Control::Changed
         }
-        AppEvent::UpdateFiles(files) => {
-            state.current_file_entries = files.to_vec();
-            state.input_state.clear();
-            state.input_mode = InputMode::default();
+        AppEvent::UpdateFiles(pane, files) => {
+            state.panes[*pane].set_entries(files.to_vec());
+            if state.input_mode != InputMode::Filter {
+                state.input_state.clear();
+                state.input_mode = InputMode::default();
+            }
             Control::Changed
         }
-        AppEvent::UpdateCurrentPath(path) => {
-            state.current_path = path.clone();
+        AppEvent::GoTo(pane, path) => {
+            state.panes[*pane].navigate(path.clone());
+            load_pane(state, *pane, ctx);
+            Control::Changed
+        }
+        AppEvent::Reveal(pane, path) => {
+            let path = PathBuf::from(path);
+            let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                return Ok(Control::Continue);
+            };
+            let pane = *pane;
+            state.panes[pane].navigate(dir.display().to_string());
+            state.panes[pane].reveal = Some(name.to_string_lossy().to_string());
+            load_pane(state, pane, ctx);
+            Control::Changed
+        }
+        AppEvent::Listing(key, input, listing) => {
+            state.completer.store(key.clone(), listing.clone());
+            // Apply only if the prompt is still where it was
+            if let Some(source) = completion_source(state)
+                && state.input_state.value::<String>() == *input
+            {
+                apply_completion(state, input, listing, source.dirs_only);
+            }
+            Control::Changed
+        }
+        AppEvent::UpdateCurrentPath(pane, path) => {
+            state.panes[*pane].current_path = path.clone();
             Control::Continue
         }
+        AppEvent::TransferSummary(summary) => {
+            info!("{}", summary);
+            if summary.needs_attention() {
+                Control::Event(AppEvent::Message(summary.to_string()))
+            } else {
+                Control::Changed
+            }
+        }
 
         _ => Control::Continue,
     };
 
     Ok(r)
 }
-use tokio::sync::{mpsc, oneshot};
-
-enum SftpCmd {
-    ReadFile {
-        remote_path: String,
-        local_path: PathBuf,
-        reply: oneshot::Sender<Result<()>>,
-    },
-}
-
-fn start_sftp_worker(session: Arc<AsyncMutex<Session>>) -> mpsc::UnboundedSender<SftpCmd> {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        let _: Result<()> = rt.block_on(async move {
-            while let Some(cmd) = rx.recv().await {
-                match cmd {
-                    SftpCmd::ReadFile {
-                        remote_path,
-                        local_path,
-                        reply,
-                    } => {
-                        let result = async {
-                            let mut session = session.lock().await;
-                            info!("Opening remote file {:?}", remote_path);
-                            let sftp = session.sftp().await?;
-                            info!("Got SFTP channel");
-                            let mut remote_file = sftp.open(&remote_path).await?;
-                            info!("Opened remote file");
-                            let mut buf = Vec::new();
-                            let read = remote_file.read_to_end(&mut buf).await;
-
-                            if read.is_err() {
-                                error!("Read result: {:?}", read);
-                            } else {
-                                info!("Read result: {:?}", read);
-                            }
-                            let mut file = tokio::fs::File::create(local_path).await?;
-                            file.write_all(&buf).await?;
-                            file.flush().await?;
-                            file.sync_all().await?;
-                            eyre::Ok(())
-                        }
-                        .await;
-                        let _ = reply.send(result);
-                    }
-                }
-            }
-            eyre::Ok(())
-        });
-        eyre::Ok(())
-    });
-    tx
-}
 
 #[inline]
 fn keybind<'a>(key: &'a str, description: &str) -> Vec<Span<'a>> {
@@ -1261,26 +2263,3 @@ fn keybind<'a>(key: &'a str, description: &str) -> Vec<Span<'a>> {
         ),
     ]
 }
-
-async fn remove_dir_recursive(sftp: &SftpSession, root: &str) -> Result<()> {
-    let mut stack = vec![root.to_string()];
-
-    while let Some(path) = stack.pop() {
-        let entries = sftp.read_dir(&path).await?;
-        for entry in entries {
-            let attrs = entry.metadata();
-            let name = entry.file_name();
-            let child_path = format!("{}/{}", path, name);
-            if attrs.is_dir() {
-                // Push directory for later deletion
-                stack.push(child_path.clone());
-            } else {
-                sftp.remove_file(&child_path).await?;
-            }
-        }
-        // Once children are deleted, remove dir
-        sftp.remove_dir(&path).await?;
-    }
-
-    Ok(())
-}
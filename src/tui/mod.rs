@@ -1,18 +1,30 @@
+use self::bookmarks::BookmarkPicker;
+use self::column_picker::ColumnPicker;
+use self::diff_view::DiffView;
+use self::dir_compare::DirCompare;
+use self::editing::ConflictDialog;
+use self::finder::Finder;
+use self::host_picker::HostPicker;
 use self::main_ui::MainUI;
+use self::permissions::PermissionsDialog;
+use self::sudo::SudoPrompt;
+use self::transfers::TransferPanel;
 use crate::cli::ResolvedConnectArgs;
-use crate::config::Theme;
-use crate::files::FileEntry;
-use crate::ssh::Session;
-use async_lock::Mutex;
+use crate::config::{EditorSettings, Settings, SortSettings, Theme};
+use crate::files::{Column, FileEntry};
+use crate::remote_fs::{self, Connection, SudoFs};
+use crate::transfer::rate_limit::{RateLimiter, Throttle};
+use crate::transfer::{TransferOptions, TransferSummary};
 use color_eyre::Report as Error;
 use color_eyre::eyre::Result;
 use rat_salsa::event::RenderedEvent;
 use rat_salsa::poll::{PollCrossterm, PollRendered, PollTasks, PollTimers};
+use rat_salsa::tasks::Liveness;
 use rat_salsa::timer::TimeOut;
 use rat_salsa::{Control, RunConfig, SalsaAppContext, SalsaContext, run_tui};
 use rat_theme3::{SalsaTheme, create_theme};
 use rat_widget::event::{ConsumedEvent, Dialog, HandleEvent, Regular, ct_event};
-use rat_widget::focus::FocusBuilder;
+use rat_widget::focus::{FocusBuilder, HasFocus};
 use rat_widget::layout::layout_middle;
 use rat_widget::msgdialog::{MsgDialog, MsgDialogState};
 use rat_widget::statusline::{StatusLine, StatusLineState};
@@ -22,29 +34,70 @@ use ratatui::crossterm::terminal::{LeaveAlternateScreen, disable_raw_mode};
 use ratatui::crossterm::{self, ExecutableCommand};
 use ratatui::layout::{Constraint, Layout, Rect};
 use ratatui::widgets::StatefulWidget;
-use russh_sftp::client::SftpSession;
 use std::io::stdout;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime};
-use tracing::error;
+use tokio::sync::mpsc::{self, Sender};
+use tokio::task::AbortHandle;
+use tracing::{error, info};
+pub mod bookmarks;
+pub mod column_picker;
+pub mod completion;
+pub mod diff_view;
+pub mod dir_compare;
+pub mod editing;
+pub mod finder;
+pub mod hex_view;
+pub mod host_picker;
 pub mod main_ui;
+pub mod paging;
+pub mod pane;
+pub mod permissions;
+pub mod preview;
+pub mod sudo;
+pub mod transfers;
+
+/// How long quitting waits for each session to disconnect.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A connected host that the panes of every tab can browse.
+#[derive(Debug, Clone)]
+pub struct OpenHost {
+    pub label: String,
+    pub connection: Connection,
+}
+
+/// Identifies a tab. Unlike its position, the id stays the same while
+/// other tabs are opened and closed.
+pub type TabId = u64;
 
 pub fn tui(
     current_path: String,
     cli: ResolvedConnectArgs,
     rt: tokio::runtime::Runtime,
-    sftp: Arc<SftpSession>,
-    session: Arc<Mutex<Session>>,
-    theme: &Theme,
+    connection: Connection,
+    settings: &Settings,
 ) -> Result<(), Error> {
-    let config = Config::new(cli);
-    let theme = match theme {
+    let mut transfers = TransferOptions::from(settings.get_transfers());
+    transfers.verify |= cli.verify;
+    transfers.tar_mode |= cli.tar;
+    transfers.resume |= cli.resume;
+    if let Some(rate) = cli.limit_rate {
+        transfers.limit_rate = (rate > 0).then_some(rate);
+    }
+    let config = Config::new(
+        transfers,
+        settings.get_sort(),
+        settings.get_table().columns.clone(),
+        settings.get_editor().clone(),
+    );
+    let theme = match settings.get_theme() {
         Theme::Custom(c) => c.clone().into(),
         Theme::Default(d) => create_theme(&d.to_string()).expect("theme"),
     };
     let mut global = Global::new(config, theme);
-    let mut state = Scenery::new(current_path, sftp, session);
+    let mut state = Scenery::new(current_path, cli, connection);
 
     run_tui(
         init, //
@@ -70,6 +123,10 @@ pub struct Global {
     pub ctx: SalsaAppContext<AppEvent, Error>,
     pub cfg: Config,
     pub theme: Box<dyn SalsaTheme>,
+    /// The tab whose events are being handled.
+    pub tab: TabId,
+    /// The hosts of all tabs.
+    pub hosts: Vec<OpenHost>,
 }
 
 impl SalsaContext<AppEvent, Error> for Global {
@@ -88,19 +145,89 @@ impl Global {
             ctx: Default::default(),
             cfg,
             theme,
+            tab: 0,
+            hosts: Vec::new(),
         }
     }
+
+    /// Addresses `event` to the tab being handled.
+    pub fn tab_event(&self, event: AppEvent) -> AppEvent {
+        AppEvent::Tab(self.tab, Box::new(event))
+    }
+
+    /// Queues `event` for the tab being handled.
+    pub fn queue_tab_event(&self, event: AppEvent) {
+        self.queue_event(self.tab_event(event));
+    }
+
+    /// Like `spawn_async_ext`, but everything the task sends back goes to
+    /// the tab being handled, even if another tab is shown by then.
+    pub fn spawn_tab_async<C, F>(&self, cr_future: C) -> (AbortHandle, Liveness)
+    where
+        C: FnOnce(Sender<Result<Control<AppEvent>, Error>>) -> F,
+        F: Future<Output = Result<Control<AppEvent>, Error>> + Send + 'static,
+    {
+        let tab = self.tab;
+        let (tx, mut rx) = mpsc::channel(64);
+        let future = cr_future(tx);
+        self.spawn_async_ext(move |chan| async move {
+            let forward = async {
+                while let Some(msg) = rx.recv().await {
+                    if chan.send(for_tab(tab, msg)).await.is_err() {
+                        break;
+                    }
+                }
+            };
+            let (result, ()) = tokio::join!(future, forward);
+            for_tab(tab, result)
+        })
+    }
+}
+
+fn for_tab(tab: TabId, msg: Result<Control<AppEvent>, Error>) -> Result<Control<AppEvent>, Error> {
+    match msg {
+        Ok(Control::Event(event)) => Ok(Control::Event(AppEvent::Tab(tab, Box::new(event)))),
+        msg => msg,
+    }
 }
 
 /// Configuration.
 #[derive(Debug, Default)]
 pub struct Config {
-    pub(crate) cli: ResolvedConnectArgs,
+    pub(crate) transfers: TransferOptions,
+    /// Bandwidth limit shared by every transfer.
+    pub(crate) limiter: RateLimiter,
+    /// Sort order new panes start with.
+    pub(crate) sort: SortSettings,
+    /// Columns of the file tables, toggled at runtime with the column picker.
+    pub(crate) columns: Vec<Column>,
+    /// Editors for remote files by extension.
+    pub(crate) editor: EditorSettings,
 }
 
 impl Config {
-    pub fn new(cli: ResolvedConnectArgs) -> Self {
-        Self { cli }
+    pub fn new(
+        transfers: TransferOptions,
+        sort: SortSettings,
+        columns: Vec<Column>,
+        editor: EditorSettings,
+    ) -> Self {
+        let limiter = RateLimiter::new(transfers.limit_rate);
+        Self {
+            transfers,
+            limiter,
+            sort,
+            columns,
+            editor,
+        }
+    }
+
+    /// A throttle for a new transfer job, bound by the global limit.
+    pub fn job_throttle(&self) -> Throttle {
+        Throttle::new(
+            self.limiter.clone(),
+            RateLimiter::new(self.transfers.job_limit_rate),
+        )
     }
 }
 
@@ -110,27 +237,86 @@ impl Config {
 pub enum AppEvent {
     Timer(TimeOut),
     Event(crossterm::event::Event),
-    ChangeDir(String),
+    /// Lists a directory into the pane with the given index.
+    ChangeDir(usize, String),
     DownloadStart,
     DownloadEnd,
-    UpdateCurrentPath(String),
+    UpdateCurrentPath(usize, String),
     Throb,
     Gauge(f64),
     SetTotalFilesToDownload(usize),
-    UpdateContent(Option<String>),
-    UpdateFiles(Vec<FileEntry>),
+    /// A page of the previewed file.
+    Page(preview::Page),
+    /// The size of the followed file.
+    Polled(preview::Poll),
+    /// The type guessed for the file at a path, from its first bytes.
+    FileType(String, Option<&'static str>),
+    UpdateFiles(usize, Vec<FileEntry>),
     SpawnExternalEditor(String),
+    /// A file read for editing, to open in the editor.
+    EditContent(Box<editing::Edit>),
+    /// A file was saved from the editor, by path.
+    Edited(String),
+    /// Edits could not be saved since the file changed meanwhile.
+    EditConflict(Box<editing::Conflict>),
+    /// Asks for the sudo password of the host the pane with the given index
+    /// browses.
+    AskSudoPassword(usize),
+    /// The pane with the given index switched to sudo mode.
+    Elevated(usize, Arc<SudoFs>),
+    /// Two files read for comparing.
+    Diff(Box<diff_view::Loaded>),
+    /// Two directory trees walked and compared.
+    Compared(Box<dir_compare::Comparison>),
+    /// Copies differences between two compared trees, comparing them again
+    /// afterwards.
+    CopyDifferences(Box<dir_compare::CopyPlan>),
     SpawnSSHCommand,
     DownloadFile(String, PathBuf, Option<String>),
     DownloadFolder(String, PathBuf),
+    UploadFile(PathBuf, String),
+    UploadFolder(PathBuf, String),
     DeleteEntry(FileEntry),
     MoveEntry(String, String),
+    /// Copies an entry of the given pane into the other pane's directory,
+    /// removing the source afterwards if the flag is set.
+    CopyToPane(usize, FileEntry, bool),
     Rendered,
     Message(String),
     Status(usize, String),
     AsyncMsg(String),
     UpdateNextFiveFiles(Vec<FileEntry>),
     AsyncTick(u32),
+    TransferSummary(TransferSummary),
+    /// An event for the tab with the given id rather than the shown one.
+    Tab(TabId, Box<AppEvent>),
+    /// Opens a connection in a new tab.
+    Connect(Box<ResolvedConnectArgs>),
+    Connected(Box<ResolvedConnectArgs>, Connection),
+    /// Opens the column picker.
+    EditColumns,
+    /// Opens the panel listing the running transfers.
+    OpenTransfers,
+    /// The table columns were toggled, the panes need their details again.
+    ColumnsChanged,
+    /// Opens the permissions dialog for the marked entries of a pane.
+    EditPermissions(usize),
+    /// Opens a directory in the pane with the given index, remembering the
+    /// current one in its history.
+    GoTo(usize, String),
+    /// Opens the bookmarks of the host the pane with the given index browses.
+    OpenBookmarks(usize),
+    /// A directory listing for completing the given prompt text.
+    Listing(completion::CacheKey, String, Vec<completion::Candidate>),
+    /// Paths found by the walk indexing a tree for the finder, the flag is
+    /// set on the last batch.
+    Indexed(finder::IndexKey, Vec<String>, bool),
+    /// Opens the directory holding a file in the pane with the given index
+    /// and selects the file.
+    Reveal(usize, String),
+    /// Lines of the preview highlighted, for the given generation of it and
+    /// the offset its loaded lines start at.
+    Highlighted(u64, u64, Vec<ratatui::text::Line<'static>>),
 }
 
 impl From<RenderedEvent> for AppEvent {
@@ -153,19 +339,96 @@ impl From<crossterm::event::Event> for AppEvent {
 
 // #[derive(Debug, Default)]
 pub struct Scenery {
-    pub async1: MainUI,
+    /// One browser per connected host.
+    pub tabs: Vec<MainUI>,
+    pub active_tab: usize,
+    next_tab_id: TabId,
+    pub host_picker: HostPicker,
+    pub column_picker: ColumnPicker,
+    pub permissions: PermissionsDialog,
+    pub diff: DiffView,
+    pub dir_compare: DirCompare,
+    pub conflict: ConflictDialog,
+    pub sudo: SudoPrompt,
+    pub transfers: TransferPanel,
+    pub bookmarks: BookmarkPicker,
+    pub finder: Finder,
     pub status: StatusLineState,
     pub error_dlg: MsgDialogState,
+    /// Set once the sessions are being closed on the way out.
+    quitting: bool,
 }
 
 impl Scenery {
-    pub fn new(current_path: String, sftp: Arc<SftpSession>, session: Arc<Mutex<Session>>) -> Self {
+    pub fn new(current_path: String, cli: ResolvedConnectArgs, connection: Connection) -> Self {
         Self {
-            async1: MainUI::new(current_path, sftp, session),
+            tabs: vec![MainUI::new(0, cli, current_path, connection)],
+            active_tab: 0,
+            next_tab_id: 1,
+            host_picker: HostPicker::default(),
+            column_picker: ColumnPicker::default(),
+            permissions: PermissionsDialog::default(),
+            diff: DiffView::default(),
+            dir_compare: DirCompare::default(),
+            conflict: ConflictDialog::default(),
+            sudo: SudoPrompt::default(),
+            transfers: TransferPanel::default(),
+            bookmarks: BookmarkPicker::default(),
+            finder: Finder::default(),
             status: StatusLineState::default(),
             error_dlg: MsgDialogState::default(),
+            quitting: false,
+        }
+    }
+
+    pub fn tab(&self) -> &MainUI {
+        &self.tabs[self.active_tab]
+    }
+
+    pub fn tab_mut(&mut self) -> &mut MainUI {
+        &mut self.tabs[self.active_tab]
+    }
+}
+
+/// Shows tab `idx` and moves the focus into it.
+fn switch_tab(state: &mut Scenery, ctx: &mut Global, idx: usize) {
+    state.active_tab = idx;
+    ctx.tab = state.tab().id;
+    ctx.set_focus(FocusBuilder::build_for(state.tab()));
+    ctx.focus().focus(&state.tab().pane().table_state);
+}
+
+fn sync_hosts(state: &Scenery, ctx: &mut Global) {
+    ctx.hosts = state.tabs.iter().map(MainUI::host).collect();
+}
+
+/// Closes the shown tab and its connection, unless it is the last one.
+fn close_tab(state: &mut Scenery, ctx: &mut Global) {
+    if state.tabs.len() == 1 {
+        return;
+    }
+    let mut tab = state.tabs.remove(state.active_tab);
+    if let Some(cancel) = tab.throbber_cancel.take() {
+        cancel.cancel();
+    }
+    info!(host = tab.cli.host, "Closing tab");
+    sync_hosts(state, ctx);
+    // Panes of other tabs browsing the closed host go back to their own
+    for other in &mut state.tabs {
+        ctx.tab = other.id;
+        for idx in 0..other.panes.len() {
+            if Arc::ptr_eq(&other.panes[idx].fs, &tab.remote_fs) {
+                let host = other.host();
+                other.panes[idx].browse(Some(&host), ".".to_string());
+                main_ui::load_pane(other, idx, ctx);
+            }
         }
     }
+    ctx.spawn_async_ext(async move |_| {
+        tab.session.lock().await.close().await?;
+        Ok(Control::Changed)
+    });
+    switch_tab(state, ctx, state.active_tab.min(state.tabs.len() - 1));
 }
 
 pub fn render(
@@ -183,7 +446,39 @@ pub fn render(
         Constraint::Length(1),
     ])
     .split(area);
-    main_ui::render(layout[0], buf, &mut state.async1, ctx)?;
+    ctx.tab = state.tab().id;
+    main_ui::render(layout[0], buf, state.tab_mut(), ctx)?;
+
+    if state.host_picker.active {
+        host_picker::render(layout[0], buf, &mut state.host_picker, ctx);
+    }
+    if state.column_picker.active {
+        column_picker::render(layout[0], buf, &mut state.column_picker, ctx);
+    }
+    if state.permissions.active {
+        permissions::render(layout[0], buf, &mut state.permissions, ctx);
+    }
+    if state.bookmarks.active {
+        bookmarks::render(layout[0], buf, &mut state.bookmarks, ctx);
+    }
+    if state.finder.active {
+        finder::render(layout[0], buf, &mut state.finder, ctx);
+    }
+    if state.dir_compare.active {
+        dir_compare::render(layout[0], buf, &mut state.dir_compare, ctx);
+    }
+    if state.diff.active {
+        diff_view::render(layout[0], buf, &mut state.diff, ctx);
+    }
+    if state.conflict.active {
+        editing::render(layout[0], buf, &mut state.conflict, ctx);
+    }
+    if state.sudo.active {
+        sudo::render(layout[0], buf, &mut state.sudo, ctx);
+    }
+    if state.transfers.active {
+        transfers::render(layout[0], buf, &mut state.transfers, &state.tabs, ctx);
+    }
 
     if state.error_dlg.active() {
         MsgDialog::new()
@@ -204,11 +499,38 @@ pub fn render(
     let el = t0.elapsed().unwrap_or(Duration::from_nanos(0));
 
     state.status.status(1, format!("R {:.0?}", el).to_string());
+    let tabs = state
+        .tabs
+        .iter()
+        .enumerate()
+        .map(|(idx, tab)| {
+            let label = format!("{}:{}", idx + 1, tab.cli.host);
+            if idx == state.active_tab {
+                format!("[{label}]")
+            } else {
+                label
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let tab = state.tab();
+    let fs = &tab.remote_fs;
     let remote_host_details = format!(
-        "  Connected to {}@{}:{}  ",
-        ctx.cfg.cli.username.as_ref().map_or("root", |s| s.as_str()),
-        ctx.cfg.cli.host.as_str(),
-        ctx.cfg.cli.port
+        "  {}  Connected to {}@{}:{}{}{}  ",
+        tabs,
+        tab.cli.username.as_ref().map_or("root", |s| s.as_str()),
+        tab.cli.host.as_str(),
+        tab.cli.port,
+        if fs.whole_file_writes() {
+            format!(" [{}, transfers only]", fs.kind())
+        } else {
+            String::new()
+        },
+        if tab.pane().sudo.is_some() {
+            " [sudo]"
+        } else {
+            ""
+        }
     );
     let len = remote_host_details.len();
     state.status.status(3, remote_host_details);
@@ -227,8 +549,10 @@ pub fn render(
 }
 
 pub fn init(state: &mut Scenery, ctx: &mut Global) -> Result<(), Error> {
-    ctx.set_focus(FocusBuilder::build_for(&state.async1));
-    main_ui::init(&mut state.async1, ctx)?;
+    ctx.tab = state.tab().id;
+    sync_hosts(state, ctx);
+    ctx.set_focus(FocusBuilder::build_for(state.tab()));
+    main_ui::init(state.tab_mut(), ctx)?;
     Ok(())
 }
 
@@ -238,46 +562,201 @@ pub fn event(
     ctx: &mut Global,
 ) -> Result<Control<AppEvent>, Error> {
     let t0 = SystemTime::now();
-    if state.async1.in_editor {
+    if state.tabs.iter().any(|tab| tab.in_editor) {
         return Ok(Control::Changed);
     }
 
+    // Untagged events belong to the shown tab
+    let (tab_id, event) = match event {
+        AppEvent::Tab(id, event) => (*id, &**event),
+        event => (state.tab().id, event),
+    };
+
     let mut r = match event {
         AppEvent::Event(event) => {
-            let mut r = match &event {
+            let r = match &event {
                 ct_event!(resized) => Control::Changed,
+                // A second Ctrl-Q doesn't wait for the sessions any more
+                ct_event!(key press CONTROL-'q') if state.quitting => Control::Quit,
                 ct_event!(key press CONTROL-'q') => {
-                    if let Some(cancel) = state.async1.throbber_cancel.take() {
-                        cancel.cancel();
+                    state.quitting = true;
+                    let mut sessions = Vec::new();
+                    for tab in &mut state.tabs {
+                        if let Some(cancel) = tab.throbber_cancel.take() {
+                            cancel.cancel();
+                        }
+                        sessions.push(Arc::clone(&tab.session));
                     }
-                    let session = Arc::clone(&state.async1.session);
+                    // Quit once every session is disconnected cleanly
                     ctx.spawn_async_ext(async move |_| {
-                        let mut session = session.lock().await;
-                        session.close().await?;
+                        for session in sessions {
+                            let session = session.lock().await.clone();
+                            match tokio::time::timeout(CLOSE_TIMEOUT, session.close()).await {
+                                Ok(Ok(())) => {}
+                                Ok(Err(err)) => error!("Closing the session failed: {:?}", err),
+                                Err(_) => error!("Closing the session timed out"),
+                            }
+                        }
                         Ok(Control::Quit)
                     });
-                    Control::Quit
+                    Control::Event(AppEvent::Status(1, "Disconnecting...".to_string()))
+                }
+                _ if state.error_dlg.active() => state.error_dlg.handle(event, Dialog).into(),
+                _ if state.host_picker.active => host_picker::event(event, &mut state.host_picker)?,
+                _ if state.column_picker.active => {
+                    column_picker::event(event, &mut state.column_picker, ctx)?
+                }
+                _ if state.permissions.active => {
+                    permissions::event(event, &mut state.permissions, ctx)?
+                }
+                _ if state.bookmarks.active => bookmarks::event(event, &mut state.bookmarks)?,
+                _ if state.finder.active => finder::event(event, &mut state.finder)?,
+                _ if state.conflict.active => editing::event(event, &mut state.conflict, ctx)?,
+                _ if state.diff.active => diff_view::event(event, &mut state.diff, ctx)?,
+                _ if state.dir_compare.active => {
+                    dir_compare::event(event, &mut state.dir_compare, ctx)?
+                }
+                _ if state.sudo.active => sudo::event(event, &mut state.sudo, ctx)?,
+                _ if state.transfers.active => {
+                    transfers::event(event, &mut state.transfers, &state.tabs, ctx)?
+                }
+                ct_event!(keycode press Tab) | ct_event!(keycode press SHIFT-BackTab)
+                    if state.tab().input_state.is_focused()
+                        && main_ui::completion_source(state.tab()).is_some() =>
+                {
+                    let forward = matches!(event, ct_event!(keycode press Tab));
+                    main_ui::complete(state.tab_mut(), forward, ctx);
+                    Control::Changed
+                }
+                ct_event!(keycode press Tab)
+                    if state
+                        .tab()
+                        .panes
+                        .iter()
+                        .any(|pane| pane.table_state.is_focused()) =>
+                {
+                    main_ui::switch_pane(state.tab_mut(), ctx);
+                    Control::Changed
+                }
+                ct_event!(key press CONTROL-'t') => {
+                    state.host_picker.open()?;
+                    Control::Changed
+                }
+                ct_event!(key press CONTROL-'p') => {
+                    let tab = &state.tabs[state.active_tab];
+                    state.finder.open(tab.active, tab.pane(), ctx);
+                    Control::Changed
+                }
+                ct_event!(key press CONTROL-'w') => {
+                    close_tab(state, ctx);
+                    Control::Changed
+                }
+                ct_event!(keycode press CONTROL-Right) => {
+                    switch_tab(state, ctx, (state.active_tab + 1) % state.tabs.len());
+                    Control::Changed
+                }
+                ct_event!(keycode press CONTROL-Left) => {
+                    let len = state.tabs.len();
+                    switch_tab(state, ctx, (state.active_tab + len - 1) % len);
+                    Control::Changed
                 }
                 _ => Control::Continue,
             };
 
-            r = r.or_else(|| {
-                if state.error_dlg.active() {
-                    state.error_dlg.handle(event, Dialog).into()
-                } else {
-                    Control::Continue
-                }
-            });
-
-            let f = ctx.focus_mut().handle(event, Regular);
-            ctx.queue(f);
+            if !r.is_consumed() {
+                let f = ctx.focus_mut().handle(event, Regular);
+                ctx.queue(f);
+            }
 
             r
         }
         AppEvent::Rendered => {
-            ctx.set_focus(FocusBuilder::rebuild_for(&state.async1, ctx.take_focus()));
+            ctx.set_focus(FocusBuilder::rebuild_for(state.tab(), ctx.take_focus()));
             Control::Continue
         }
+        AppEvent::Connect(cli) => {
+            info!(host = cli.host, "Connecting in a new tab");
+            let cli = cli.clone();
+            ctx.spawn_async_ext(move |_| async move {
+                let connection = remote_fs::connect(&cli).await?;
+                Ok(Control::Event(AppEvent::Connected(cli, connection)))
+            });
+            Control::Changed
+        }
+        AppEvent::Connected(cli, connection) => {
+            let id = state.next_tab_id;
+            state.next_tab_id += 1;
+            let current_path = cli.path.display().to_string();
+            state.tabs.push(MainUI::new(
+                id,
+                (**cli).clone(),
+                current_path,
+                connection.clone(),
+            ));
+            sync_hosts(state, ctx);
+            switch_tab(state, ctx, state.tabs.len() - 1);
+            main_ui::init(state.tab_mut(), ctx)?;
+            Control::Changed
+        }
+        AppEvent::EditColumns => {
+            state.column_picker.open();
+            Control::Changed
+        }
+        AppEvent::OpenTransfers => {
+            state.transfers.open();
+            Control::Changed
+        }
+        AppEvent::EditPermissions(pane) => {
+            // Only the shown tab asks for the dialog
+            if tab_id == state.tab().id {
+                state
+                    .permissions
+                    .open(*pane, &state.tabs[state.active_tab].panes[*pane]);
+            }
+            Control::Changed
+        }
+        AppEvent::Diff(loaded) => {
+            state.diff.open((**loaded).clone());
+            Control::Changed
+        }
+        AppEvent::Compared(comparison) => {
+            state.dir_compare.open((**comparison).clone());
+            Control::Changed
+        }
+        AppEvent::AskSudoPassword(pane) => {
+            if tab_id == state.tab().id {
+                state
+                    .sudo
+                    .open(*pane, &state.tabs[state.active_tab].panes[*pane]);
+            }
+            Control::Changed
+        }
+        AppEvent::OpenBookmarks(pane) => {
+            if tab_id == state.tab().id {
+                let label = &state.tabs[state.active_tab].panes[*pane].label;
+                state.bookmarks.open(*pane, label)?;
+            }
+            Control::Changed
+        }
+        AppEvent::EditConflict(conflict) => {
+            // Asked whichever tab is shown, the edits are lost otherwise
+            state.conflict.open((**conflict).clone());
+            Control::Changed
+        }
+        AppEvent::Indexed(key, batch, done) => {
+            state.finder.indexed(key, batch.clone(), *done);
+            Control::Changed
+        }
+        AppEvent::ColumnsChanged => {
+            for tab in &state.tabs {
+                ctx.tab = tab.id;
+                for idx in 0..tab.panes.len() {
+                    main_ui::load_pane(tab, idx, ctx);
+                }
+            }
+            ctx.tab = state.tab().id;
+            Control::Changed
+        }
         AppEvent::Message(s) => {
             state.error_dlg.append(s);
             Control::Changed
@@ -289,7 +768,18 @@ pub fn event(
         _ => Control::Continue,
     };
 
-    r = r.or_else_try(|| main_ui::event(event, &mut state.async1, ctx))?;
+    if !r.is_consumed() {
+        // Events of closed tabs are dropped
+        if let Some(tab) = state.tabs.iter_mut().find(|tab| tab.id == tab_id) {
+            ctx.tab = tab.id;
+            r = match main_ui::event(event, tab, ctx)? {
+                // Follow-up events stay with the tab
+                Control::Event(event) => Control::Event(ctx.tab_event(event)),
+                r => r,
+            };
+            ctx.tab = state.tab().id;
+        }
+    }
 
     let el = t0.elapsed()?;
     state.status.status(2, format!("E {:.0?}", el).to_string());
@@ -309,8 +799,10 @@ pub fn error(
 
 impl Drop for Scenery {
     fn drop(&mut self) {
-        if let Some(cancel) = self.async1.throbber_cancel.take() {
-            cancel.cancel();
+        for tab in &mut self.tabs {
+            if let Some(cancel) = tab.throbber_cancel.take() {
+                cancel.cancel();
+            }
         }
         disable_raw_mode().unwrap();
         stdout().execute(LeaveAlternateScreen).unwrap();
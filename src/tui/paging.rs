@@ -0,0 +1,398 @@
+//! Text of a previewed file, read a page at a time.
+//!
+//! Pages are cut at line ends, so the lines of a [`Document`] always map to a
+//! contiguous byte range of the file that more pages can be added to on
+//! either side.
+use color_eyre::eyre::Result;
+use tokio::io::AsyncReadExt;
+
+use crate::remote_fs::RemoteFs;
+
+/// How much a single read of the preview asks for.
+pub const PAGE_LEN: u64 = 64 * 1024;
+
+/// Bytes the encoding is guessed from.
+pub const SNIFF_LEN: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// Guesses the encoding from the first bytes of a file, `None` if they
+    /// look binary.
+    pub fn detect(head: &[u8]) -> Option<Self> {
+        if head.starts_with(b"\xef\xbb\xbf") {
+            return Some(Encoding::Utf8);
+        }
+        if head.starts_with(b"\xff\xfe") {
+            return Some(Encoding::Utf16Le);
+        }
+        if head.starts_with(b"\xfe\xff") {
+            return Some(Encoding::Utf16Be);
+        }
+        let head = &head[..head.len().min(SNIFF_LEN)];
+        // Mostly ASCII UTF-16 has every other byte zero
+        let zeros = |parity: usize| {
+            head.iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|byte| **byte == 0)
+                .count()
+        };
+        let (even, odd) = (zeros(0), zeros(1));
+        let half = head.len() / 2;
+        if half > 0 && odd * 10 > half * 7 && even * 10 < half {
+            return Some(Encoding::Utf16Le);
+        }
+        if half > 0 && even * 10 > half * 7 && odd * 10 < half {
+            return Some(Encoding::Utf16Be);
+        }
+        if even + odd > 0 {
+            return None;
+        }
+        match std::str::from_utf8(head) {
+            Ok(_) => Some(Encoding::Utf8),
+            // A char cut off by the end of the read
+            Err(err) if err.error_len().is_none() => Some(Encoding::Utf8),
+            Err(_) => {
+                let control = head
+                    .iter()
+                    .filter(|byte| **byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(byte))
+                    .count();
+                (control * 10 < head.len()).then_some(Encoding::Latin1)
+            }
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "Latin-1",
+        }
+    }
+
+    /// Length of the byte order mark `bytes` start with.
+    fn bom_len(self, bytes: &[u8]) -> usize {
+        let bom: &[u8] = match self {
+            Encoding::Utf8 => b"\xef\xbb\xbf",
+            Encoding::Utf16Le => b"\xff\xfe",
+            Encoding::Utf16Be => b"\xfe\xff",
+            Encoding::Latin1 => b"",
+        };
+        if bytes.starts_with(bom) { bom.len() } else { 0 }
+    }
+
+    fn unit(self) -> usize {
+        match self {
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+            Encoding::Utf8 | Encoding::Latin1 => 1,
+        }
+    }
+
+    fn newline(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf16Le => b"\n\0",
+            Encoding::Utf16Be => b"\0\n",
+            Encoding::Utf8 | Encoding::Latin1 => b"\n",
+        }
+    }
+
+    /// Offsets just past every line end in `bytes`, which start at a char
+    /// boundary.
+    fn line_ends(self, bytes: &[u8]) -> impl Iterator<Item = usize> + '_ {
+        let (unit, newline) = (self.unit(), self.newline());
+        bytes
+            .chunks_exact(unit)
+            .enumerate()
+            .filter(move |(_, chunk)| *chunk == newline)
+            .map(move |(idx, _)| (idx + 1) * unit)
+    }
+
+    /// How much of `bytes` holds whole lines: up to the last line end, or
+    /// up to the last whole char if there is none.
+    fn whole_lines(self, bytes: &[u8]) -> usize {
+        if let Some(end) = self.line_ends(bytes).last() {
+            return end;
+        }
+        match self {
+            Encoding::Utf8 => match std::str::from_utf8(bytes) {
+                Ok(_) => bytes.len(),
+                Err(err) if err.error_len().is_none() => err.valid_up_to(),
+                Err(_) => bytes.len(),
+            },
+            _ => bytes.len() / self.unit() * self.unit(),
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The loaded lines of a file and the bytes they came from.
+#[derive(Debug)]
+pub struct Document {
+    pub encoding: Encoding,
+    /// Size of the file when it was last asked.
+    pub size: u64,
+    /// Byte range of the file the lines were decoded from.
+    pub start: u64,
+    pub end: u64,
+    /// Number of the first line, unknown after jumping to the end.
+    pub first_line: Option<usize>,
+    pub lines: Vec<String>,
+    /// Whether the last line goes on past `end`.
+    partial: bool,
+}
+
+impl Document {
+    /// The lines of `bytes` read at `offset`. Unless the page starts at a
+    /// known line, the line it cuts into is left out.
+    pub fn new(
+        encoding: Encoding,
+        size: u64,
+        offset: u64,
+        bytes: &[u8],
+        first_line: Option<usize>,
+    ) -> Self {
+        let skip = if offset == 0 {
+            encoding.bom_len(bytes)
+        } else if first_line.is_none() {
+            encoding.line_ends(bytes).next().unwrap_or(bytes.len())
+        } else {
+            0
+        };
+        let start = offset + skip as u64;
+        let mut document = Self {
+            encoding,
+            size,
+            start,
+            end: start,
+            first_line: first_line.or((offset == 0).then_some(1)),
+            lines: Vec::new(),
+            partial: false,
+        };
+        document.append(&bytes[skip..]);
+        document
+    }
+
+    /// Whether the lines reach the end of the file.
+    pub fn at_end(&self) -> bool {
+        self.end >= self.size
+    }
+
+    /// Adds the page read at `end`.
+    pub fn append(&mut self, bytes: &[u8]) {
+        let eof = self.end + bytes.len() as u64 >= self.size;
+        let mut len = if eof {
+            bytes.len()
+        } else {
+            self.encoding.whole_lines(bytes)
+        };
+        if len == 0 {
+            len = bytes.len();
+        }
+        let text = self.encoding.decode(&bytes[..len]);
+        let mut lines = split_lines(&text);
+        if self.partial
+            && let Some(last) = self.lines.last_mut()
+            && !lines.is_empty()
+        {
+            last.push_str(&lines.remove(0));
+        }
+        self.lines.extend(lines);
+        self.end += len as u64;
+        self.partial = !text.ends_with('\n');
+    }
+
+    /// Adds the page read at `offset`, which ends at `start`. Returns how
+    /// many lines were added in front.
+    pub fn prepend(&mut self, offset: u64, bytes: &[u8]) -> usize {
+        let skip = if offset == 0 {
+            self.encoding.bom_len(bytes)
+        } else {
+            // A line longer than a page is taken in part, so the next
+            // page can add the rest of it
+            self.encoding
+                .line_ends(bytes)
+                .next()
+                .filter(|end| *end < bytes.len())
+                .unwrap_or_default()
+        };
+        let text = self.encoding.decode(&bytes[skip..]);
+        let mut lines = split_lines(&text);
+        if !text.ends_with('\n')
+            && let Some(first) = self.lines.first_mut()
+            && let Some(last) = lines.pop()
+        {
+            first.insert_str(0, &last);
+        }
+        let added = lines.len();
+        self.lines.splice(0..0, lines);
+        self.start = offset + skip as u64;
+        self.first_line = self.first_line.map(|line| line.saturating_sub(added));
+        if self.start == 0 {
+            self.first_line = Some(1);
+        }
+        added
+    }
+
+    /// Index of line `number` among the loaded ones.
+    pub fn index_of(&self, number: usize) -> Option<usize> {
+        let idx = number.checked_sub(self.first_line?)?;
+        (idx < self.lines.len()).then_some(idx)
+    }
+}
+
+fn split_lines(text: &str) -> Vec<String> {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    if text.is_empty() {
+        return Vec::new();
+    }
+    text.split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line).to_string())
+        .collect()
+}
+
+/// Offset at which line `number` of the file starts and the number of the
+/// line found there, the last one if the file is shorter. Reads the file up
+/// to there.
+pub async fn find_line(
+    fs: &dyn RemoteFs,
+    path: &str,
+    encoding: Encoding,
+    number: usize,
+) -> Result<(u64, usize)> {
+    let mut reader = fs.open(path).await?;
+    let mut buf = vec![0; PAGE_LEN as usize];
+    // Bytes of a char cut off by the previous read
+    let mut carry = 0;
+    let (mut offset, mut line) = (0u64, 1);
+    let (mut line_start, mut previous) = (0u64, 0u64);
+    while line < number {
+        let read = reader.read(&mut buf[carry..]).await?;
+        if read == 0 {
+            // Nothing follows the final line end
+            if line > 1 && line_start == offset {
+                return Ok((previous, line - 1));
+            }
+            break;
+        }
+        let filled = carry + read;
+        let whole = filled / encoding.unit() * encoding.unit();
+        for end in encoding.line_ends(&buf[..whole]) {
+            line += 1;
+            previous = line_start;
+            line_start = offset + end as u64;
+            if line == number {
+                break;
+            }
+        }
+        offset += whole as u64;
+        buf.copy_within(whole..filled, 0);
+        carry = filled - whole;
+    }
+    Ok((line_start, line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn test_detect_and_decode() {
+        assert_eq!(Encoding::detect(b"plain text\n"), Some(Encoding::Utf8));
+        assert_eq!(
+            Encoding::detect("caf\u{e9}".as_bytes()),
+            Some(Encoding::Utf8)
+        );
+        assert_eq!(
+            Encoding::detect(b"caf\xe9 au lait\n"),
+            Some(Encoding::Latin1)
+        );
+        assert_eq!(
+            Encoding::detect(&utf16le("hello\n")),
+            Some(Encoding::Utf16Le)
+        );
+        assert_eq!(Encoding::detect(b"\xfe\xff\0h\0i"), Some(Encoding::Utf16Be));
+        assert_eq!(Encoding::detect(b"\x7fELF\x02\x01\x01\0\0\0"), None);
+
+        assert_eq!(Encoding::Latin1.decode(b"caf\xe9"), "caf\u{e9}");
+        assert_eq!(Encoding::Utf16Le.decode(&utf16le("h\u{e9}")), "h\u{e9}");
+    }
+
+    #[test]
+    fn test_document_pages() {
+        let text = b"one\ntwo\nthree\nfour\nfive\n";
+        let size = text.len() as u64;
+
+        // The first page stops after the last whole line
+        let mut document = Document::new(Encoding::Utf8, size, 0, &text[..10], None);
+        assert_eq!(document.lines, ["one", "two"]);
+        assert_eq!(document.end, 8);
+        document.append(&text[8..]);
+        assert_eq!(document.lines, ["one", "two", "three", "four", "five"]);
+        assert!(document.at_end());
+
+        // A page at the end drops the line it cuts into
+        let mut document = Document::new(Encoding::Utf8, size, 10, &text[10..], None);
+        assert_eq!(document.lines, ["four", "five"]);
+        assert_eq!(document.first_line, None);
+        assert_eq!(document.prepend(0, &text[..14]), 3);
+        assert_eq!(document.lines[0], "one");
+        assert_eq!(document.index_of(4), Some(3));
+
+        let text = utf16le("a\r\nb\n");
+        let document = Document::new(Encoding::Utf16Le, text.len() as u64, 0, &text, None);
+        assert_eq!(document.lines, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_find_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        std::fs::write(&path, b"one\ntwo\nthree\n").unwrap();
+        let path = path.display().to_string();
+        let fs = crate::remote_fs::local::LocalFs;
+        assert_eq!(
+            find_line(&fs, &path, Encoding::Utf8, 1).await.unwrap(),
+            (0, 1)
+        );
+        assert_eq!(
+            find_line(&fs, &path, Encoding::Utf8, 3).await.unwrap(),
+            (8, 3)
+        );
+        // Past the end is the last line
+        assert_eq!(
+            find_line(&fs, &path, Encoding::Utf8, 9).await.unwrap(),
+            (8, 3)
+        );
+    }
+}
@@ -0,0 +1,391 @@
+//! One side of the dual-pane browser: a directory on some filesystem along
+//! with its own filter, sort order and selection.
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use async_lock::Mutex as AsyncMutex;
+use rat_ftable::TableState;
+use rat_ftable::selection::RowSelection;
+
+use super::OpenHost;
+use crate::files::{Column, FileEntry, JoinablePaths, SortKey};
+use crate::remote_fs::{LocalFs, RemoteFs, SudoFs};
+use crate::ssh::Session;
+
+pub struct Pane {
+    pub fs: Arc<dyn RemoteFs>,
+    /// Where the pane is browsing, `local` or a host name.
+    pub label: String,
+    /// Session of the host the pane is browsing, `None` on the local disk.
+    pub session: Option<Arc<AsyncMutex<Session>>>,
+    pub current_path: String,
+    pub table_state: TableState<RowSelection>,
+    pub current_file_entries: Vec<FileEntry>,
+    pub filtered_file_entries: Vec<FileEntry>,
+    /// Text of the filter prompt, restored when the pane becomes active again.
+    pub filter: String,
+    pub hidden_files: bool,
+    pub sort: SortKey,
+    pub sort_reverse: bool,
+    /// List directories before files regardless of the sort key.
+    pub dirs_first: bool,
+    /// Columns shown at the last render, those that fit the pane.
+    pub columns: Vec<Column>,
+    /// Names of the entries marked with <Space> in the current directory.
+    pub marked: HashSet<String>,
+    /// Directories visited before the current one, most recent last.
+    pub back: Vec<String>,
+    /// Directories left with "back", most recent last.
+    pub forward: Vec<String>,
+    /// Entry to select once the next listing arrives.
+    pub reveal: Option<String>,
+    /// Root access to the host in sudo mode, used for the files that are
+    /// previewed and edited.
+    pub sudo: Option<Arc<SudoFs>>,
+}
+
+/// `input` relative to `base`, or to `home` if it starts with `~`.
+pub fn resolve_path(base: &str, home: &str, input: &str) -> String {
+    match input.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            home.to_string().join(rest.trim_start_matches('/'))
+        }
+        _ => base.to_string().join(input),
+    }
+}
+
+/// How many directories the history of a pane remembers.
+const HISTORY_LEN: usize = 100;
+
+impl Pane {
+    pub fn local(current_path: String) -> Self {
+        Self::new(Arc::new(LocalFs), "local".to_string(), None, current_path)
+    }
+
+    pub fn remote(host: &OpenHost, current_path: String) -> Self {
+        Self::new(
+            Arc::clone(&host.connection.fs),
+            host.label.clone(),
+            Some(Arc::clone(&host.connection.session)),
+            current_path,
+        )
+    }
+
+    fn new(
+        fs: Arc<dyn RemoteFs>,
+        label: String,
+        session: Option<Arc<AsyncMutex<Session>>>,
+        current_path: String,
+    ) -> Self {
+        Self {
+            fs,
+            label,
+            session,
+            current_path,
+            table_state: TableState::default(),
+            current_file_entries: Vec::new(),
+            filtered_file_entries: Vec::new(),
+            filter: String::new(),
+            hidden_files: false,
+            sort: SortKey::default(),
+            sort_reverse: false,
+            dirs_first: true,
+            columns: Vec::new(),
+            marked: HashSet::new(),
+            back: Vec::new(),
+            forward: Vec::new(),
+            reveal: None,
+            sudo: None,
+        }
+    }
+
+    #[inline]
+    pub fn get_file_entries(&self) -> Cow<'_, [FileEntry]> {
+        let entries = if self.filter.is_empty() {
+            &self.current_file_entries
+        } else {
+            &self.filtered_file_entries
+        };
+
+        if self.hidden_files {
+            Cow::Owned(
+                entries
+                    .iter()
+                    .filter(|entry| !entry.name.starts_with('.'))
+                    .cloned()
+                    .collect(),
+            )
+        } else {
+            Cow::Borrowed(entries.as_slice())
+        }
+    }
+
+    /// Switches the pane to `host`, or to the local disk for `None`.
+    pub fn browse(&mut self, host: Option<&OpenHost>, current_path: String) {
+        let pane = match host {
+            Some(host) => Self::remote(host, current_path),
+            None => Self::local(current_path),
+        };
+        (self.fs, self.label, self.session) = (pane.fs, pane.label, pane.session);
+        self.sudo = None;
+        self.current_path = pane.current_path;
+        self.set_entries(Vec::new());
+        self.set_filter(String::new());
+        // Paths of the old host mean nothing here
+        self.back.clear();
+        self.forward.clear();
+    }
+
+    /// Where `~` points: the login directory on a host, which is where
+    /// `canonicalize(".")` lands, or the user's home on the local disk.
+    pub fn home(&self) -> String {
+        if self.fs.is_local()
+            && let Some(dirs) = directories::BaseDirs::new()
+        {
+            return dirs.home_dir().display().to_string();
+        }
+        ".".to_string()
+    }
+
+    /// A path typed into a prompt, relative to the current directory or
+    /// starting with `~`.
+    pub fn resolve(&self, input: &str) -> String {
+        resolve_path(&self.current_path, &self.home(), input)
+    }
+
+    /// Moves to `path`, remembering the current directory for "back".
+    pub fn navigate(&mut self, path: String) {
+        if path == self.current_path {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.current_path, path);
+        self.back.push(previous);
+        if self.back.len() > HISTORY_LEN {
+            self.back.remove(0);
+        }
+        self.forward.clear();
+        self.set_filter(String::new());
+        self.marked.clear();
+    }
+
+    /// Moves to the previous directory, returning it.
+    pub fn go_back(&mut self) -> Option<String> {
+        let path = self.back.pop()?;
+        let current = std::mem::replace(&mut self.current_path, path.clone());
+        self.forward.push(current);
+        self.set_filter(String::new());
+        self.marked.clear();
+        Some(path)
+    }
+
+    /// Undoes the last [`Pane::go_back`], returning the directory.
+    pub fn go_forward(&mut self) -> Option<String> {
+        let path = self.forward.pop()?;
+        let current = std::mem::replace(&mut self.current_path, path.clone());
+        self.back.push(current);
+        self.set_filter(String::new());
+        self.marked.clear();
+        Some(path)
+    }
+
+    /// The filesystem files are previewed and edited on, as root in sudo
+    /// mode.
+    pub fn file_fs(&self) -> Arc<dyn RemoteFs> {
+        match &self.sudo {
+            Some(sudo) => Arc::clone(sudo) as Arc<dyn RemoteFs>,
+            None => Arc::clone(&self.fs),
+        }
+    }
+
+    /// Whether the pane is browsing `host`.
+    pub fn is_on(&self, host: Option<&OpenHost>) -> bool {
+        match host {
+            Some(host) => Arc::ptr_eq(&self.fs, &host.connection.fs),
+            None => self.fs.is_local(),
+        }
+    }
+
+    pub fn selected(&self) -> Option<FileEntry> {
+        let row = self.table_state.selected()?;
+        self.get_file_entries().get(row).cloned()
+    }
+
+    /// Marks or unmarks the selected entry.
+    pub fn toggle_mark(&mut self) {
+        if let Some(entry) = self.selected()
+            && !self.marked.remove(entry.name())
+        {
+            self.marked.insert(entry.name().clone());
+        }
+    }
+
+    /// The marked entries, or the selected one if nothing is marked.
+    pub fn targets(&self) -> Vec<FileEntry> {
+        if self.marked.is_empty() {
+            return self.selected().into_iter().collect();
+        }
+        self.current_file_entries
+            .iter()
+            .filter(|entry| self.marked.contains(entry.name()))
+            .cloned()
+            .collect()
+    }
+
+    /// Replaces the listing, keeping the pane's sort order and filter and
+    /// the marks of entries that are still there.
+    pub fn set_entries(&mut self, entries: Vec<FileEntry>) {
+        self.marked
+            .retain(|name| entries.iter().any(|entry| entry.name() == name));
+        self.current_file_entries = entries;
+        self.sort_entries();
+        if let Some(name) = self.reveal.take()
+            && let Some(row) = self
+                .get_file_entries()
+                .iter()
+                .position(|entry| *entry.name() == name)
+        {
+            self.table_state.select(Some(row));
+        }
+    }
+
+    pub fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.filtered_file_entries = self
+            .current_file_entries
+            .iter()
+            .filter(|file| file.name().contains(&self.filter))
+            .cloned()
+            .collect();
+    }
+
+    /// Sorts by `key`, or reverses the order if that is the key already.
+    pub fn sort_by(&mut self, key: SortKey) {
+        if self.sort == key {
+            self.sort_reverse = !self.sort_reverse;
+        } else {
+            self.sort = key;
+            self.sort_reverse = false;
+        }
+        self.sort_entries();
+    }
+
+    pub fn sort_entries(&mut self) {
+        let (sort, reverse, dirs_first) = (self.sort, self.sort_reverse, self.dirs_first);
+        self.current_file_entries.sort_by(|a, b| {
+            let dirs = if dirs_first {
+                b.is_dir().cmp(&a.is_dir())
+            } else {
+                Ordering::Equal
+            };
+            dirs.then_with(|| {
+                let ord = sort.compare(a, b);
+                if reverse { ord.reverse() } else { ord }
+            })
+        });
+        let filter = std::mem::take(&mut self.filter);
+        self.set_filter(filter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use russh_sftp::protocol::{FileAttributes, FileType};
+
+    use super::*;
+
+    fn entry(name: &str, type_: FileType, size: u64) -> FileEntry {
+        let attributes = FileAttributes {
+            size: Some(size),
+            ..FileAttributes::empty()
+        };
+        FileEntry::from_file(name.to_string(), type_, attributes)
+    }
+
+    fn names(pane: &Pane) -> Vec<String> {
+        pane.get_file_entries()
+            .iter()
+            .map(|e| e.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() {
+        let pane = Pane::local("/srv".to_string());
+        let home = pane.home();
+        assert_eq!(pane.resolve("app"), "/srv/app");
+        assert_eq!(pane.resolve("/etc"), "/etc");
+        assert_eq!(pane.resolve("~"), home);
+        assert_eq!(pane.resolve("~/logs"), home.join("logs"));
+        assert_eq!(pane.resolve("~user"), "/srv/~user");
+    }
+
+    #[test]
+    fn test_history() {
+        let mut pane = Pane::local("/".to_string());
+        pane.navigate("/a".to_string());
+        pane.navigate("/a/b".to_string());
+        assert_eq!(pane.go_back().as_deref(), Some("/a"));
+        assert_eq!(pane.go_back().as_deref(), Some("/"));
+        assert_eq!(pane.go_back(), None);
+        assert_eq!(pane.go_forward().as_deref(), Some("/a"));
+
+        // Going somewhere new drops the forward history
+        pane.navigate("/c".to_string());
+        assert_eq!(pane.go_forward(), None);
+        assert_eq!(pane.current_path, "/c");
+        assert_eq!(pane.back, ["/", "/a"]);
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        let mut pane = Pane::local("/".to_string());
+        pane.set_entries(vec![
+            entry("b.txt", FileType::File, 10),
+            entry("zdir", FileType::Dir, 0),
+            entry("a.txt", FileType::File, 20),
+        ]);
+        assert_eq!(names(&pane), ["zdir", "a.txt", "b.txt"]);
+
+        pane.sort = SortKey::Size;
+        pane.sort_reverse = true;
+        pane.sort_entries();
+        assert_eq!(names(&pane), ["zdir", "a.txt", "b.txt"]);
+
+        pane.set_filter("b".to_string());
+        assert_eq!(names(&pane), ["b.txt"]);
+        // The filter survives a refresh
+        pane.set_entries(vec![entry("b2", FileType::File, 0)]);
+        assert_eq!(names(&pane), ["b2"]);
+    }
+
+    #[test]
+    fn test_natural_sort() {
+        let mut pane = Pane::local("/".to_string());
+        pane.set_entries(vec![
+            entry("file10.txt", FileType::File, 0),
+            entry("File2.txt", FileType::File, 0),
+            entry("dir", FileType::Dir, 0),
+            entry("file1.tar", FileType::File, 0),
+        ]);
+        assert_eq!(
+            names(&pane),
+            ["dir", "file1.tar", "File2.txt", "file10.txt"]
+        );
+
+        pane.sort_by(SortKey::Extension);
+        pane.dirs_first = false;
+        pane.sort_entries();
+        assert_eq!(
+            names(&pane),
+            ["dir", "file1.tar", "File2.txt", "file10.txt"]
+        );
+        // Picking the same key again reverses it
+        pane.sort_by(SortKey::Extension);
+        assert_eq!(
+            names(&pane),
+            ["file10.txt", "File2.txt", "file1.tar", "dir"]
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! Dialog changing the mode and ownership of the marked entries of a pane.
+use std::sync::{Arc, Mutex};
+
+use async_lock::Mutex as AsyncMutex;
+use color_eyre::Report as Error;
+use color_eyre::eyre::{Result, bail, eyre};
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::style::Modifier;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+use russh_sftp::protocol::FileAttributes;
+
+use super::pane::Pane;
+use super::{AppEvent, Global};
+use crate::files::{FileEntry, JoinablePaths};
+use crate::par_dir_traversal::{WalkParallel, WalkState};
+use crate::remote_fs::RemoteFs;
+use crate::ssh::Session;
+
+/// Mode bits in the order the dialog lists them: the rwx grid, then the
+/// special bits.
+const BITS: [u32; 12] = [
+    0o400, 0o200, 0o100, 0o040, 0o020, 0o010, 0o004, 0o002, 0o001, 0o4000, 0o2000, 0o1000,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Bit(usize),
+    Octal,
+    Owner,
+    Group,
+    Recursive,
+}
+
+const FIELDS: usize = BITS.len() + 4;
+
+impl Field {
+    fn at(idx: usize) -> Self {
+        match idx {
+            idx if idx < BITS.len() => Field::Bit(idx),
+            12 => Field::Octal,
+            13 => Field::Owner,
+            14 => Field::Group,
+            _ => Field::Recursive,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PermissionsDialog {
+    pub active: bool,
+    /// Pane of the shown tab the targets were taken from.
+    pub pane: usize,
+    fs: Option<Arc<dyn RemoteFs>>,
+    session: Option<Arc<AsyncMutex<Session>>>,
+    dir: String,
+    targets: Vec<FileEntry>,
+    mode: u32,
+    octal: String,
+    owner: String,
+    group: String,
+    initial: (u32, String, String),
+    recursive: bool,
+    field: usize,
+}
+
+/// What to change on every target, `None` leaves it as it is.
+struct Change {
+    mode: Option<u32>,
+    owner: Option<String>,
+    group: Option<String>,
+}
+
+impl PermissionsDialog {
+    /// Shows the dialog for the marked entries of `pane`, prefilled from the
+    /// first of them.
+    pub fn open(&mut self, idx: usize, pane: &Pane) {
+        let targets = pane.targets();
+        let Some(first) = targets.first() else {
+            return;
+        };
+        let attributes = &first.attributes;
+        let mode = attributes.permissions.unwrap_or(0) & 0o7777;
+        let owner = attributes
+            .user
+            .clone()
+            .or(attributes.uid.map(|uid| uid.to_string()))
+            .unwrap_or_default();
+        let group = attributes
+            .group
+            .clone()
+            .or(attributes.gid.map(|gid| gid.to_string()))
+            .unwrap_or_default();
+        *self = Self {
+            active: true,
+            pane: idx,
+            fs: Some(Arc::clone(&pane.fs)),
+            session: pane.session.clone(),
+            dir: pane.current_path.clone(),
+            targets,
+            mode,
+            octal: format!("{:04o}", mode),
+            initial: (mode, owner.clone(), group.clone()),
+            owner,
+            group,
+            recursive: false,
+            field: 0,
+        };
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+
+    fn toggle(&mut self, bit: usize) {
+        self.mode ^= BITS[bit];
+        self.octal = format!("{:04o}", self.mode);
+    }
+
+    fn text_mut(&mut self) -> Option<&mut String> {
+        match Field::at(self.field) {
+            Field::Octal => Some(&mut self.octal),
+            Field::Owner => Some(&mut self.owner),
+            Field::Group => Some(&mut self.group),
+            _ => None,
+        }
+    }
+
+    /// Takes the octal field over into the checkboxes if it parses.
+    fn sync_octal(&mut self) {
+        if let Ok(mode) = u32::from_str_radix(&self.octal, 8)
+            && mode <= 0o7777
+        {
+            self.mode = mode;
+        }
+    }
+
+    fn change(&self) -> Change {
+        let (mode, owner, group) = &self.initial;
+        Change {
+            mode: (self.mode != *mode).then_some(self.mode),
+            owner: (self.owner != *owner).then(|| self.owner.trim().to_string()),
+            group: (self.group != *group).then(|| self.group.trim().to_string()),
+        }
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut PermissionsDialog, ctx: &mut Global) {
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(30),
+        Constraint::Percentage(30),
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+    );
+    let focused = |field: usize| {
+        if field == state.field {
+            ctx.theme.select()
+        } else {
+            ctx.theme.popup_base()
+        }
+    };
+    let check = |field: usize, checked: bool| {
+        Span::styled(if checked { "[x]" } else { "[ ]" }, focused(field))
+    };
+    let bit = |idx: usize| check(idx, state.mode & BITS[idx] != 0);
+
+    let name = state.targets.first().map_or("", |entry| entry.name());
+    let target = match state.targets.len() {
+        1 => name.to_string(),
+        n => format!("{} (+{} more)", name, n - 1),
+    };
+    let mut lines = vec![
+        Line::from(target).style(ctx.theme.popup_base().add_modifier(Modifier::BOLD)),
+        Line::from(""),
+        Line::from("         Read  Write  Exec"),
+    ];
+    for (row, label) in ["User   ", "Group  ", "Other  "].into_iter().enumerate() {
+        lines.push(Line::from(vec![
+            Span::from(format!("{}  ", label)),
+            bit(row * 3),
+            Span::from("   "),
+            bit(row * 3 + 1),
+            Span::from("    "),
+            bit(row * 3 + 2),
+        ]));
+    }
+    lines.push(Line::from(vec![
+        Span::from("Special  "),
+        bit(9),
+        Span::from(" setuid  "),
+        bit(10),
+        Span::from(" setgid  "),
+        bit(11),
+        Span::from(" sticky"),
+    ]));
+    lines.push(Line::from(""));
+    for (field, label, value) in [
+        (12, "Octal  ", &state.octal),
+        (13, "Owner  ", &state.owner),
+        (14, "Group  ", &state.group),
+    ] {
+        lines.push(Line::from(vec![
+            Span::from(format!("{}  ", label)),
+            Span::styled(format!("{:<12}", value), focused(field)),
+        ]));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        check(15, state.recursive),
+        Span::from(" Also apply to everything inside directories"),
+    ]));
+    lines.push(Line::from(
+        "    Inside, files keep execute only if they had it",
+    ));
+
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .style(ctx.theme.popup_base())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title("Permissions")
+                .title_bottom("<Space> Toggle  <Tab> Next  <Enter> Apply  <Esc> Cancel"),
+        )
+        .render(area, buf);
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut PermissionsDialog,
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    let r = match event {
+        ct_event!(keycode press Esc) => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Tab) | ct_event!(keycode press Down) => {
+            state.field = (state.field + 1) % FIELDS;
+            Control::Changed
+        }
+        ct_event!(keycode press SHIFT-BackTab) | ct_event!(keycode press Up) => {
+            state.field = (state.field + FIELDS - 1) % FIELDS;
+            Control::Changed
+        }
+        ct_event!(keycode press Enter) => {
+            state.sync_octal();
+            state.close();
+            apply(state, ctx);
+            Control::Changed
+        }
+        ct_event!(keycode press Backspace) => {
+            if let Some(text) = state.text_mut() {
+                text.pop();
+                state.sync_octal();
+            }
+            Control::Changed
+        }
+        ct_event!(key press ' ') => {
+            match Field::at(state.field) {
+                Field::Bit(bit) => state.toggle(bit),
+                Field::Recursive => state.recursive = !state.recursive,
+                _ => {}
+            }
+            Control::Changed
+        }
+        ct_event!(key press c) | ct_event!(key press SHIFT-c) => {
+            let octal = Field::at(state.field) == Field::Octal;
+            if let Some(text) = state.text_mut()
+                && (!octal || (c.is_digit(8) && text.len() < 4))
+            {
+                text.push(*c);
+                state.sync_octal();
+            }
+            Control::Changed
+        }
+        // The dialog is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
+
+/// Applies the dialog to its targets in the background and reloads the pane.
+fn apply(state: &PermissionsDialog, ctx: &mut Global) {
+    let change = state.change();
+    if change.mode.is_none() && change.owner.is_none() && change.group.is_none() {
+        return;
+    }
+    let Some(fs) = state.fs.clone() else {
+        return;
+    };
+    let session = state.session.clone();
+    let (pane, dir, recursive) = (state.pane, state.dir.clone(), state.recursive);
+    let targets: Vec<FileEntry> = state
+        .targets
+        .iter()
+        .map(|entry| {
+            let mut entry = entry.clone();
+            entry.name = dir.join(entry.name());
+            entry
+        })
+        .collect();
+    ctx.spawn_tab_async(move |chan| async move {
+        let uid = match &change.owner {
+            Some(owner) => Some(resolve_id(session.as_ref(), owner, false).await?),
+            None => None,
+        };
+        let gid = match &change.group {
+            Some(group) => Some(resolve_id(session.as_ref(), group, true).await?),
+            None => None,
+        };
+        // Whether the entry was found inside a target, rather than marked
+        let mut entries = Vec::new();
+        for target in targets {
+            if recursive && target.is_dir() {
+                let inside = walk(&fs, &target.name).await;
+                entries.extend(inside.into_iter().map(|entry| (entry, true)));
+            }
+            entries.push((target, false));
+        }
+
+        let mut failed = Vec::new();
+        for (entry, inside) in &entries {
+            let attributes = &entry.attributes;
+            let old = attributes.permissions.unwrap_or(0);
+            let mut update = FileAttributes {
+                // The marked entries get exactly the mode that was entered
+                permissions: change.mode.map(|mode| match inside {
+                    true => (old & !0o7777) | recursive_mode(mode, old, entry.is_dir()),
+                    false => (old & !0o7777) | mode,
+                }),
+                ..FileAttributes::empty()
+            };
+            // SFTP sets owner and group together
+            if uid.is_some() || gid.is_some() {
+                update.uid = uid.or(attributes.uid);
+                update.gid = gid.or(attributes.gid);
+                if update.uid.is_none() || update.gid.is_none() {
+                    failed.push(format!("{}: unknown owner", entry.name));
+                    continue;
+                }
+            }
+            if let Err(err) = fs.setstat(&entry.name, update).await {
+                failed.push(format!("{}: {}", entry.name, err));
+            }
+        }
+        chan.send(Ok(Control::Event(AppEvent::ChangeDir(pane, dir))))
+            .await?;
+        if !failed.is_empty() {
+            bail!(
+                "{} of {} entries could not be changed:\n{}",
+                failed.len(),
+                entries.len(),
+                failed.join("\n")
+            );
+        }
+        Ok(Control::Event(AppEvent::Status(
+            1,
+            format!("Changed {} entries", entries.len()),
+        )))
+    });
+}
+
+/// `mode` as applied to the entries inside the marked directories, with the
+/// execute bits meaning what `X` means to chmod: directories get them wherever they can be read so
+/// they stay traversable, files only keep them if they were executable.
+fn recursive_mode(mode: u32, old: u32, is_dir: bool) -> u32 {
+    let exec = 0o111;
+    if is_dir {
+        mode | ((mode & 0o444) >> 2)
+    } else if old & exec != 0 {
+        mode
+    } else {
+        mode & !exec
+    }
+}
+
+/// Every entry below `root` with absolute names. Links are left alone as
+/// changing them would change what they point to.
+async fn walk(fs: &Arc<dyn RemoteFs>, root: &str) -> Vec<FileEntry> {
+    let walker = WalkParallel {
+        filter: Arc::new(|_| true),
+        path: root.into(),
+        max_depth: None,
+        min_depth: None,
+        threads: 4,
+        fs: Arc::clone(fs),
+    };
+    let collected = Arc::new(Mutex::new(Vec::new()));
+    let collected_ref = Arc::clone(&collected);
+    walker
+        .run(|| {
+            let collected = Arc::clone(&collected_ref);
+            let root = root.to_string();
+            Box::new(move |entry: Result<FileEntry>| {
+                // The root comes back without attributes
+                if let Ok(entry) = entry
+                    && !entry.is_symlink()
+                    && entry.name != root
+                {
+                    collected.lock().unwrap().push(entry);
+                }
+                WalkState::Continue
+            })
+        })
+        .await;
+    std::mem::take(&mut *collected.lock().unwrap())
+}
+
+/// The id of a user or group name on the pane's host, numbers are taken
+/// as they are.
+async fn resolve_id(
+    session: Option<&Arc<AsyncMutex<Session>>>,
+    name: &str,
+    group: bool,
+) -> Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+    let escaped = shell_escape::unix::escape(name.into());
+    let command = if group {
+        format!("getent group {} | cut -d: -f3", escaped)
+    } else {
+        format!("id -u -- {}", escaped)
+    };
+    let output = match session {
+        Some(session) => session
+            .lock()
+            .await
+            .clone()
+            .exec(&command)
+            .await?
+            .stdout_lossy(),
+        None => {
+            let output = tokio::process::Command::new("sh")
+                .arg("-c")
+                .arg(&command)
+                .output()
+                .await?;
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+    };
+    let kind = if group { "group" } else { "user" };
+    output
+        .trim()
+        .parse()
+        .map_err(|_| eyre!("unknown {} {}", kind, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_fields() {
+        let mut dialog = PermissionsDialog {
+            initial: (0o644, "root".to_string(), "root".to_string()),
+            mode: 0o644,
+            owner: "root".to_string(),
+            group: "root".to_string(),
+            ..Default::default()
+        };
+        dialog.toggle(2);
+        dialog.toggle(9);
+        assert_eq!(dialog.octal, "4744");
+
+        dialog.octal = "750".to_string();
+        dialog.sync_octal();
+        let change = dialog.change();
+        assert_eq!(change.mode, Some(0o750));
+        assert_eq!(change.owner, None);
+        assert_eq!(change.group, None);
+    }
+
+    #[test]
+    fn test_recursive_mode() {
+        // Directories stay traversable
+        assert_eq!(recursive_mode(0o644, 0o40700, true), 0o755);
+        assert_eq!(recursive_mode(0o640, 0o40700, true), 0o750);
+        assert_eq!(recursive_mode(0o2750, 0o40700, true), 0o2750);
+        // Only files that were executable stay so
+        assert_eq!(recursive_mode(0o755, 0o100644, false), 0o644);
+        assert_eq!(recursive_mode(0o755, 0o100700, false), 0o755);
+        assert_eq!(recursive_mode(0o4755, 0o100600, false), 0o4644);
+    }
+}
@@ -0,0 +1,1244 @@
+//! The content view of the details pane: the previewed file read a page at
+//! a time, syntax highlighted, with optional line numbers and wrapping.
+//!
+//! Pages are read as the view scrolls near the edge of what is loaded, so a
+//! huge log costs no more to open than a small file. A followed file is
+//! polled like `tail -F` and lines can be filtered or marked by a regex.
+//! Searching past the loaded lines reads further pages until a match turns
+//! up.
+//!
+//! Highlighting a large file takes a while, so it runs in the background and
+//! the plain text is shown until it is done.
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use rat_salsa::Control;
+use rat_theme3::Palette;
+use rat_widget::event::ct_event;
+use rat_widget::paragraph::ParagraphState;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use regex::Regex;
+use syntect::easy::ScopeRangeIterator;
+use syntect::highlighting::ScopeSelectors;
+use syntect::parsing::{ParseState, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+use super::hex_view::HexView;
+use super::paging::{Document, Encoding, PAGE_LEN, find_line};
+use super::{AppEvent, Global};
+use crate::files::human_readable_size;
+use crate::remote_fs::RemoteFs;
+
+/// Lines after this many are left plain, highlighting is linear in the size
+/// of the file and a huge log gains nothing from it.
+const MAX_HIGHLIGHTED_LINES: usize = 20_000;
+
+/// How close to the edge of the loaded lines the view may scroll before
+/// the next page is read.
+const FETCH_MARGIN: usize = 50;
+
+/// How often a followed file is asked for its size.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bytes at the start of a followed file compared to tell a rotated file
+/// from one that grew.
+const FINGERPRINT_LEN: u64 = 256;
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// A read of the previewed file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fetch {
+    /// The first page, which also decides between text and a dump.
+    Open,
+    /// The page after the loaded lines.
+    Next,
+    /// The page before the loaded lines.
+    Previous,
+    /// The last page, dropping the loaded lines.
+    End,
+    /// The page starting at a line number, dropping the loaded lines.
+    Line(usize),
+    /// The bytes of the dump starting at an offset.
+    Hex(u64),
+}
+
+/// Bytes read for a [`Fetch`].
+#[derive(Debug)]
+pub struct Page {
+    generation: u64,
+    fetch: Fetch,
+    offset: u64,
+    bytes: Vec<u8>,
+    /// Size of the file at the time of the read.
+    size: u64,
+    /// Number of the line the page starts at, if known.
+    line: Option<usize>,
+}
+
+/// The size and first bytes of a followed file, read by [`Preview::poll`].
+#[derive(Debug)]
+pub struct Poll {
+    generation: u64,
+    token: u64,
+    /// `None` while the file is missing, as during a rotation.
+    size: Option<u64>,
+    /// Empty unless the size changed.
+    head: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Follow {
+    /// Tells the polls of this follow from those of an earlier one.
+    token: u64,
+    paused: bool,
+    missing: bool,
+}
+
+/// What the prompt of the content view asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    GoTo,
+    Filter,
+    Mark,
+    Search { forward: bool },
+}
+
+/// A position in the loaded lines: the line index and a byte offset in it.
+type Position = (usize, usize);
+
+#[derive(Debug, Clone)]
+struct Search {
+    regex: Regex,
+    /// Direction of `n`, `N` goes the other way.
+    forward: bool,
+    /// Start of the match moved to last.
+    current: Option<Position>,
+    /// Matches in the loaded lines, and the number of the current one.
+    count: usize,
+    ordinal: usize,
+    /// Set while pages are read to find the next match: the direction and
+    /// where to go on from, leaving out a match there if the flag is set.
+    seeking: Option<(bool, Position, bool)>,
+}
+
+#[derive(Default)]
+pub struct Preview {
+    /// Name of the previewed file, picks the syntax along with its first
+    /// line.
+    pub name: String,
+    /// Where the file is read from, `None` unless a preview is open.
+    source: Option<(Arc<dyn RemoteFs>, String)>,
+    /// The loaded text, `None` until the first page is read and for binary
+    /// files.
+    pub doc: Option<Document>,
+    /// Dump of a binary file, shown instead of the text.
+    pub hex: Option<HexView>,
+    /// First line of the file, for guessing the syntax of later pages.
+    head: String,
+    /// Highlighted lines and the start of the document they were made for.
+    highlighted: Option<(u64, Vec<Line<'static>>)>,
+    /// Start and number of lines of the last highlighting asked for.
+    requested: Option<(u64, usize)>,
+    /// Bumped for every opened file, so pages of older ones are dropped.
+    pub generation: u64,
+    /// The read in flight, so scrolling asks for a page only once.
+    pending: Option<Fetch>,
+    /// The prompt open and what was typed into it.
+    prompt: Option<(Prompt, String)>,
+    /// Set while new lines of the file are polled for.
+    follow: Option<Follow>,
+    /// Counts the follows started, for their tokens.
+    polls: u64,
+    /// The first bytes of the file when it was opened or rotated.
+    fingerprint: Vec<u8>,
+    /// Only lines matching this are shown.
+    filter: Option<Regex>,
+    /// Lines matching this are marked.
+    mark: Option<Regex>,
+    /// Scrolls to the last line once the new lines are laid out.
+    stick: bool,
+    search: Option<Search>,
+    /// The view offset and search from before the search prompt opened,
+    /// restored if it is cancelled.
+    search_origin: Option<(usize, Option<Search>)>,
+    pub line_numbers: bool,
+    pub wrap: bool,
+}
+
+impl Preview {
+    /// Starts previewing the file at `path`.
+    pub fn open(&mut self, fs: Arc<dyn RemoteFs>, path: String, name: &str, ctx: &Global) {
+        self.close();
+        self.name = name.to_string();
+        self.source = Some((fs, path));
+        self.fetch(Fetch::Open, ctx);
+    }
+
+    /// Reads the previewed file again from the start.
+    pub fn reload(&mut self, ctx: &Global) {
+        if let Some((fs, path)) = self.source.clone() {
+            let name = self.name.clone();
+            self.open(fs, path, &name, ctx);
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.generation += 1;
+        self.source = None;
+        self.doc = None;
+        self.hex = None;
+        self.head.clear();
+        self.highlighted = None;
+        self.requested = None;
+        self.pending = None;
+        self.prompt = None;
+        self.follow = None;
+        self.fingerprint.clear();
+        self.stick = false;
+        self.search = None;
+        self.search_origin = None;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Path of the previewed file.
+    pub fn path(&self) -> Option<&str> {
+        self.source.as_ref().map(|(_, path)| path.as_str())
+    }
+
+    /// Reads a page of the file in the background. Pages for scrolling are
+    /// skipped while another read is in flight.
+    pub fn fetch(&mut self, fetch: Fetch, ctx: &Global) {
+        let Some((fs, path)) = self.source.clone() else {
+            return;
+        };
+        if matches!(fetch, Fetch::Next | Fetch::Previous) && self.pending.is_some() {
+            return;
+        }
+        let (offset, len) = match (fetch, &self.doc) {
+            (Fetch::Next, Some(doc)) => (doc.end, PAGE_LEN),
+            (Fetch::Previous, Some(doc)) => {
+                let offset = doc.start.saturating_sub(PAGE_LEN);
+                (offset, doc.start - offset)
+            }
+            (Fetch::Next | Fetch::Previous, None) => return,
+            // The rows of a dump shown at once fit in two pages
+            (Fetch::Hex(offset), _) => (offset, 2 * PAGE_LEN),
+            _ => (0, PAGE_LEN),
+        };
+        let encoding = self.doc.as_ref().map_or(Encoding::Utf8, |doc| doc.encoding);
+        let generation = self.generation;
+        self.pending = Some(fetch);
+        ctx.spawn_tab_async(move |_| async move {
+            let size = fs.stat(&path).await?.size.unwrap_or_default();
+            let (offset, line) = match fetch {
+                Fetch::End => (size.saturating_sub(PAGE_LEN), None),
+                Fetch::Line(number) => {
+                    let (offset, line) = find_line(&*fs, &path, encoding, number).await?;
+                    (offset, Some(line))
+                }
+                _ => (offset, None),
+            };
+            let bytes = fs.read_at(&path, offset, len).await?;
+            Ok(Control::Event(AppEvent::Page(Page {
+                generation,
+                fetch,
+                offset,
+                bytes,
+                size,
+                line,
+            })))
+        });
+    }
+
+    /// Takes in a page read by [`Preview::fetch`], keeping the view on the
+    /// same lines when they move down.
+    pub fn paged(
+        &mut self,
+        page: &Page,
+        para: &mut ParagraphState,
+        ctx: &Global,
+    ) -> Control<AppEvent> {
+        if page.generation != self.generation {
+            return Control::Continue;
+        }
+        if self.pending == Some(page.fetch) {
+            self.pending = None;
+        }
+        match page.fetch {
+            Fetch::Open => match Encoding::detect(&page.bytes) {
+                Some(encoding) => {
+                    let len = page.bytes.len().min(FINGERPRINT_LEN as usize);
+                    self.fingerprint = page.bytes[..len].to_vec();
+                    let doc = Document::new(encoding, page.size, 0, &page.bytes, None);
+                    self.head = doc.lines.first().cloned().unwrap_or_default();
+                    self.doc = Some(doc);
+                    self.hex = None;
+                    para.vscroll.offset = 0;
+                    para.hscroll.offset = 0;
+                }
+                None => {
+                    self.doc = None;
+                    self.hex = Some(HexView::new(page.bytes.clone(), page.size));
+                }
+            },
+            Fetch::Next => {
+                if let Some(doc) = &mut self.doc
+                    && doc.end == page.offset
+                {
+                    doc.size = page.size;
+                    doc.append(&page.bytes);
+                    // Like tail, unless the view was scrolled up
+                    let following = self.follow.as_ref().is_some_and(|follow| !follow.paused);
+                    if following && para.vscroll.offset >= para.vscroll.max_offset {
+                        self.stick = true;
+                    }
+                }
+            }
+            Fetch::Previous => {
+                if let Some(doc) = &mut self.doc
+                    && page.offset + page.bytes.len() as u64 == doc.start
+                {
+                    doc.size = page.size;
+                    let added = doc.prepend(page.offset, &page.bytes);
+                    para.vscroll.offset += self.shown_before(added);
+                    if let Some(search) = &mut self.search {
+                        if let Some(current) = &mut search.current {
+                            current.0 += added;
+                        }
+                        if let Some((_, from, _)) = &mut search.seeking {
+                            from.0 += added;
+                        }
+                    }
+                }
+            }
+            Fetch::End | Fetch::Line(_) => {
+                let Some(encoding) = self.doc.as_ref().map(|doc| doc.encoding) else {
+                    return Control::Continue;
+                };
+                let doc = Document::new(encoding, page.size, page.offset, &page.bytes, page.line);
+                para.vscroll.offset = 0;
+                self.stick = page.fetch == Fetch::End;
+                self.doc = Some(doc);
+                if let Some(search) = &mut self.search {
+                    search.current = None;
+                    search.seeking = None;
+                }
+            }
+            Fetch::Hex(offset) => {
+                if let Some(hex) = &mut self.hex {
+                    hex.load(offset, page.bytes.clone(), page.size);
+                }
+            }
+        }
+        self.highlight(ctx);
+        if let Some(Search {
+            seeking: Some((forward, from, strict)),
+            ..
+        }) = self.search
+            && matches!(page.fetch, Fetch::Next | Fetch::Previous)
+        {
+            return self.step(forward, Some((from, strict)), para, ctx);
+        }
+        self.recount();
+        Control::Changed
+    }
+
+    /// Reads the next page if the view came close to the edge of what is
+    /// loaded. Called after every render, returns whether the view scrolled
+    /// and needs another.
+    pub fn check(&mut self, para: &mut ParagraphState, ctx: &Global) -> bool {
+        if std::mem::take(&mut self.stick) {
+            para.vscroll.offset = para.vscroll.max_offset;
+            return true;
+        }
+        if self.pending.is_some() {
+            return false;
+        }
+        if let Some(hex) = &self.hex {
+            if let Some(offset) = hex.wanted() {
+                self.fetch(Fetch::Hex(offset), ctx);
+            }
+            return false;
+        }
+        let Some(doc) = &self.doc else {
+            return false;
+        };
+        // Rendered lines, which differ from the text lines when wrapping
+        let (offset, page_len) = (para.vscroll.offset, para.vscroll.page_len);
+        if !doc.at_end() && offset + page_len + FETCH_MARGIN >= para.lines {
+            self.fetch(Fetch::Next, ctx);
+        } else if doc.start > 0 && offset < FETCH_MARGIN {
+            self.fetch(Fetch::Previous, ctx);
+        }
+        false
+    }
+
+    /// Starts following the file from its end, or stops.
+    fn toggle_follow(&mut self, ctx: &Global) {
+        if self.follow.take().is_some() {
+            return;
+        }
+        self.polls += 1;
+        self.follow = Some(Follow {
+            token: self.polls,
+            ..Default::default()
+        });
+        self.fetch(Fetch::End, ctx);
+        self.poll(ctx);
+    }
+
+    fn toggle_pause(&mut self, ctx: &Global) {
+        let Some(follow) = &mut self.follow else {
+            return;
+        };
+        follow.paused = !follow.paused;
+        if !follow.paused {
+            // Polls of before the pause are still sleeping
+            self.polls += 1;
+            follow.token = self.polls;
+            self.poll(ctx);
+        }
+    }
+
+    /// Asks for the size of the followed file after a while.
+    fn poll(&mut self, ctx: &Global) {
+        let (Some((fs, path)), Some(follow), Some(doc)) = (&self.source, &self.follow, &self.doc)
+        else {
+            return;
+        };
+        if follow.paused {
+            return;
+        }
+        let (fs, path) = (Arc::clone(fs), path.clone());
+        let (generation, token, known) = (self.generation, follow.token, doc.size);
+        ctx.spawn_tab_async(move |_| async move {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let size = fs
+                .stat(&path)
+                .await
+                .ok()
+                .and_then(|attributes| attributes.size);
+            let head = match size {
+                Some(size) if size != known => fs
+                    .read_at(&path, 0, FINGERPRINT_LEN)
+                    .await
+                    .unwrap_or_default(),
+                _ => Vec::new(),
+            };
+            Ok(Control::Event(AppEvent::Polled(Poll {
+                generation,
+                token,
+                size,
+                head,
+            })))
+        });
+    }
+
+    /// Takes in the size of the followed file: reads what was appended, or
+    /// the file from its end again if it was truncated or replaced.
+    pub fn polled(&mut self, poll: &Poll, ctx: &Global) -> Control<AppEvent> {
+        if poll.generation != self.generation {
+            return Control::Continue;
+        }
+        let (Some(follow), Some(doc)) = (&mut self.follow, &mut self.doc) else {
+            return Control::Continue;
+        };
+        if follow.token != poll.token || follow.paused {
+            return Control::Continue;
+        }
+        let missing = poll.size.is_none();
+        let changed = std::mem::replace(&mut follow.missing, missing) != missing;
+        let mut control = if changed {
+            Control::Changed
+        } else {
+            Control::Continue
+        };
+        if let Some(size) = poll.size
+            && size != doc.size
+        {
+            let common = poll.head.len().min(self.fingerprint.len());
+            if size < doc.end || poll.head[..common] != self.fingerprint[..common] {
+                self.fingerprint = poll.head.clone();
+                self.fetch(Fetch::End, ctx);
+                control = Control::Event(AppEvent::Status(
+                    1,
+                    format!("{} was truncated or rotated", self.name),
+                ));
+            } else {
+                doc.size = size;
+                self.fetch(Fetch::Next, ctx);
+            }
+        }
+        self.poll(ctx);
+        control
+    }
+
+    fn shown(&self, line: &str) -> bool {
+        passes(&self.filter, line)
+    }
+
+    /// Index of the loaded line shown at `offset`.
+    fn line_at(&self, offset: usize) -> usize {
+        let Some(doc) = &self.doc else {
+            return 0;
+        };
+        doc.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| self.shown(line))
+            .nth(offset)
+            .map_or(doc.lines.len(), |(idx, _)| idx)
+    }
+
+    /// Scrolls the loaded line `idx` into view unless it is shown.
+    fn scroll_to(&self, idx: usize, para: &mut ParagraphState) {
+        let position = self.shown_before(idx);
+        let (offset, page_len) = (para.vscroll.offset, para.vscroll.page_len);
+        if position < offset || position >= offset + page_len {
+            para.vscroll.offset = position.saturating_sub(page_len / 3);
+        }
+    }
+
+    /// Moves to the next match in the direction, from `from` or else the
+    /// current match or the top of the view. Reads further pages while the
+    /// loaded lines have none.
+    fn step(
+        &mut self,
+        forward: bool,
+        from: Option<(Position, bool)>,
+        para: &mut ParagraphState,
+        ctx: &Global,
+    ) -> Control<AppEvent> {
+        let top = (self.line_at(para.vscroll.offset), 0);
+        let (Some(search), Some(doc)) = (&mut self.search, &self.doc) else {
+            return Control::Continue;
+        };
+        let (from, strict) = from
+            .or(search.current.map(|current| (current, true)))
+            .unwrap_or((top, !forward));
+        search.seeking = None;
+        let found = find_match(
+            &doc.lines,
+            &self.filter,
+            &search.regex,
+            from,
+            strict,
+            forward,
+        );
+        if let Some(found) = found {
+            search.current = Some(found);
+            self.recount();
+            self.scroll_to(found.0, para);
+            return Control::Changed;
+        }
+        if forward && !doc.at_end() {
+            // The last line may go on in the next page
+            let last = doc.lines.len().saturating_sub(1);
+            let resume = if from.0 >= last {
+                (from, strict)
+            } else {
+                ((last, 0), false)
+            };
+            search.seeking = Some((true, resume.0, resume.1));
+            self.fetch(Fetch::Next, ctx);
+        } else if !forward && doc.start > 0 {
+            search.seeking = Some((false, from, strict));
+            self.fetch(Fetch::Previous, ctx);
+        } else {
+            let message = format!("No more matches of /{}/", search.regex);
+            self.recount();
+            return Control::Event(AppEvent::Status(1, message));
+        }
+        Control::Changed
+    }
+
+    /// Counts the matches in the loaded lines passing the filter.
+    fn recount(&mut self) {
+        let (Some(search), Some(doc)) = (&mut self.search, &self.doc) else {
+            return;
+        };
+        (search.count, search.ordinal) = (0, 0);
+        for (idx, line) in doc.lines.iter().enumerate() {
+            if !passes(&self.filter, line) {
+                continue;
+            }
+            for found in search
+                .regex
+                .find_iter(line)
+                .filter(|found| !found.is_empty())
+            {
+                search.count += 1;
+                if search.current == Some((idx, found.start())) {
+                    search.ordinal = search.count;
+                }
+            }
+        }
+    }
+
+    /// Searches for what the search prompt holds while it is typed, from
+    /// where the view was when the prompt opened.
+    fn search_incrementally(&mut self, forward: bool, input: &str, para: &mut ParagraphState) {
+        let Some((offset, _)) = self.search_origin else {
+            return;
+        };
+        let Ok(regex) = Regex::new(input) else {
+            return;
+        };
+        let origin = (self.line_at(offset), 0);
+        let current = match (&self.doc, input.is_empty()) {
+            (Some(doc), false) => {
+                find_match(&doc.lines, &self.filter, &regex, origin, !forward, forward)
+            }
+            _ => None,
+        };
+        self.search = Some(Search {
+            regex,
+            forward,
+            current,
+            count: 0,
+            ordinal: 0,
+            seeking: None,
+        });
+        self.recount();
+        match current {
+            Some((idx, _)) => self.scroll_to(idx, para),
+            None => para.vscroll.offset = offset,
+        }
+    }
+
+    /// How many of the first `count` loaded lines pass the filter.
+    fn shown_before(&self, count: usize) -> usize {
+        match (&self.doc, &self.filter) {
+            (Some(doc), Some(_)) => doc.lines[..count]
+                .iter()
+                .filter(|line| self.shown(line))
+                .count(),
+            _ => count,
+        }
+    }
+
+    /// Takes in the text of the prompt when it is confirmed.
+    fn confirm(
+        &mut self,
+        prompt: Prompt,
+        input: &str,
+        para: &mut ParagraphState,
+        ctx: &Global,
+    ) -> Control<AppEvent> {
+        if let Prompt::Search { forward } = prompt {
+            self.search_origin = None;
+            if input.is_empty() {
+                self.search = None;
+                return Control::Changed;
+            }
+            let Err(err) = Regex::new(input) else {
+                // Not found among the loaded lines, look further
+                if self
+                    .search
+                    .as_ref()
+                    .is_some_and(|search| search.current.is_none())
+                {
+                    return self.step(forward, None, para, ctx);
+                }
+                return Control::Changed;
+            };
+            return Control::Event(AppEvent::Message(err.to_string()));
+        }
+        let regex = match prompt {
+            Prompt::GoTo => {
+                if let Ok(number) = input.parse::<usize>() {
+                    self.go_to_line(number.max(1), para, ctx);
+                }
+                return Control::Changed;
+            }
+            _ if input.is_empty() => None,
+            _ => match Regex::new(input) {
+                Ok(regex) => Some(regex),
+                Err(err) => return Control::Event(AppEvent::Message(err.to_string())),
+            },
+        };
+        if prompt == Prompt::Filter {
+            self.filter = regex;
+            para.vscroll.offset = 0;
+            self.recount();
+        } else {
+            self.mark = regex;
+        }
+        Control::Changed
+    }
+
+    /// Scrolls to line `number`, reading the file up to it unless it is
+    /// loaded.
+    fn go_to_line(&mut self, number: usize, para: &mut ParagraphState, ctx: &Global) {
+        match self.doc.as_ref().and_then(|doc| doc.index_of(number)) {
+            Some(idx) => para.vscroll.offset = self.shown_before(idx),
+            None => self.fetch(Fetch::Line(number), ctx),
+        }
+    }
+
+    /// Highlights the loaded lines up to [`MAX_HIGHLIGHTED_LINES`], unless
+    /// that was already asked for.
+    fn highlight(&mut self, ctx: &Global) {
+        let Some(doc) = &self.doc else {
+            return;
+        };
+        let len = doc.lines.len().min(MAX_HIGHLIGHTED_LINES);
+        if len == 0 || syntax_for(&self.name, &self.head).is_none() {
+            return;
+        }
+        if let Some((start, requested)) = self.requested
+            && start == doc.start
+            && requested >= len
+        {
+            return;
+        }
+        self.requested = Some((doc.start, len));
+        let (generation, start) = (self.generation, doc.start);
+        let (name, head) = (self.name.clone(), self.head.clone());
+        let content = doc.lines[..len].join("\n");
+        let palette = ctx.theme.palette().clone();
+        ctx.spawn_tab_async(move |_| async move {
+            let lines =
+                tokio::task::spawn_blocking(move || highlight(&name, &head, &content, &palette))
+                    .await?;
+            Ok(Control::Event(AppEvent::Highlighted(
+                generation, start, lines,
+            )))
+        });
+    }
+
+    /// Takes in lines highlighted for the document starting at `start`.
+    pub fn highlighted(&mut self, generation: u64, start: u64, lines: &[Line<'static>]) {
+        let current = self.doc.as_ref().map(|doc| doc.start);
+        if generation != self.generation || current != Some(start) {
+            return;
+        }
+        // An older, shorter job may finish last
+        if let Some((known, highlighted)) = &self.highlighted
+            && *known == start
+            && highlighted.len() > lines.len()
+        {
+            return;
+        }
+        self.highlighted = Some((start, lines.to_vec()));
+    }
+
+    /// What the pane shows: the loaded lines passing the filter,
+    /// highlighted where that is done, numbered if asked for.
+    pub fn text(&self, number_style: Style, palette: &Palette) -> Text<'_> {
+        let Some(doc) = &self.doc else {
+            return Text::default();
+        };
+        let mark_style = Style::default().bg(palette.yellow[Palette::DARK_0]);
+        let match_style = palette.normal_contrast(palette.orange[Palette::DARK_2]);
+        let current_style = palette
+            .normal_contrast(palette.orange[Palette::BRIGHT_2])
+            .add_modifier(Modifier::BOLD);
+        let highlighted = match &self.highlighted {
+            Some((start, lines)) if *start == doc.start => lines.as_slice(),
+            _ => &[],
+        };
+        let lines = doc
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| self.shown(line))
+            .map(|(idx, line)| {
+                let mut text = match highlighted.get(idx) {
+                    Some(highlighted) => Line::from(highlighted.spans.clone()),
+                    None => Line::from(line.as_str()),
+                };
+                if self.mark.as_ref().is_some_and(|mark| mark.is_match(line)) {
+                    text = text.style(mark_style);
+                }
+                if let Some(search) = &self.search {
+                    let current = search
+                        .current
+                        .filter(|(line, _)| *line == idx)
+                        .map(|(_, start)| start);
+                    let styles = (match_style, current_style);
+                    text = style_matches(text, line, &search.regex, current, styles);
+                }
+                (idx, text)
+            });
+        if !self.line_numbers {
+            return lines.map(|(_, line)| line).collect::<Vec<_>>().into();
+        }
+        let first = doc.first_line;
+        let width = first
+            .map_or(1, |first| first + doc.lines.len())
+            .to_string()
+            .len();
+        lines
+            .map(|(idx, mut line)| {
+                // Numbers are unknown after jumping to the end
+                let number = match first {
+                    Some(first) => format!("{:>width$} ", first + idx),
+                    None => format!("{:>width$} ", "~"),
+                };
+                line.spans.insert(0, Span::styled(number, number_style));
+                line
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// The encoding and, unless the whole file is loaded, how much of it.
+    pub fn title(&self) -> String {
+        let Some(doc) = &self.doc else {
+            return "[2] Content".to_string();
+        };
+        let mut title = format!("[2] Content: {}", doc.encoding.label());
+        if doc.start > 0 || !doc.at_end() {
+            title += &format!(
+                ", {} of {} loaded",
+                human_readable_size(doc.end - doc.start),
+                human_readable_size(doc.size)
+            );
+        }
+        match &self.follow {
+            Some(follow) if follow.paused => title += ", paused",
+            Some(follow) if follow.missing => title += ", waiting for the file",
+            Some(_) => title += ", following",
+            None => {}
+        }
+        if let Some(filter) = &self.filter {
+            title += &format!(", lines matching /{}/", filter);
+        }
+        if let Some(search) = &self.search {
+            // More may be in the parts of the file not read yet
+            let more = if doc.start > 0 || !doc.at_end() {
+                "+"
+            } else {
+                ""
+            };
+            title += &match (search.count, search.ordinal) {
+                (0, _) => format!(", no matches{}", more),
+                (count, 0) => format!(", {}{} matches", count, more),
+                (count, ordinal) => format!(", match {} of {}{}", ordinal, count, more),
+            };
+        }
+        title
+    }
+
+    pub fn footer(&self) -> String {
+        let Some((prompt, input)) = &self.prompt else {
+            return "</ ?> Search  <n/N> Next/Prev  <:> Line  <F> Follow  <p> Pause  <&> Filter  \
+                    <*> Mark  <#> Numbers  <w> Wrap"
+                .to_string();
+        };
+        let label = match prompt {
+            Prompt::GoTo => "Go to line",
+            Prompt::Filter => "Show lines matching",
+            Prompt::Mark => "Mark lines matching",
+            Prompt::Search { forward: true } => "Search forward",
+            Prompt::Search { forward: false } => "Search backward",
+        };
+        format!("{}: {}_", label, input)
+    }
+}
+
+/// Keys of the focused content view that need more of the file than is
+/// loaded, and those following and filtering it.
+pub fn event(
+    event: &Event,
+    state: &mut Preview,
+    para: &mut ParagraphState,
+    ctx: &Global,
+) -> Control<AppEvent> {
+    if let Some((prompt, input)) = &mut state.prompt {
+        return match event {
+            ct_event!(keycode press Esc) => {
+                state.prompt = None;
+                if let Some((offset, search)) = state.search_origin.take() {
+                    para.vscroll.offset = offset;
+                    state.search = search;
+                }
+                Control::Changed
+            }
+            ct_event!(keycode press Enter) => {
+                let (prompt, input) = (*prompt, std::mem::take(input));
+                state.prompt = None;
+                state.confirm(prompt, &input, para, ctx)
+            }
+            ct_event!(keycode press Backspace) => {
+                input.pop();
+                if let Prompt::Search { forward } = *prompt {
+                    let input = input.clone();
+                    state.search_incrementally(forward, &input, para);
+                }
+                Control::Changed
+            }
+            ct_event!(key press c) | ct_event!(key press SHIFT-c)
+                if *prompt != Prompt::GoTo || c.is_ascii_digit() =>
+            {
+                input.push(*c);
+                if let Prompt::Search { forward } = *prompt {
+                    let input = input.clone();
+                    state.search_incrementally(forward, &input, para);
+                }
+                Control::Changed
+            }
+            // The prompt takes every key
+            _ => Control::Unchanged,
+        };
+    }
+    let Some(doc) = &state.doc else {
+        return Control::Continue;
+    };
+    // The pattern in use, to edit it
+    let pattern = |regex: &Option<Regex>| regex.as_ref().map(|regex| regex.to_string());
+    match event {
+        ct_event!(keycode press Home) | ct_event!(key press 'g') => {
+            if doc.start > 0 {
+                state.fetch(Fetch::Open, ctx);
+            } else {
+                para.vscroll.offset = 0;
+            }
+        }
+        ct_event!(keycode press End) | ct_event!(key press SHIFT-'G') => {
+            if doc.at_end() {
+                para.vscroll.offset = para.vscroll.max_offset;
+            } else {
+                state.fetch(Fetch::End, ctx);
+            }
+        }
+        ct_event!(key press ':') => state.prompt = Some((Prompt::GoTo, String::new())),
+        ct_event!(key press SHIFT-'F') => state.toggle_follow(ctx),
+        ct_event!(key press 'p') if state.follow.is_some() => state.toggle_pause(ctx),
+        ct_event!(key press '/') | ct_event!(key press '?') | ct_event!(key press SHIFT-'?') => {
+            let forward = matches!(event, ct_event!(key press '/'));
+            state.search_origin = Some((para.vscroll.offset, state.search.clone()));
+            state.prompt = Some((Prompt::Search { forward }, String::new()));
+        }
+        ct_event!(key press 'n') | ct_event!(key press SHIFT-'N') if state.search.is_some() => {
+            let forward = state.search.as_ref().is_some_and(|search| search.forward);
+            let forward = forward == matches!(event, ct_event!(key press 'n'));
+            return state.step(forward, None, para, ctx);
+        }
+        ct_event!(key press SHIFT-'&') | ct_event!(key press '&') => {
+            let input = pattern(&state.filter).unwrap_or_default();
+            state.prompt = Some((Prompt::Filter, input));
+        }
+        ct_event!(key press SHIFT-'*') | ct_event!(key press '*') => {
+            let input = pattern(&state.mark).unwrap_or_default();
+            state.prompt = Some((Prompt::Mark, input));
+        }
+        _ => return Control::Continue,
+    }
+    Control::Changed
+}
+
+fn passes(filter: &Option<Regex>, line: &str) -> bool {
+    filter.as_ref().is_none_or(|filter| filter.is_match(line))
+}
+
+/// The start of the first match of `regex` from `from` on in the direction,
+/// in the lines passing `filter`. `strict` leaves out a match at `from`.
+fn find_match(
+    lines: &[String],
+    filter: &Option<Regex>,
+    regex: &Regex,
+    from: Position,
+    strict: bool,
+    forward: bool,
+) -> Option<Position> {
+    let starts = |idx: usize| -> Vec<usize> {
+        if !passes(filter, &lines[idx]) {
+            return Vec::new();
+        }
+        regex
+            .find_iter(&lines[idx])
+            .filter(|found| !found.is_empty())
+            .map(|found| found.start())
+            .collect()
+    };
+    let past = |start: usize, beyond: bool| beyond || (!strict && start == from.1);
+    if forward {
+        (from.0..lines.len()).find_map(|idx| {
+            let start = starts(idx)
+                .into_iter()
+                .find(|start| past(*start, idx > from.0 || *start > from.1))?;
+            Some((idx, start))
+        })
+    } else {
+        let last = from.0.min(lines.len().checked_sub(1)?);
+        (0..=last).rev().find_map(|idx| {
+            let start = starts(idx)
+                .into_iter()
+                .rev()
+                .find(|start| past(*start, idx < from.0 || *start < from.1))?;
+            Some((idx, start))
+        })
+    }
+}
+
+/// Restyles the parts of `line` that `regex` matches in its `text`, the
+/// one starting at `current` with the second style.
+fn style_matches<'a>(
+    mut line: Line<'a>,
+    text: &str,
+    regex: &Regex,
+    current: Option<usize>,
+    (match_style, current_style): (Style, Style),
+) -> Line<'a> {
+    let ranges: Vec<_> = regex
+        .find_iter(text)
+        .filter(|found| !found.is_empty())
+        .map(|found| found.range())
+        .collect();
+    let len: usize = line.spans.iter().map(|span| span.content.len()).sum();
+    // Spans that don't spell out the text can't be cut by its offsets
+    if ranges.is_empty() || len != text.len() {
+        return line;
+    }
+    let mut spans = Vec::new();
+    let mut start = 0;
+    for span in std::mem::take(&mut line.spans) {
+        let end = start + span.content.len();
+        let mut cuts: Vec<_> = ranges
+            .iter()
+            .flat_map(|range| [range.start, range.end])
+            .filter(|cut| *cut > start && *cut < end)
+            .collect();
+        cuts.push(end);
+        let mut from = start;
+        for cut in cuts {
+            let style = match ranges.iter().find(|range| range.contains(&from)) {
+                Some(range) if Some(range.start) == current => span.style.patch(current_style),
+                Some(_) => span.style.patch(match_style),
+                None => span.style,
+            };
+            let piece = &span.content[from - start..cut - start];
+            spans.push(Span::styled(piece.to_string(), style));
+            from = cut;
+        }
+        start = end;
+    }
+    line.spans = spans;
+    line
+}
+
+/// The syntax of the file `name` starting with `first_line`: by extension,
+/// by the whole name for files like `Makefile`, then by shebang or modeline.
+fn syntax_for(name: &str, first_line: &str) -> Option<&'static SyntaxReference> {
+    let path = Path::new(name);
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    let file_name = path.file_name().and_then(|name| name.to_str());
+    extension
+        .and_then(|ext| SYNTAXES.find_syntax_by_extension(ext))
+        .or_else(|| file_name.and_then(|name| SYNTAXES.find_syntax_by_extension(name)))
+        .or_else(|| SYNTAXES.find_syntax_by_first_line(first_line))
+}
+
+/// Styles for scopes, picked from the palette so the preview matches the
+/// rest of the theme. The most specific selector wins.
+fn rules(palette: &Palette) -> Vec<(ScopeSelectors, Style)> {
+    let fg = |colors: &[ratatui::style::Color; 8]| Style::default().fg(colors[Palette::BRIGHT_2]);
+    [
+        ("comment", fg(&palette.gray).add_modifier(Modifier::ITALIC)),
+        ("string, constant.character", fg(&palette.green)),
+        (
+            "constant.numeric, constant.language, constant.other",
+            fg(&palette.orange),
+        ),
+        (
+            "keyword, storage, keyword.operator.word",
+            fg(&palette.magenta),
+        ),
+        (
+            "keyword.operator, punctuation.separator.key-value",
+            fg(&palette.bluegreen),
+        ),
+        (
+            "entity.name.function, support.function, meta.function-call",
+            fg(&palette.blue),
+        ),
+        (
+            "entity.name.type, entity.name.class, support.type, support.class, storage.type",
+            fg(&palette.cyan),
+        ),
+        ("entity.name.tag, entity.name.section", fg(&palette.redpink)),
+        (
+            "entity.other.attribute-name, variable.parameter",
+            fg(&palette.yellow),
+        ),
+        (
+            "variable.other.readwrite, variable.language",
+            fg(&palette.limegreen),
+        ),
+        (
+            "markup.heading",
+            fg(&palette.blue).add_modifier(Modifier::BOLD),
+        ),
+        ("markup.bold", Style::default().add_modifier(Modifier::BOLD)),
+        (
+            "markup.italic",
+            Style::default().add_modifier(Modifier::ITALIC),
+        ),
+        ("markup.inserted", fg(&palette.green)),
+        ("markup.deleted, invalid", fg(&palette.red)),
+    ]
+    .into_iter()
+    .filter_map(|(selector, style)| Some((selector.parse().ok()?, style)))
+    .collect()
+}
+
+fn style_for(rules: &[(ScopeSelectors, Style)], stack: &ScopeStack) -> Style {
+    rules
+        .iter()
+        .filter_map(|(selector, style)| Some((selector.does_match(stack.as_slice())?, style)))
+        .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+        .map_or(Style::default(), |(_, style)| *style)
+}
+
+/// Highlights `content` of the file `name` starting with `first_line`.
+/// Unknown file types and lines the parser gives up on stay plain.
+pub fn highlight(
+    name: &str,
+    first_line: &str,
+    content: &str,
+    palette: &Palette,
+) -> Vec<Line<'static>> {
+    let plain = |line: &str| Line::from(line.trim_end_matches(['\n', '\r']).to_string());
+    let Some(syntax) = syntax_for(name, first_line) else {
+        return content.lines().map(plain).collect();
+    };
+    let rules = rules(palette);
+    let mut parser = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut lines = Vec::new();
+    for (idx, line) in LinesWithEndings::from(content).enumerate() {
+        if idx >= MAX_HIGHLIGHTED_LINES {
+            lines.push(plain(line));
+            continue;
+        }
+        let ops = match parser.parse_line(line, &SYNTAXES) {
+            Ok(ops) => ops,
+            Err(_) => {
+                lines.push(plain(line));
+                continue;
+            }
+        };
+        let mut spans = Vec::new();
+        for (range, op) in ScopeRangeIterator::new(&ops, line) {
+            if stack.apply(op).is_err() {
+                break;
+            }
+            let text = line[range].trim_end_matches(['\n', '\r']);
+            if !text.is_empty() {
+                spans.push(Span::styled(text.to_string(), style_for(&rules, &stack)));
+            }
+        }
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(syntax_for("deploy.yaml", "").unwrap().name, "YAML");
+        assert_eq!(
+            syntax_for("run", "#!/bin/bash").unwrap().name,
+            "Bourne Again Shell (bash)"
+        );
+        assert_eq!(syntax_for("Makefile", "").unwrap().name, "Makefile");
+        assert!(syntax_for("notes", "hello").is_none());
+
+        let palette = Palette {
+            gray: [ratatui::style::Color::Gray; 8],
+            ..Default::default()
+        };
+        let lines = highlight("run.sh", "# setup", "# setup\necho hi\n", &palette);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0].spans[0].style.fg,
+            Some(ratatui::style::Color::Gray)
+        );
+        assert_eq!(lines[1].to_string(), "echo hi");
+    }
+
+    #[test]
+    fn test_filter_and_mark() {
+        let text = b"GET /\nPOST /login\nGET /health\n";
+        let mut preview = Preview {
+            doc: Some(Document::new(
+                Encoding::Utf8,
+                text.len() as u64,
+                0,
+                text,
+                None,
+            )),
+            filter: Regex::new("^GET").ok(),
+            mark: Regex::new("health").ok(),
+            line_numbers: true,
+            ..Default::default()
+        };
+        let palette = Palette::default();
+        let marked = Style::default().bg(palette.yellow[Palette::DARK_0]);
+        let shown = preview.text(Style::default(), &palette);
+        let lines: Vec<_> = shown.lines.iter().map(|line| line.to_string()).collect();
+        // Filtered lines keep their numbers
+        assert_eq!(lines, ["1 GET /", "3 GET /health"]);
+        assert_eq!(shown.lines[1].style, marked);
+        assert_eq!(preview.shown_before(2), 1);
+
+        preview.filter = None;
+        assert_eq!(preview.text(Style::default(), &palette).lines.len(), 3);
+    }
+
+    #[test]
+    fn test_search() {
+        let lines: Vec<String> = ["error: a", "ok", "error: b, error: c"]
+            .map(String::from)
+            .to_vec();
+        let regex = Regex::new("error").unwrap();
+        let find = |from, strict, forward| find_match(&lines, &None, &regex, from, strict, forward);
+        assert_eq!(find((0, 0), false, true), Some((0, 0)));
+        assert_eq!(find((0, 0), true, true), Some((2, 0)));
+        assert_eq!(find((2, 0), true, true), Some((2, 10)));
+        assert_eq!(find((2, 10), true, true), None);
+        assert_eq!(find((2, 10), true, false), Some((2, 0)));
+        assert_eq!(find((2, 0), true, false), Some((0, 0)));
+        // Filtered out lines are skipped
+        let filter = Regex::new("b").ok();
+        assert_eq!(
+            find_match(&lines, &filter, &regex, (0, 0), false, true),
+            Some((2, 0))
+        );
+
+        let styles = (
+            Style::new().add_modifier(Modifier::ITALIC),
+            Style::new().add_modifier(Modifier::BOLD),
+        );
+        let line = Line::from(vec![Span::raw("error: "), Span::raw("b, error: c")]);
+        let line = style_matches(line, &lines[2], &regex, Some(10), styles);
+        let spans: Vec<_> = line
+            .spans
+            .iter()
+            .map(|span| (span.content.as_ref(), span.style))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("error", styles.0),
+                (": ", Style::new()),
+                ("b, ", Style::new()),
+                ("error", styles.1),
+                (": c", Style::new())
+            ]
+        );
+    }
+}
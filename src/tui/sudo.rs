@@ -0,0 +1,122 @@
+//! Password prompt for switching a pane to sudo mode.
+use std::sync::Arc;
+
+use async_lock::Mutex as AsyncMutex;
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, BorderType, Borders, Clear, Paragraph, Widget};
+use zeroize::Zeroizing;
+
+use super::pane::Pane;
+use super::{AppEvent, Global};
+use crate::remote_fs::{RemoteFs, SudoFs};
+use crate::ssh::Session;
+
+#[derive(Default)]
+pub struct SudoPrompt {
+    pub active: bool,
+    /// Pane of the shown tab to elevate.
+    pub pane: usize,
+    label: String,
+    session: Option<Arc<AsyncMutex<Session>>>,
+    fs: Option<Arc<dyn RemoteFs>>,
+    password: Zeroizing<String>,
+}
+
+impl SudoPrompt {
+    pub fn open(&mut self, idx: usize, pane: &Pane) {
+        *self = Self {
+            active: true,
+            pane: idx,
+            label: pane.label.clone(),
+            session: pane.session.clone(),
+            fs: Some(Arc::clone(&pane.fs)),
+            // Room up front, growing the string would leave copies behind
+            password: Zeroizing::new(String::with_capacity(128)),
+        };
+    }
+
+    pub fn close(&mut self) {
+        // Wipes what was typed, the password itself lives on in the SudoFs
+        // until the pane leaves sudo mode
+        *self = Self::default();
+    }
+}
+
+pub fn render(area: Rect, buf: &mut Buffer, state: &mut SudoPrompt, ctx: &mut Global) {
+    // Two lines inside the borders
+    let top = area.height.saturating_sub(4) / 2;
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(30),
+        Constraint::Percentage(30),
+        Constraint::Length(top),
+        Constraint::Length(area.height.saturating_sub(top + 4)),
+    );
+    let lines = vec![
+        Line::from(format!("sudo password for {}", state.label)),
+        Line::from(vec![
+            Span::from("Password  "),
+            Span::styled(
+                format!("{:<16}", "*".repeat(state.password.chars().count())),
+                ctx.theme.select(),
+            ),
+        ]),
+    ];
+
+    Clear.render(area, buf);
+    Paragraph::new(lines)
+        .style(ctx.theme.popup_base())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title("Sudo")
+                .title_bottom("<Enter> Elevate  <Esc> Cancel"),
+        )
+        .render(area, buf);
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut SudoPrompt,
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    let r = match event {
+        ct_event!(keycode press Esc) => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Enter) => {
+            if let (Some(session), Some(fs)) = (state.session.clone(), state.fs.clone()) {
+                let (pane, password) = (state.pane, std::mem::take(&mut state.password));
+                ctx.spawn_tab_async(move |_| async move {
+                    let sudo = SudoFs::elevate(session, fs, Some(password)).await?;
+                    Ok(Control::Event(AppEvent::Elevated(pane, Arc::new(sudo))))
+                });
+            }
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Backspace) => {
+            state.password.pop();
+            Control::Changed
+        }
+        ct_event!(key press c) | ct_event!(key press SHIFT-c) => {
+            state.password.push(*c);
+            Control::Changed
+        }
+        // The dialog is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}
@@ -0,0 +1,157 @@
+//! Popup listing the transfers of every tab with the files they have queued
+//! next, where the bandwidth limits are changed while they run.
+use color_eyre::Report as Error;
+use color_eyre::eyre::Result;
+use rat_salsa::Control;
+use rat_widget::event::ct_event;
+use rat_widget::layout::layout_middle;
+use ratatui::buffer::Buffer;
+use ratatui::crossterm;
+use ratatui::crossterm::event::Event;
+use ratatui::layout::{Constraint, Rect};
+use ratatui::text::Line;
+use ratatui::widgets::{
+    Block, BorderType, Borders, Clear, List, ListItem, ListState, StatefulWidget, Widget,
+};
+use tracing::info;
+
+use super::main_ui::MainUI;
+use super::{AppEvent, Global};
+use crate::files::human_readable_size;
+use crate::transfer::rate_limit::{Throttle, format_rate};
+
+#[derive(Debug, Default)]
+pub struct TransferPanel {
+    pub active: bool,
+    pub list_state: ListState,
+}
+
+impl TransferPanel {
+    pub fn open(&mut self) {
+        self.list_state.select(Some(0));
+        self.active = true;
+    }
+
+    pub fn close(&mut self) {
+        self.active = false;
+    }
+}
+
+/// The tabs running a transfer, with its throttle.
+fn jobs(tabs: &[MainUI]) -> Vec<(&MainUI, &Throttle)> {
+    tabs.iter()
+        .filter(|tab| tab.is_downloading)
+        .filter_map(|tab| tab.job_throttle.as_ref().map(|throttle| (tab, throttle)))
+        .collect()
+}
+
+pub fn render(
+    area: Rect,
+    buf: &mut Buffer,
+    state: &mut TransferPanel,
+    tabs: &[MainUI],
+    ctx: &mut Global,
+) {
+    let area = layout_middle(
+        area,
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+        Constraint::Percentage(20),
+    );
+    let jobs = jobs(tabs);
+    let items: Vec<ListItem> = if jobs.is_empty() {
+        vec![ListItem::new("No transfers running")]
+    } else {
+        jobs.iter()
+            .map(|(tab, throttle)| {
+                let mut lines = vec![Line::from(format!(
+                    "{}  {}/{} files  {:.0}%  job limit {}",
+                    tab.cli.host,
+                    tab.downloaded_files,
+                    tab.total_files_to_download,
+                    tab.download_progress * 100.0,
+                    format_rate(throttle.job.rate()),
+                ))];
+                // What the job transfers next
+                lines.extend(tab.next_five_files.iter().map(|entry| {
+                    Line::from(format!(
+                        "    {}  {}",
+                        entry.name(),
+                        human_readable_size(entry.attributes.size.unwrap_or_default())
+                    ))
+                }));
+                ListItem::new(lines)
+            })
+            .collect()
+    };
+    Clear.render(area, buf);
+    let list = List::new(items)
+        .style(ctx.theme.popup_base())
+        .highlight_style(ctx.theme.select())
+        .highlight_symbol("> ")
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .border_style(ctx.theme.popup_border())
+                .title(format!(
+                    "Transfers [limit {}]",
+                    format_rate(ctx.cfg.limiter.rate())
+                ))
+                .title_bottom("<+/-> Limit  <*//> Job Limit  <Esc> Close"),
+        );
+    StatefulWidget::render(list, area, buf, &mut state.list_state);
+}
+
+pub fn event(
+    event: &Event,
+    state: &mut TransferPanel,
+    tabs: &[MainUI],
+    ctx: &mut Global,
+) -> Result<Control<AppEvent>, Error> {
+    let jobs = jobs(tabs);
+    let r = match event {
+        ct_event!(keycode press Esc) | ct_event!(key press 'T') => {
+            state.close();
+            Control::Changed
+        }
+        ct_event!(keycode press Down) | ct_event!(key press 'j') => {
+            state.list_state.select_next();
+            Control::Changed
+        }
+        ct_event!(keycode press Up) | ct_event!(key press 'k') => {
+            state.list_state.select_previous();
+            Control::Changed
+        }
+        // The global limiter is shared with every running job
+        ct_event!(key press '+') | ct_event!(key press '-') => {
+            ctx.cfg
+                .limiter
+                .step(matches!(event, ct_event!(key press '+')));
+            info!(
+                "Global transfer limit: {}",
+                format_rate(ctx.cfg.limiter.rate())
+            );
+            Control::Changed
+        }
+        ct_event!(key press '*') | ct_event!(key press '/') => {
+            let selected = state.list_state.selected().unwrap_or_default();
+            match jobs.get(selected.min(jobs.len().saturating_sub(1))) {
+                Some((tab, throttle)) => {
+                    throttle.job.step(matches!(event, ct_event!(key press '*')));
+                    info!(
+                        host = tab.cli.host,
+                        "Job transfer limit: {}",
+                        format_rate(throttle.job.rate())
+                    );
+                    Control::Changed
+                }
+                None => Control::Unchanged,
+            }
+        }
+        // The popup is modal
+        _ => Control::Unchanged,
+    };
+    Ok(r)
+}